@@ -0,0 +1,416 @@
+//! # the checks of `yobatis check`
+use std::collections::HashMap;
+use std::fmt;
+
+use super::schema::Schema;
+use crate::gen::mapper::{self, Mapper, ParseMapperError};
+use crate::gen::sql;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// a problem found in a mapper. `code` names the check that found it.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub file: String,
+    pub line: u64,
+    pub column: u64,
+    pub element: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn from_parse_error(severity: Severity, code: &'static str, e: ParseMapperError) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            file: e.file,
+            line: e.line,
+            column: e.column,
+            element: e.element,
+            message: e.message,
+        }
+    }
+
+    /// a diagnostic on a top level element of mapper
+    fn at(
+        mapper: &Mapper,
+        kind: &str,
+        element: &str,
+        id: &str,
+        severity: Severity,
+        code: &'static str,
+        message: String,
+    ) -> Diagnostic {
+        let (line, column) = mapper
+            .locations
+            .get(&(kind.to_string(), id.to_string()))
+            .cloned()
+            .unwrap_or((0, 0));
+        Diagnostic {
+            severity,
+            code,
+            file: mapper.file.clone(),
+            line,
+            column,
+            element: element.to_string(),
+            message,
+        }
+    }
+
+    /// `file:line:column: severity: <element>: message [code]`, the format
+    /// of compiler diagnostics
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}:{}:{}: {}: ",
+            self.file, self.line, self.column, self.severity
+        );
+        if !self.element.is_empty() {
+            text.push_str(&format!("<{}>: ", self.element));
+        }
+        text.push_str(&format!("{} [{}]", self.message, self.code));
+        text
+    }
+
+    /// a JSON object on one line
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"severity\":\"{}\",\"code\":\"{}\",\"element\":{},\"message\":{}}}",
+            json_string(&self.file),
+            self.line,
+            self.column,
+            self.severity,
+            self.code,
+            json_string(&self.element),
+            json_string(&self.message)
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// statement ids are C function names, so they must be unique across
+/// namespaces
+fn check_duplicate_ids(mappers: &[&Mapper], diagnostics: &mut Vec<Diagnostic>) {
+    let mut seen: HashMap<&str, &Mapper> = HashMap::new();
+    for mapper in mappers {
+        for statement in mapper.statements() {
+            match seen.get(statement.id) {
+                Some(first) => diagnostics.push(Diagnostic::at(
+                    mapper,
+                    "statement",
+                    statement.kind,
+                    statement.id,
+                    Severity::Error,
+                    "duplicate-id",
+                    format!(
+                        "statement id {} is also defined in namespace {} ({})",
+                        statement.id, first.namespace, first.file
+                    ),
+                )),
+                None => {
+                    seen.insert(statement.id, mapper);
+                }
+            }
+        }
+    }
+}
+
+/// the #{}, ${} and test properties must be parameters of the statement
+fn check_properties(mapper: &Mapper, diagnostics: &mut Vec<Diagnostic>) {
+    for statement in mapper.statements() {
+        let error = |code, message| {
+            Diagnostic::at(
+                mapper,
+                "statement",
+                statement.kind,
+                statement.id,
+                Severity::Error,
+                code,
+                message,
+            )
+        };
+
+        let (known, source): (Vec<&str>, String) = if !statement.params.is_empty() {
+            (
+                statement.params.iter().map(|p| p.name.as_str()).collect(),
+                format!("the parameters of {}", statement.id),
+            )
+        } else if statement.parameter_type.is_empty() {
+            (
+                Vec::new(),
+                format!("{}, it has no parameterType", statement.id),
+            )
+        } else {
+            match mapper.type_maps.get(statement.parameter_type) {
+                Some(result_map) => (
+                    result_map
+                        .results
+                        .iter()
+                        .map(|r| r.property.as_str())
+                        .collect(),
+                    format!("resultMap {}", result_map.id),
+                ),
+                None => {
+                    diagnostics.push(error(
+                        "unknown-parameter-type",
+                        format!(
+                            "parameterType {} is not the type of any resultMap",
+                            statement.parameter_type
+                        ),
+                    ));
+                    continue;
+                }
+            }
+        };
+
+        let mut names = Vec::new();
+        mapper::collect_properties(statement.content, &mut names);
+        // the <selectKey> query takes the parameters of its <insert>
+        if statement.kind == "insert" {
+            if let Some(key) = mapper
+                .inserts
+                .get(statement.id)
+                .and_then(|insert| insert.select_key.as_ref())
+            {
+                mapper::collect_properties(&key.content, &mut names);
+            }
+        }
+        for name in names {
+            if !known.contains(&name.as_str()) {
+                diagnostics.push(error(
+                    "unknown-property",
+                    format!("property {} is not in {}", name, source),
+                ));
+            }
+        }
+    }
+}
+
+/// resultMaps referenced by selects, associations and collections
+fn check_result_map_refs(mapper: &Mapper, diagnostics: &mut Vec<Diagnostic>) {
+    for statement in mapper.statements() {
+        if let Some(result_map) = statement.result_map {
+            if !mapper.result_maps.contains_key(result_map) {
+                diagnostics.push(Diagnostic::at(
+                    mapper,
+                    "statement",
+                    statement.kind,
+                    statement.id,
+                    Severity::Error,
+                    "unknown-result-map",
+                    format!("resultMap {} not found", result_map),
+                ));
+            }
+        }
+    }
+
+    for result_map in mapper.result_maps.values() {
+        for nested in result_map
+            .associations
+            .iter()
+            .chain(result_map.collections.iter())
+        {
+            if !mapper.result_maps.contains_key(&nested.result_map) {
+                diagnostics.push(Diagnostic::at(
+                    mapper,
+                    "resultMap",
+                    "resultMap",
+                    &result_map.id,
+                    Severity::Error,
+                    "unknown-result-map",
+                    format!(
+                        "resultMap {} of property {} not found",
+                        nested.result_map, nested.property
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// the result columns must be columns of a table in db.xml
+fn check_columns(mapper: &Mapper, schema: &Schema, diagnostics: &mut Vec<Diagnostic>) {
+    for result_map in mapper.result_maps.values() {
+        for result in &result_map.results {
+            if !schema.has_column(&result.column) {
+                diagnostics.push(Diagnostic::at(
+                    mapper,
+                    "resultMap",
+                    "resultMap",
+                    &result_map.id,
+                    Severity::Warning,
+                    "unknown-column",
+                    format!(
+                        "column {} of property {} is not in any table of db.xml",
+                        result.column, result.property
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// check the mappers loaded by `mapper::load_mappers`, errors are those it
+/// found. schema is the one of db.xml if there is one. The diagnostics are
+/// sorted by file and position.
+pub fn check(
+    mappers: &[Mapper],
+    errors: Vec<ParseMapperError>,
+    schema: Option<&Schema>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = errors
+        .into_iter()
+        .map(|e| Diagnostic::from_parse_error(Severity::Error, e.code.unwrap_or("parse"), e))
+        .collect();
+
+    let (sql_errors, sql_warnings) = sql::check_sql(mappers);
+    for e in sql_errors {
+        diagnostics.push(Diagnostic::from_parse_error(
+            Severity::Error,
+            "sql-syntax",
+            e,
+        ));
+    }
+    for w in sql_warnings {
+        diagnostics.push(Diagnostic::from_parse_error(
+            Severity::Warning,
+            "sql-syntax",
+            w,
+        ));
+    }
+
+    if let Some(schema) = schema {
+        for e in sql::check_schema(mappers, schema) {
+            diagnostics.push(Diagnostic::from_parse_error(Severity::Error, "schema", e));
+        }
+    }
+
+    let mut mappers: Vec<&Mapper> = mappers.iter().collect();
+    mappers.sort_by(|a, b| a.file.cmp(&b.file));
+    check_duplicate_ids(&mappers, &mut diagnostics);
+    for mapper in &mappers {
+        for warning in &mapper.warnings {
+            diagnostics.push(Diagnostic::from_parse_error(
+                Severity::Warning,
+                warning.code.unwrap_or("unknown-element"),
+                warning.clone(),
+            ));
+        }
+        check_properties(mapper, &mut diagnostics);
+        check_result_map_refs(mapper, &mut diagnostics);
+        if let Some(schema) = schema {
+            check_columns(mapper, schema, &mut diagnostics);
+        }
+    }
+
+    diagnostics.sort_by(|a, b| {
+        (&a.file, a.line, a.column, a.severity).cmp(&(&b.file, b.line, b.column, b.severity))
+    });
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::mapper::test_util::{load, mapper_xml};
+
+    fn diagnostics(body: &str) -> Vec<Diagnostic> {
+        let (mappers, errors) = load(&[("t-mapper.xml", &mapper_xml(body))]);
+        check(&mappers, errors, None)
+    }
+
+    #[test]
+    fn unknown_include_at_the_include() {
+        let diagnostics = diagnostics(
+            r#"<delete id="d">
+                DELETE FROM t
+                <include refid="missing"/>
+            </delete>"#,
+        );
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        let d = &diagnostics[0];
+        assert_eq!(d.code, "unknown-include");
+        assert_eq!(d.element, "include");
+        assert!(d.file.ends_with("t-mapper.xml"), "{}", d.file);
+        assert_eq!((d.line, d.column), (5, 17));
+    }
+
+    #[test]
+    fn select_key_properties() {
+        let diagnostics = diagnostics(
+            r#"<insert id="i" parameterType="yb_t_t">
+                <selectKey keyProperty="id" order="BEFORE">SELECT id FROM s WHERE name = #{nam}</selectKey>
+                INSERT INTO t (id, name) VALUES (#{id}, #{name})
+            </insert>
+            <resultMap id="m" type="yb_t_t">
+                <result column="id" property="id" yo_type="int64_t"/>
+                <result column="name" property="name" yo_type="yb_string_t"/>
+            </resultMap>"#,
+        );
+        let codes: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|d| (d.code, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![("unknown-property", "property nam is not in resultMap m")]
+        );
+    }
+
+    #[test]
+    fn subst_without_whitelist_once() {
+        let diagnostics = diagnostics(
+            r#"<select id="s" parameterType="yb_t_t" resultMap="m">
+                SELECT id FROM ${tbl} ORDER BY ${tbl}, ${id}, ${name, whitelist=id|name}
+            </select>
+            <resultMap id="m" type="yb_t_t">
+                <result column="id" property="id" yo_type="int64_t"/>
+                <result column="name" property="name" yo_type="yb_string_t"/>
+                <result column="tbl" property="tbl" yo_type="yb_string_t"/>
+            </resultMap>"#,
+        );
+        let warnings: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == "subst-without-whitelist")
+            .collect();
+        assert_eq!(warnings.len(), 1, "{:?}", diagnostics);
+        let d = warnings[0];
+        assert_eq!(d.severity, Severity::Warning);
+        assert_eq!(d.element, "select");
+        assert_eq!((d.line, d.column), (3, 1));
+        assert_eq!(
+            d.message,
+            "s: ${tbl} substitutes a string without a whitelist, the value is only escaped"
+        );
+    }
+}
@@ -0,0 +1,3 @@
+/// # module to validate mapper files without generating code
+pub mod lint;
+pub mod schema;
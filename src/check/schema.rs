@@ -0,0 +1,178 @@
+//! # the database schema written to db.xml by `init`
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use sqlparser::ast::Statement;
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::parser::Parser;
+
+/// a column of a table
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    /// the yo_type the column maps to
+    pub yo_type: String,
+}
+
+/// a table, parsed from its CREATE TABLE statement
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+}
+
+impl Table {
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// the tables of db.xml
+#[derive(Debug, Clone)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+}
+
+impl Schema {
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables
+            .iter()
+            .find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    /// whether any of the tables has the column
+    pub fn has_column(&self, name: &str) -> bool {
+        self.tables.iter().any(|t| t.column(name).is_some())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaError {
+    message: String,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SchemaError>;
+
+/// the yo_type of a column type, the same mapping `init` uses
+fn column_yo_type(column_type: &str) -> &'static str {
+    let column_type = column_type.to_lowercase();
+    if column_type.contains("int") || column_type.contains("bit") {
+        "int64_t"
+    } else if ["float", "dec", "numeric", "double"]
+        .iter()
+        .any(|t| column_type.contains(t))
+    {
+        "double"
+    } else {
+        "yb_string_t"
+    }
+}
+
+/// parse the columns of a CREATE TABLE statement
+pub fn parse_create_table(ddl: &str) -> Result<Table> {
+    let err = |msg: String| SchemaError {
+        message: format!("{}: {}", msg, ddl.trim().lines().next().unwrap_or("")),
+    };
+    let statements = Parser::parse_sql(&MySqlDialect {}, ddl).map_err(|e| err(e.to_string()))?;
+    let create = statements
+        .iter()
+        .find_map(|statement| match statement {
+            Statement::CreateTable(create) => Some(create),
+            _ => None,
+        })
+        .ok_or_else(|| err("not a CREATE TABLE statement".to_string()))?;
+
+    // `db`.`table`
+    let name = create
+        .name
+        .0
+        .last()
+        .map(|ident| ident.value.clone())
+        .unwrap_or_default();
+    let columns = create
+        .columns
+        .iter()
+        .map(|column| Column {
+            name: column.name.value.clone(),
+            yo_type: column_yo_type(&column.data_type.to_string()).to_string(),
+        })
+        .collect();
+
+    Ok(Table { name, columns })
+}
+
+/// load the tables of a db.xml
+pub fn load_schema(path: &Path) -> Result<Schema> {
+    let err = |msg: String| SchemaError {
+        message: format!("{}: {}", path.display(), msg),
+    };
+    let contents = fs::read_to_string(path).map_err(|e| err(e.to_string()))?;
+    let root: minidom::Element = contents
+        .parse()
+        .map_err(|e: minidom::Error| err(e.to_string()))?;
+
+    let mut tables = Vec::new();
+    for table in root.children().filter(|c| c.name() == "table") {
+        for create in table.children().filter(|c| c.name() == "create") {
+            let mut parsed = parse_create_table(&create.text()).map_err(|e| err(e.message))?;
+            if let Some(name) = table.attr("name") {
+                parsed.name = name.to_string();
+            }
+            tables.push(parsed);
+        }
+    }
+    Ok(Schema { tables })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_create_table_output() {
+        let table = parse_create_table(
+            "CREATE TABLE IF NOT EXISTS `shop`.`order item` (
+              `id` bigint NOT NULL AUTO_INCREMENT COMMENT 'the id, (auto)',
+              `name` varchar(64) NOT NULL DEFAULT '' COMMENT 'a, b',
+              `price` decimal(10,2) DEFAULT (round(1.5, 1)),
+              `flags` bit(8) DEFAULT NULL,
+              `created` datetime DEFAULT CURRENT_TIMESTAMP,
+              PRIMARY KEY (`id`),
+              UNIQUE KEY `uk_name` (`name`),
+              CONSTRAINT `ck_price` CHECK ((`price` >= 0))
+            ) ENGINE=InnoDB AUTO_INCREMENT=5 DEFAULT CHARSET=utf8mb4 COMMENT='items'",
+        )
+        .unwrap();
+        assert_eq!(table.name, "order item");
+        let columns: Vec<(&str, &str)> = table
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.yo_type.as_str()))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("id", "int64_t"),
+                ("name", "yb_string_t"),
+                ("price", "double"),
+                ("flags", "int64_t"),
+                ("created", "yb_string_t"),
+            ]
+        );
+    }
+
+    #[test]
+    fn not_a_create_table() {
+        assert!(parse_create_table("CREATE DATABASE IF NOT EXISTS shop").is_err());
+        assert!(parse_create_table("CREATE TABLE t (").is_err());
+    }
+}
@@ -2,13 +2,13 @@
 use std::fs;
 use std::path::Path;
 
-use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 
 use super::mapper;
-use log::{debug, error, info, trace, warn};
+use super::sql;
+use log::{debug, warn};
 
 /// error type
 #[derive(Debug, Clone)]
@@ -33,7 +33,7 @@ fn gen_common(path: &Path) -> Result<()> {
     common_c_file.write_all(yb_common_c).unwrap();
     common_h_file.write_all(yb_common_h).unwrap();
 
-    return Ok(());
+    Ok(())
 }
 
 /// write header guard
@@ -41,59 +41,273 @@ fn write_guard_start(mapper_h_file: &mut File, mapper: &mapper::Mapper) -> Resul
     // #ifndef YB_XXXXX_MAPPER_H__
     // #define YB_XXXXX_MAPPER_H__
     let pp_guard = format!("YB_{}_H__", mapper.namespace.to_uppercase());
-    mapper_h_file.write("#ifndef ".as_bytes()).unwrap();
-    mapper_h_file.write(pp_guard.as_bytes()).unwrap();
-    mapper_h_file.write("\n".as_bytes()).unwrap();
-    mapper_h_file.write("#define ".as_bytes()).unwrap();
-    mapper_h_file.write(pp_guard.as_bytes()).unwrap();
-    mapper_h_file.write("\n\n".as_bytes()).unwrap();
-
-    return Ok(());
+    mapper_h_file.write_all("#ifndef ".as_bytes()).unwrap();
+    mapper_h_file.write_all(pp_guard.as_bytes()).unwrap();
+    mapper_h_file.write_all("\n".as_bytes()).unwrap();
+    mapper_h_file.write_all("#define ".as_bytes()).unwrap();
+    mapper_h_file.write_all(pp_guard.as_bytes()).unwrap();
+    mapper_h_file.write_all("\n\n".as_bytes()).unwrap();
+
+    // the declarations use the types of yb_common.h
+    let up = "../".repeat(mapper.dir.components().count());
+    let line = format!("#include \"{}yb_common.h\"\n\n", up);
+    mapper_h_file.write_all(line.as_bytes()).unwrap();
+
+    Ok(())
 }
 
 fn write_guard_end(mapper_h_file: &mut File, mapper: &mapper::Mapper) -> Result<()> {
     // #endif // YB_XXXXX_MAPPER_H__
     let pp_guard = format!("YB_{}_H__", mapper.namespace.to_uppercase());
-    mapper_h_file.write("#endif // ".as_bytes()).unwrap();
-    mapper_h_file.write(pp_guard.as_bytes()).unwrap();
-    mapper_h_file.write("\n\n".as_bytes()).unwrap();
+    mapper_h_file.write_all("#endif // ".as_bytes()).unwrap();
+    mapper_h_file.write_all(pp_guard.as_bytes()).unwrap();
+    mapper_h_file.write_all("\n\n".as_bytes()).unwrap();
 
-    return Ok(());
+    Ok(())
 }
 
-fn write_includes(mapper_c_file: &mut File, filename_h: &str) -> Result<()> {
-    mapper_c_file.write("#include \"".as_bytes()).unwrap();
-    mapper_c_file.write(filename_h.as_bytes()).unwrap();
-    mapper_c_file.write("\"\n\n".as_bytes()).unwrap();
+/// yb_common.h is in the output dir, `dir` is the one of the mapper below it
+fn write_includes(mapper_c_file: &mut File, filename_h: &str, dir: &Path) -> Result<()> {
+    mapper_c_file.write_all("#include \"".as_bytes()).unwrap();
+    mapper_c_file.write_all(filename_h.as_bytes()).unwrap();
+    mapper_c_file.write_all("\"\n\n".as_bytes()).unwrap();
 
     mapper_c_file
-        .write("#include \"yb_common.h\"\n\n".as_bytes())
+        .write_all("#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n\n".as_bytes())
         .unwrap();
 
-    return Ok(());
+    let up = "../".repeat(dir.components().count());
+    let line = format!("#include \"{}yb_common.h\"\n\n", up);
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    Ok(())
+}
+
+/// the resultMap an <association> or <collection> is filled by
+fn nested_result_map<'a>(
+    mapper: &'a mapper::Mapper,
+    result_map: &mapper::YoResultMap,
+    nested: &mapper::YoNestedMap,
+) -> Result<&'a mapper::YoResultMap> {
+    mapper
+        .result_maps
+        .get(&nested.result_map)
+        .ok_or_else(|| GenCError {
+            message: format!(
+                "resultMap {} not found for property {} of resultMap {}",
+                nested.result_map, nested.property, result_map.id
+            ),
+        })
+}
+
+fn write_result_map_typedef(
+    mapper_h_file: &mut File,
+    result_map: &mapper::YoResultMap,
+) -> Result<()> {
+    let tydef = format!(
+        "typedef struct {}_s* {};\n",
+        result_map.type_, result_map.type_
+    );
+    mapper_h_file.write_all(tydef.as_bytes()).unwrap();
+    Ok(())
 }
 
 fn write_result_map_define(
     mapper_h_file: &mut File,
-    mapper_c_file: &mut File,
+    _mapper_c_file: &mut File,
     result_map: &mapper::YoResultMap,
+    mapper: &mapper::Mapper,
 ) -> Result<()> {
     // struct
     let def_line = format!("struct {}_s {{\n", result_map.type_);
-    mapper_h_file.write(def_line.as_bytes()).unwrap();
+    mapper_h_file.write_all(def_line.as_bytes()).unwrap();
     for result in &result_map.results {
         let member_line = format!("    {} {};\n", result.yo_type, result.property);
-        mapper_h_file.write(member_line.as_bytes()).unwrap();
+        mapper_h_file.write_all(member_line.as_bytes()).unwrap();
     }
-    mapper_h_file.write("};\n".as_bytes()).unwrap();
-    // typedef
-    let tydef = format!(
-        "typedef struct {}_s* {};\n",
-        result_map.type_, result_map.type_
+    // an association is NULL when the joined row has no match
+    for association in &result_map.associations {
+        let nested = nested_result_map(mapper, result_map, association)?;
+        let member_line = format!("    {} {};\n", nested.type_, association.property);
+        mapper_h_file.write_all(member_line.as_bytes()).unwrap();
+    }
+    for collection in &result_map.collections {
+        let nested = nested_result_map(mapper, result_map, collection)?;
+        let member_line = format!(
+            "    {}* {};\n    int64_t {}_len;\n",
+            nested.type_, collection.property, collection.property
+        );
+        mapper_h_file.write_all(member_line.as_bytes()).unwrap();
+    }
+    // a bit per result, set when it is NULL, and one set when it is assigned
+    if !result_map.results.is_empty() {
+        let member_line = format!(
+            "    unsigned char yb_null[{n}];\n    unsigned char yb_dirty[{n}];\n",
+            n = result_map.results.len().div_ceil(8)
+        );
+        mapper_h_file.write_all(member_line.as_bytes()).unwrap();
+    }
+    mapper_h_file.write_all("};\n".as_bytes()).unwrap();
+
+    Ok(())
+}
+
+/// the null and dirty bitmaps of the results and the setters:
+///
+/// ```c
+/// enum <type>_field { <type>_field_<property>, ... };
+/// int <type>_is_null(<type> n, enum <type>_field field);
+/// void <type>_set_null(<type> n, enum <type>_field field, int is_null);
+/// int <type>_is_dirty(<type> n, enum <type>_field field);
+/// void <type>_clear_dirty(<type> n);
+/// void <type>_set_<property>(<type> n, <yo_type> v);
+/// ```
+fn write_result_map_fields(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    result_map: &mapper::YoResultMap,
+) -> Result<()> {
+    if result_map.results.is_empty() {
+        return Ok(());
+    }
+    let ty = &result_map.type_;
+    let fields = result_map
+        .results
+        .iter()
+        .map(|r| format!("    {}_field_{},\n", ty, r.property))
+        .collect::<String>();
+    let line = format!(
+        "enum {ty}_field {{
+{fields}}};
+/// @brief whether the field of n is NULL, a new {ty} is all NULL.
+int {ty}_is_null({ty} n, enum {ty}_field field);
+/// @brief mark the field of n NULL or not, after its value is set. The field
+/// becomes dirty.
+void {ty}_set_null({ty} n, enum {ty}_field field, int is_null);
+/// @brief whether the field of n was assigned since n was created, fetched or
+/// cleaned. The selective statements write the dirty fields only.
+int {ty}_is_dirty({ty} n, enum {ty}_field field);
+/// @brief mark all the fields of n clean.
+void {ty}_clear_dirty({ty} n);
+",
+        ty = ty,
+        fields = fields
     );
-    mapper_h_file.write(tydef.as_bytes()).unwrap();
+    mapper_h_file.write_all(line.as_bytes()).unwrap();
 
-    return Ok(());
+    let line = format!(
+        "int {ty}_is_null({ty} n, enum {ty}_field field) {{
+{sp}return (n->yb_null[field / 8] >> (field % 8)) & 1;
+}}
+
+void {ty}_set_null({ty} n, enum {ty}_field field, int is_null) {{
+{sp}if (is_null) {{
+{sp}    n->yb_null[field / 8] |= 1 << (field % 8);
+{sp}}} else {{
+{sp}    n->yb_null[field / 8] &= ~(1 << (field % 8));
+{sp}}}
+{sp}n->yb_dirty[field / 8] |= 1 << (field % 8);
+}}
+
+int {ty}_is_dirty({ty} n, enum {ty}_field field) {{
+{sp}return (n->yb_dirty[field / 8] >> (field % 8)) & 1;
+}}
+
+void {ty}_clear_dirty({ty} n) {{
+{sp}memset(n->yb_dirty, 0, sizeof(n->yb_dirty));
+}}
+
+",
+        ty = ty,
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    for result in &result_map.results {
+        let (doc, body) = if result.yo_type == "yb_string_t" {
+            (
+                "n owns v afterwards, YB_STRING_NULL sets it NULL.",
+                format!(
+                    "{sp}if (n->{prop} != YB_STRING_NULL) {{
+{sp}    yb_string_free(n->{prop});
+{sp}}}
+{sp}n->{prop} = v;
+{sp}{ty}_set_null(n, {ty}_field_{prop}, v == YB_STRING_NULL);
+",
+                    sp = spaces(4),
+                    ty = ty,
+                    prop = result.property
+                ),
+            )
+        } else {
+            (
+                "it is not NULL afterwards.",
+                format!(
+                    "{sp}n->{prop} = v;
+{sp}{ty}_set_null(n, {ty}_field_{prop}, 0);
+",
+                    sp = spaces(4),
+                    ty = ty,
+                    prop = result.property
+                ),
+            )
+        };
+        let signature = format!(
+            "void {ty}_set_{prop}({ty} n, {yo_type} v)",
+            ty = ty,
+            prop = result.property,
+            yo_type = result.yo_type
+        );
+        let line = format!(
+            "/// @brief assign v to {prop} of n and mark it dirty, {doc}\n{signature};\n",
+            prop = result.property,
+            doc = doc,
+            signature = signature
+        );
+        mapper_h_file.write_all(line.as_bytes()).unwrap();
+        let line = format!("{} {{\n{}}}\n\n", signature, body);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    Ok(())
+}
+
+/// the value of a NULL field
+fn zero_value(yo_type: &str) -> Result<&'static str> {
+    match yo_type {
+        "int64_t" => Ok("0"),
+        "double" => Ok("0.0"),
+        "yb_string_t" => Ok("NULL"),
+        _ => Err(GenCError {
+            message: format!("unsupported type: {}", yo_type),
+        }),
+    }
+}
+
+/// the C declaration, value and NULL condition of a scalar parameter.
+/// Numbers are passed by value, or by pointer when they are nullable so NULL
+/// is a NULL pointer. Strings are passed as they are.
+fn scalar_param(param: &mapper::YoParam) -> Result<(String, String, String)> {
+    let name = &param.name;
+    match param.yo_type.as_str() {
+        "int64_t" | "double" if param.nullable => Ok((
+            format!("const {}* {}", param.yo_type, name),
+            format!("(*{})", name),
+            format!("({} == NULL)", name),
+        )),
+        "int64_t" | "double" => Ok((
+            format!("{} {}", param.yo_type, name),
+            name.to_string(),
+            "0".to_string(),
+        )),
+        "yb_string_t" => Ok((
+            format!("yb_string_t {}", name),
+            name.to_string(),
+            format!("({} == NULL)", name),
+        )),
+        yo_type => Err(GenCError {
+            message: format!("unsupported type: {}", yo_type),
+        }),
+    }
 }
 
 fn write_result_map_new(
@@ -103,507 +317,1617 @@ fn write_result_map_new(
 ) -> Result<()> {
     // declare new
     let new_fn = format!("{} {}_new();\n", result_map.type_, result_map.type_);
-    mapper_h_file.write(new_fn.as_bytes()).unwrap();
+    mapper_h_file.write_all(new_fn.as_bytes()).unwrap();
 
     // impl init
     let new_fn_line = format!("{} {}_new() {{\n", result_map.type_, result_map.type_);
-    mapper_c_file.write(new_fn_line.as_bytes()).unwrap();
+    mapper_c_file.write_all(new_fn_line.as_bytes()).unwrap();
     let malloc_line = format!(
         "    {} n = ({})malloc(sizeof(struct {}_s));\n",
         result_map.type_, result_map.type_, result_map.type_
     );
-    mapper_c_file.write(malloc_line.as_bytes()).unwrap();
+    mapper_c_file.write_all(malloc_line.as_bytes()).unwrap();
+    mapper_c_file
+        .write_all("    if (n == NULL) {\n        return NULL;\n    }\n".as_bytes())
+        .unwrap();
     for result in &result_map.results {
-        let line;
-        match result.yo_type.as_str() {
-            "int64_t" => {
-                line = format!("    n->{} = YB_INT_NULL;\n", result.property);
-            }
-            "double" => {
-                line = format!("    n->{} = YB_FLOAT_NULL;\n", result.property);
-            }
-            "yb_string_t" => {
-                line = format!("    n->{} = YB_STRING_NULL;\n", result.property);
-            }
-            _ => {
-                return Err(GenCError {
-                    message: format!("unsupported type: {}", result.yo_type),
-                });
-            }
-        }
-        mapper_c_file.write(line.as_bytes()).unwrap();
+        let line = format!(
+            "    n->{} = {};\n",
+            result.property,
+            zero_value(&result.yo_type)?
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    if !result_map.results.is_empty() {
+        mapper_c_file
+            .write_all(
+                "    memset(n->yb_null, 0xff, sizeof(n->yb_null));\n    memset(n->yb_dirty, 0, sizeof(n->yb_dirty));\n"
+                    .as_bytes(),
+            )
+            .unwrap();
+    }
+    for association in &result_map.associations {
+        let line = format!("    n->{} = NULL;\n", association.property);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    for collection in &result_map.collections {
+        let line = format!(
+            "    n->{} = NULL;\n    n->{}_len = 0;\n",
+            collection.property, collection.property
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
     }
     mapper_c_file
-        .write("    return n;\n}\n\n".as_bytes())
+        .write_all("    return n;\n}\n\n".as_bytes())
         .unwrap();
-    return Ok(());
+    Ok(())
 }
 
 fn write_result_map_free(
     mapper_h_file: &mut File,
     mapper_c_file: &mut File,
     result_map: &mapper::YoResultMap,
+    mapper: &mapper::Mapper,
 ) -> Result<()> {
     // declare free
     let free_fn = format!("void {}_free({});\n", result_map.type_, result_map.type_);
-    mapper_h_file.write(free_fn.as_bytes()).unwrap();
+    mapper_h_file.write_all(free_fn.as_bytes()).unwrap();
     // impl free
     let free_fn_line = format!(
         "void {}_free({} n) {{\n",
         result_map.type_, result_map.type_
     );
-    mapper_c_file.write(free_fn_line.as_bytes()).unwrap();
+    mapper_c_file.write_all(free_fn_line.as_bytes()).unwrap();
 
     for result in &result_map.results {
         let val_name = &result.property;
-        match result.yo_type.as_str() {
-            "yb_string_t" => {
-                let free_string_stmt = format!(
-                    "    if (n->{} != YB_STRING_NULL) {{\n        yb_string_free(n->{});\n    }}\n",
-                    val_name, val_name
-                );
-                mapper_c_file.write(free_string_stmt.as_bytes()).unwrap();
-            }
-            _ => {}
+        if result.yo_type.as_str() == "yb_string_t" {
+            let free_string_stmt = format!(
+                "    if (n->{} != YB_STRING_NULL) {{\n        yb_string_free(n->{});\n    }}\n",
+                val_name, val_name
+            );
+            mapper_c_file
+                .write_all(free_string_stmt.as_bytes())
+                .unwrap();
+        }
+    }
+    for association in &result_map.associations {
+        let nested = nested_result_map(mapper, result_map, association)?;
+        let line = format!(
+            "    if (n->{} != NULL) {{\n        {}_free(n->{});\n    }}\n",
+            association.property, nested.type_, association.property
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    for collection in &result_map.collections {
+        let nested = nested_result_map(mapper, result_map, collection)?;
+        let line = format!(
+            "    for (int64_t i = 0; i < n->{}_len; ++i) {{\n        {}_free(n->{}[i]);\n    }}\n    free(n->{});\n",
+            collection.property, nested.type_, collection.property, collection.property
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    mapper_c_file
+        .write_all("    free(n);\n}\n\n".as_bytes())
+        .unwrap();
+
+    Ok(())
+}
+
+/// `<type>_<collection>_push` appends a child to a collection, the struct
+/// takes the ownership of the child
+fn write_result_map_push(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    result_map: &mapper::YoResultMap,
+    mapper: &mapper::Mapper,
+) -> Result<()> {
+    for collection in &result_map.collections {
+        let nested = nested_result_map(mapper, result_map, collection)?;
+        let fn_sig = format!(
+            "int {}_{}_push({} n, {} child)",
+            result_map.type_, collection.property, result_map.type_, nested.type_
+        );
+        let line = format!("{};\n", fn_sig);
+        mapper_h_file.write_all(line.as_bytes()).unwrap();
+
+        let line = format!("{} {{\n", fn_sig);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        let line = format!(
+            "    {}* p = ({}*)realloc(n->{}, sizeof({}) * (n->{}_len + 1));\n",
+            nested.type_, nested.type_, collection.property, nested.type_, collection.property
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        mapper_c_file
+            .write_all("    if (p == NULL) {\n        return YB_FAIL;\n    }\n".as_bytes())
+            .unwrap();
+        let line = format!(
+            "    n->{} = p;\n    n->{}[n->{}_len++] = child;\n    return YB_OK;\n}}\n\n",
+            collection.property, collection.property, collection.property
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    Ok(())
+}
+
+/// `<type>_merge(n, row)` groups the struct fetched from a joined row into
+/// the struct fetched from the previous row. When both have the same <id>
+/// results, the collection children of `row` are moved to `n` (merged into
+/// the last child of `n` if it is the same object) and `row` is freed. The
+/// rows of an object must be adjacent, e.g. ordered by its <id> columns.
+fn write_result_map_merge(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    result_map: &mapper::YoResultMap,
+    mapper: &mapper::Mapper,
+) -> Result<()> {
+    let fn_sig = format!(
+        "int {}_merge({} n, {} row)",
+        result_map.type_, result_map.type_, result_map.type_
+    );
+    let line = format!(
+        "/// @retval 1 if row is merged into n and freed.\n/// @retval 0 if row is another object, it is untouched.\n/// @retval YB_FAIL if the operation is failed.\n{};\n",
+        fn_sig
+    );
+    mapper_h_file.write_all(line.as_bytes()).unwrap();
+
+    let line = format!("{} {{\n", fn_sig);
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    let mut conds = Vec::new();
+    for result in result_map.id_results() {
+        conds.push(format!(
+            "{ty}_is_null(n, {ty}_field_{p}) != {ty}_is_null(row, {ty}_field_{p})",
+            ty = result_map.type_,
+            p = result.property
+        ));
+        if result.yo_type == "yb_string_t" {
+            conds.push(format!(
+                "yb_string_compare(n->{}, row->{}) != 0",
+                result.property, result.property
+            ));
+        } else {
+            conds.push(format!(
+                "n->{} != row->{}",
+                result.property, result.property
+            ));
         }
     }
+    if !conds.is_empty() {
+        let line = format!(
+            "    if ({}) {{\n        return 0;\n    }}\n",
+            conds.join(" ||\n        ")
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    for collection in &result_map.collections {
+        let nested = nested_result_map(mapper, result_map, collection)?;
+        let prop = &collection.property;
+        let line = format!("    for (int64_t i = 0; i < row->{}_len; ++i) {{\n", prop);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        let line = format!(
+            "{}int merged = n->{}_len > 0 ? {}_merge(n->{}[n->{}_len - 1], row->{}[i]) : 0;\n",
+            spaces(8),
+            prop,
+            nested.type_,
+            prop,
+            prop,
+            prop
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        let line = format!(
+            "{}if (merged == 0) {{\n{}merged = {}_{}_push(n, row->{}[i]);\n{}}}\n",
+            spaces(8),
+            spaces(12),
+            result_map.type_,
+            prop,
+            prop,
+            spaces(8)
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        // on failure the children not moved yet are left to row
+        let line = format!(
+            "{}if (merged == YB_FAIL) {{\n{}for (int64_t j = i; j < row->{}_len; ++j) {{\n{}row->{}[j - i] = row->{}[j];\n{}}}\n{}row->{}_len -= i;\n{}return YB_FAIL;\n{}}}\n",
+            spaces(8),
+            spaces(12),
+            prop,
+            spaces(16),
+            prop,
+            prop,
+            spaces(12),
+            spaces(12),
+            prop,
+            spaces(12),
+            spaces(8)
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        mapper_c_file.write_all("    }\n".as_bytes()).unwrap();
+        let line = format!("    row->{}_len = 0;\n", prop);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!("    {}_free(row);\n    return 1;\n}}\n\n", result_map.type_);
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
+}
+
+/// `<type>_fetch(n, r, prefix)` fills n from the current row of r, by the
+/// result columns prefixed by prefix. An association or collection child is
+/// fetched when one of its <id> columns is not NULL, with the columnPrefix of
+/// the nested map appended to prefix.
+fn write_result_map_fetch(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    result_map: &mapper::YoResultMap,
+    mapper: &mapper::Mapper,
+) -> Result<()> {
+    let fn_sig = format!(
+        "int {}_fetch({} n, yb_stmt_result_t r, const char* prefix)",
+        result_map.type_, result_map.type_
+    );
+    let line = format!("{};\n", fn_sig);
+    mapper_h_file.write_all(line.as_bytes()).unwrap();
+
+    let line = format!("{} {{\n", fn_sig);
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    for result in &result_map.results {
+        let get = match result.yo_type.as_str() {
+            "int64_t" => "yb_stmt_result_get_int",
+            "double" => "yb_stmt_result_get_double",
+            "yb_string_t" => "yb_stmt_result_get_string",
+            yo_type => {
+                return Err(GenCError {
+                    message: format!("unsupported type: {}", yo_type),
+                })
+            }
+        };
+        // a column not in the result leaves the field as it is
+        let line = format!(
+            "{sp}{{
+{sp}    int column = yb_stmt_result_column(r, prefix, \"{column}\");
+{sp}    if (column != -1) {{
+{sp}        if ({get}(r, column, &n->{property}) != YB_OK) {{
+{sp}            return YB_FAIL;
+{sp}        }}
+{sp}        {ty}_set_null(n, {ty}_field_{property}, yb_stmt_result_is_null(r, column));
+{sp}    }}
+{sp}}}
+",
+            sp = spaces(4),
+            get = get,
+            column = c_escape(&result.column),
+            property = result.property,
+            ty = result_map.type_
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    // a fetched row has nothing to write back
+    if !result_map.results.is_empty() {
+        let line = format!("    {}_clear_dirty(n);\n", result_map.type_);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+
+    let nested_maps = result_map
+        .associations
+        .iter()
+        .map(|nested| (nested, false))
+        .chain(result_map.collections.iter().map(|nested| (nested, true)));
+    for (nested, is_collection) in nested_maps {
+        let nested_map = nested_result_map(mapper, result_map, nested)?;
+        let prop = &nested.property;
+        let line = format!(
+            "{sp}{{\n{sp}    char {prop}_prefix[256];\n{sp}    snprintf({prop}_prefix, sizeof({prop}_prefix), \"%s%s\", prefix, \"{column_prefix}\");\n",
+            sp = spaces(4),
+            prop = prop,
+            column_prefix = c_escape(&nested.column_prefix)
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+        let conds: Vec<String> = nested_map
+            .id_results()
+            .iter()
+            .map(|r| {
+                format!(
+                    "!yb_stmt_result_is_null(r, yb_stmt_result_column(r, {}_prefix, \"{}\"))",
+                    prop,
+                    c_escape(&r.column)
+                )
+            })
+            .collect();
+        let conds = if conds.is_empty() {
+            "0".to_string()
+        } else {
+            conds.join(" ||\n            ")
+        };
+        let line = format!(
+            "{sp}    if ({conds}) {{
+{sp}        {ty} child = {ty}_new();
+{sp}        if (child == NULL) {{
+{sp}            return YB_FAIL;
+{sp}        }}
+{sp}        if ({ty}_fetch(child, r, {prop}_prefix) != YB_OK) {{
+{sp}            {ty}_free(child);
+{sp}            return YB_FAIL;
+{sp}        }}
+",
+            sp = spaces(4),
+            conds = conds,
+            ty = nested_map.type_,
+            prop = prop
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        let line = if is_collection {
+            format!(
+                "{sp}        if ({ty}_{prop}_push(n, child) != YB_OK) {{\n{sp}            {child_ty}_free(child);\n{sp}            return YB_FAIL;\n{sp}        }}\n",
+                sp = spaces(4),
+                ty = result_map.type_,
+                prop = prop,
+                child_ty = nested_map.type_
+            )
+        } else {
+            format!(
+                "{sp}        if (n->{prop} != NULL) {{\n{sp}            {ty}_free(n->{prop});\n{sp}        }}\n{sp}        n->{prop} = child;\n",
+                sp = spaces(4),
+                prop = prop,
+                ty = nested_map.type_
+            )
+        };
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        let line = format!("{sp}    }}\n{sp}}}\n", sp = spaces(4));
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
     mapper_c_file
-        .write("    free(n);\n}\n\n".as_bytes())
+        .write_all("    return YB_OK;\n}\n\n".as_bytes())
         .unwrap();
+    Ok(())
+}
+
+/// `<type>_list_get` and `<type>_list_free` for the yb_list_t of the structs a
+/// select fetches
+fn write_result_map_list(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    result_map: &mapper::YoResultMap,
+) -> Result<()> {
+    let ty = &result_map.type_;
+    let line = format!(
+        "{ty} {ty}_list_get(yb_list_t l, int64_t i);\nvoid {ty}_list_free(yb_list_t l);\n",
+        ty = ty
+    );
+    mapper_h_file.write_all(line.as_bytes()).unwrap();
 
-    return Ok(());
+    let line = format!(
+        "{ty} {ty}_list_get(yb_list_t l, int64_t i) {{
+    return ({ty})yb_list_get(l, i);
+}}
+
+void {ty}_list_free(yb_list_t l) {{
+    for (int64_t i = 0; i < yb_list_len(l); ++i) {{
+        {ty}_free(({ty})yb_list_get(l, i));
+    }}
+    yb_list_free(l, NULL);
+}}
+
+",
+        ty = ty
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
 }
 
 fn spaces(n: usize) -> String {
-    return std::iter::repeat(' ').take(n).collect::<String>();
+    " ".repeat(n)
+}
+
+/// escape text to the content of a C string literal
+fn c_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+/// an operand of a comparison in a test expression
+enum TestOperand<'a> {
+    Null,
+    Prop(&'a ParamProp),
+    /// C numeric literal
+    Num(String),
+    /// C string literal
+    Str(String),
+}
+
+impl TestOperand<'_> {
+    fn is_numeric(&self) -> bool {
+        match self {
+            TestOperand::Num(_) => true,
+            TestOperand::Prop(prop) => prop.yo_type != "yb_string_t",
+            _ => false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            TestOperand::Null => "null".to_string(),
+            TestOperand::Prop(prop) => format!("{} ({})", prop.c_expr, prop.yo_type),
+            TestOperand::Num(lit) => lit.clone(),
+            TestOperand::Str(lit) => lit.clone(),
+        }
+    }
+}
+
+fn test_error(test: &str, message: String) -> GenCError {
+    GenCError {
+        message: format!("test \"{}\": {}", test, message),
+    }
+}
+
+fn test_operand<'a>(
+    expr: &mapper::TestExpr,
+    params: &'a StmtParams,
+    test: &str,
+) -> Result<TestOperand<'a>> {
+    match expr {
+        mapper::TestExpr::Null => Ok(TestOperand::Null),
+        mapper::TestExpr::Bool(b) => Ok(TestOperand::Num(if *b { "1" } else { "0" }.to_string())),
+        mapper::TestExpr::Int(v) => Ok(TestOperand::Num(format!("{}LL", v))),
+        mapper::TestExpr::Float(v) => Ok(TestOperand::Num(format!("{:?}", v))),
+        mapper::TestExpr::Str(v) => Ok(TestOperand::Str(format!("\"{}\"", c_escape(v)))),
+        mapper::TestExpr::Property(name) => match params.get(name) {
+            Some(prop) => Ok(TestOperand::Prop(prop)),
+            None => Err(test_error(
+                test,
+                format!("unknown property `{}`, not in {}", name, params.source),
+            )),
+        },
+        _ => Err(test_error(
+            test,
+            "logical expression used as a comparison operand".to_string(),
+        )),
+    }
+}
+
+/// C condition of a comparison in a test expression
+fn test_compare(
+    op: mapper::CompareOp,
+    left: TestOperand,
+    right: TestOperand,
+    test: &str,
+) -> Result<String> {
+    let is_eq = op == mapper::CompareOp::Eq || op == mapper::CompareOp::Ne;
+    match (&left, &right) {
+        (TestOperand::Null, TestOperand::Null) if is_eq => Ok(if op == mapper::CompareOp::Eq {
+            "1"
+        } else {
+            "0"
+        }
+        .to_string()),
+        (TestOperand::Prop(prop), TestOperand::Null)
+        | (TestOperand::Null, TestOperand::Prop(prop))
+            if is_eq =>
+        {
+            Ok(if op == mapper::CompareOp::Eq {
+                prop.null_cond.clone()
+            } else {
+                format!("!{}", prop.null_cond)
+            })
+        }
+        (l, r) if l.is_numeric() && r.is_numeric() => {
+            let l = match l {
+                TestOperand::Prop(prop) => &prop.c_expr,
+                TestOperand::Num(e) => e,
+                _ => unreachable!(),
+            };
+            let r = match r {
+                TestOperand::Prop(prop) => &prop.c_expr,
+                TestOperand::Num(e) => e,
+                _ => unreachable!(),
+            };
+            Ok(format!("({} {} {})", l, op.c_op(), r))
+        }
+        (TestOperand::Prop(l), TestOperand::Prop(r))
+            if l.yo_type == "yb_string_t" && r.yo_type == "yb_string_t" =>
+        {
+            Ok(format!(
+                "(yb_string_compare({}, {}) {} 0)",
+                l.c_expr,
+                r.c_expr,
+                op.c_op()
+            ))
+        }
+        (TestOperand::Prop(prop), TestOperand::Str(lit)) if prop.yo_type == "yb_string_t" => {
+            Ok(format!(
+                "(yb_string_compare_cstr({}, {}) {} 0)",
+                prop.c_expr,
+                lit,
+                op.c_op()
+            ))
+        }
+        (TestOperand::Str(lit), TestOperand::Prop(prop)) if prop.yo_type == "yb_string_t" => {
+            Ok(format!(
+                "(0 {} yb_string_compare_cstr({}, {}))",
+                op.c_op(),
+                prop.c_expr,
+                lit
+            ))
+        }
+        _ => Err(test_error(
+            test,
+            format!(
+                "cannot compare {} {} {}",
+                left.describe(),
+                op.c_op(),
+                right.describe()
+            ),
+        )),
+    }
+}
+
+/// C condition of a <if>/<when> test expression
+fn test_cond(expr: &mapper::TestExpr, params: &StmtParams, test: &str) -> Result<String> {
+    match expr {
+        mapper::TestExpr::Not(e) => Ok(format!("!{}", test_cond(e, params, test)?)),
+        mapper::TestExpr::And(l, r) => Ok(format!(
+            "({} && {})",
+            test_cond(l, params, test)?,
+            test_cond(r, params, test)?
+        )),
+        mapper::TestExpr::Or(l, r) => Ok(format!(
+            "({} || {})",
+            test_cond(l, params, test)?,
+            test_cond(r, params, test)?
+        )),
+        mapper::TestExpr::Compare(op, l, r) => test_compare(
+            *op,
+            test_operand(l, params, test)?,
+            test_operand(r, params, test)?,
+            test,
+        ),
+        mapper::TestExpr::Bool(b) => Ok(if *b { "1" } else { "0" }.to_string()),
+        mapper::TestExpr::Dirty(name) => match params.get(name).map(|p| p.field_enum()) {
+            Some(Some((ty, field))) => Ok(format!("{}_is_dirty(n, {})", ty, field)),
+            Some(None) => Err(test_error(
+                test,
+                format!("dirty({}) needs a field of the parameterType", name),
+            )),
+            None => Err(test_error(
+                test,
+                format!("unknown property `{}`, not in {}", name, params.source),
+            )),
+        },
+        // a bare operand is true when it is set
+        _ => match test_operand(expr, params, test)? {
+            TestOperand::Null => Ok("0".to_string()),
+            TestOperand::Str(_) => Ok("1".to_string()),
+            TestOperand::Num(lit) => Ok(format!("({} != 0)", lit)),
+            TestOperand::Prop(prop) if prop.yo_type == "yb_string_t" => {
+                Ok(format!("!{}", prop.null_cond))
+            }
+            TestOperand::Prop(prop) => Ok(format!("(!{} && {} != 0)", prop.null_cond, prop.c_expr)),
+        },
+    }
+}
+
+/// write the body of a branch, its content is appended to valname. The
+/// caller writes the braces around it.
+fn write_append_branch_body(
+    mapper_c_file: &mut File,
+    indent: usize,
+    params: &StmtParams,
+    content: &Vec<mapper::SqlElement>,
+    valname: &String,
+    inc: &mut i32,
+) -> Result<()> {
+    write_sql_gen_stmt(mapper_c_file, indent, params, content, valname, inc)
+}
+
+/// write a call that returns YB_OK or YB_FAIL when out of memory, the
+/// failure jumps to the `oom` label of write_cmd_gen_stmt
+fn write_checked_call(mapper_c_file: &mut File, indent: usize, call: &str) {
+    // if (<call> != YB_OK) {
+    //     goto oom;
+    // }
+    let line = format!(
+        "{sp}if ({call} != YB_OK) {{\n{sp}    goto oom;\n{sp}}}\n",
+        sp = spaces(indent),
+        call = call
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 }
 
 /// write <if> statement,
 fn write_append_if_stmt(
     mapper_c_file: &mut File,
     indent: usize,
-    table: &mapper::Mapper,
+    params: &StmtParams,
     elem: &mapper::YoIf,
     valname: &String,
     inc: &mut i32,
 ) -> Result<()> {
     // if (n->???? == ????) {
-    //     generate <tmp> content ...
-    //     yb_string_append_c_str(<valname>, "<tmp>...");
-    //     yb_string_free(<tmp>);
+    //     ...
     // }
-    let line = format!("{}if (n->{}) {{\n", spaces(indent), elem.test);
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let tmp_val = format!("tmp_{}", *inc);
-    *inc += 1;
     let line = format!(
-        "{}yb_string_t {} = yb_string_new();\n",
-        spaces(indent + 4),
-        tmp_val
+        "{}if ({}) {{\n",
+        spaces(indent),
+        test_cond(&elem.expr, params, &elem.test)?
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    write_sql_gen_stmt(
+    write_append_branch_body(
         mapper_c_file,
         indent + 4,
-        table,
+        params,
         &elem.content,
-        &tmp_val,
+        valname,
         inc,
     )?;
 
-    // append
-    let line = format!(
-        "{}yb_string_append({}, {});\n",
-        spaces(indent + 4),
-        valname,
-        tmp_val
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    // }
+    let line = format!("{}}}\n", spaces(indent));
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    Ok(())
+}
+
+/// write <choose> statement as an if / else if / else chain
+fn write_append_choose_stmt(
+    mapper_c_file: &mut File,
+    indent: usize,
+    params: &StmtParams,
+    elem: &mapper::YoChoose,
+    valname: &String,
+    inc: &mut i32,
+) -> Result<()> {
+    // if (<when 1>) {
+    //     ...
+    // } else if (<when 2>) {
+    //     ...
+    // } else {
+    //     <otherwise> ...
+    // }
+    for (i, when) in elem.whens.iter().enumerate() {
+        let line = if i == 0 {
+            format!(
+                "{}if ({}) {{\n",
+                spaces(indent),
+                test_cond(&when.expr, params, &when.test)?
+            )
+        } else {
+            format!(
+                "{}}} else if ({}) {{\n",
+                spaces(indent),
+                test_cond(&when.expr, params, &when.test)?
+            )
+        };
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+        write_append_branch_body(
+            mapper_c_file,
+            indent + 4,
+            params,
+            &when.content,
+            valname,
+            inc,
+        )?;
+    }
 
-    // free tmp
-    let line = format!("{}yb_string_free({});\n", spaces(indent + 4), tmp_val);
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    if let Some(otherwise) = &elem.otherwise {
+        let line = format!("{}}} else {{\n", spaces(indent));
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+        write_append_branch_body(mapper_c_file, indent + 4, params, otherwise, valname, inc)?;
+    }
 
     // }
     let line = format!("{}}}\n", spaces(indent));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    return Ok(());
+    Ok(())
 }
 
 fn write_trim_stmt(
     mapper_c_file: &mut File,
     indent: usize,
-    table: &mapper::Mapper,
+    params: &StmtParams,
     elem: &mapper::YoTrim,
     valname: &String,
     inc: &mut i32,
 ) -> Result<()> {
-    // yb_string_t tmp_<inc> = yb_string_new();
+    // tmp_<inc> = yb_string_new();
+    // if (tmp_<inc> == NULL) {
+    //     goto oom;
+    // }
     // generate <tmp_inc> content ...
-    // yb_string_trim(...) --> valname;
-    // yb_string_free(tmp_inc);
+    // if (yb_string_trim(tmp_<inc>, ..., valname) != YB_OK) {
+    //     goto oom;
+    // }
+    // yb_string_free(tmp_<inc>);
+    // tmp_<inc> = NULL;
+    // tmp_<inc> is declared by write_cmd_gen_stmt, see count_trims
     let tmp_val = format!("tmp_{}", *inc);
     *inc += 1;
     let line = format!(
-        "{}yb_string_t {} = yb_string_new();\n",
-        spaces(indent),
-        tmp_val
+        "{sp}{tmp} = yb_string_new();\n{sp}if ({tmp} == NULL) {{\n{sp}    goto oom;\n{sp}}}\n",
+        sp = spaces(indent),
+        tmp = tmp_val
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-
-    write_sql_gen_stmt(mapper_c_file, indent, table, &elem.content, &tmp_val, inc)?;
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    write_sql_gen_stmt(mapper_c_file, indent, params, &elem.content, &tmp_val, inc)?;
+
+    let call = format!(
+        "yb_string_trim({src}, \"{prefix}\", \"{suffix}\", \"{prefix_override}\", \"{suffix_override}\", {dest})",
+        src = tmp_val,
+        prefix = c_escape(&elem.prefix),
+        suffix = c_escape(&elem.suffix),
+        prefix_override = c_escape(&elem.prefix_overrides),
+        suffix_override = c_escape(&elem.suffix_overrides),
+        dest = valname
+    );
+    write_checked_call(mapper_c_file, indent, &call);
 
     let line = format!(
-        "{space}yb_string_trim({src}, \"{prefix}\", \"{suffix}\", \"{prefix_override}\", \"{suffix_override}\", {dest});\n",
-        space=spaces(indent),
-        src=tmp_val,
-        prefix=elem.prefix,
-        suffix=elem.suffix,
-        prefix_override=elem.prefix_overrides,
-        suffix_override=elem.suffix_overrides,
-        dest=valname
+        "{sp}yb_string_free({tmp});\n{sp}{tmp} = NULL;\n",
+        sp = spaces(indent),
+        tmp = tmp_val
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    let line = format!("{}yb_string_free({});\n", spaces(indent), tmp_val);
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    Ok(())
+}
+
+/// write ${property} substitution, see mapper::YoSubst
+fn write_subst_stmt(
+    mapper_c_file: &mut File,
+    indent: usize,
+    params: &StmtParams,
+    elem: &mapper::YoSubst,
+    valname: &String,
+) -> Result<()> {
+    let prop = match params.get(&elem.property) {
+        Some(prop) => prop,
+        None => {
+            return Err(GenCError {
+                message: format!(
+                    "${{{}}}: unknown property, not in {}",
+                    elem.property, params.source
+                ),
+            });
+        }
+    };
+
+    match (prop.yo_type.as_str(), &elem.whitelist) {
+        ("yb_string_t", Some(whitelist)) => {
+            // if (yb_string_compare_cstr(n->p, "a") == 0 || ...) {
+            //     if (yb_string_append(<valname>, n->p) != YB_OK) {
+            //         goto oom;
+            //     }
+            // } else {
+            //     yb_error_set(err, "<id>", "${p} is not in its whitelist");
+            //     rc = YB_FAIL;
+            // }
+            let cond = whitelist
+                .iter()
+                .map(|v| {
+                    format!(
+                        "yb_string_compare_cstr({}, \"{}\") == 0",
+                        prop.c_expr,
+                        c_escape(v)
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(" || ");
+            let line = format!(
+                "{sp}if ({cond}) {{\n{sp}    if (yb_string_append({dest}, {p}) != YB_OK) {{\n{sp}        goto oom;\n{sp}    }}\n{sp}}} else {{\n{sp}    yb_error_set(err, \"{id}\", \"${{{name}}} is not in its whitelist\");\n{sp}    rc = YB_FAIL;\n{sp}}}\n",
+                sp = spaces(indent),
+                cond = cond,
+                dest = valname,
+                p = prop.c_expr,
+                id = params.id,
+                name = prop.name
+            );
+            mapper_c_file.write_all(line.as_bytes()).unwrap();
+        }
+        ("yb_string_t", None) => {
+            // if (<null>) {
+            //     yb_error_set(err, "<id>", "${p} is NULL");
+            //     rc = YB_FAIL;
+            // } else if (yb_string_append_escaped(conn, <valname>, n->p) != YB_OK) {
+            //     yb_error_set(err, "<id>", "${p} can not be escaped: ...");
+            //     rc = YB_ERR_ESCAPE;
+            // }
+            let line = format!(
+                "{sp}if ({null}) {{\n{sp}    yb_error_set(err, \"{id}\", \"${{{name}}} is NULL\");\n{sp}    rc = YB_FAIL;\n{sp}}} else if (yb_string_append_escaped(conn, {dest}, {p}) != YB_OK) {{\n{sp}    yb_error_set(err, \"{id}\", \"${{{name}}} can not be escaped: out of memory or mysql_real_escape_string() failed\");\n{sp}    rc = YB_ERR_ESCAPE;\n{sp}}}\n",
+                sp = spaces(indent),
+                null = prop.null_cond,
+                dest = valname,
+                p = prop.c_expr,
+                id = params.id,
+                name = prop.name
+            );
+            mapper_c_file.write_all(line.as_bytes()).unwrap();
+        }
+        (_, Some(_)) => {
+            return Err(GenCError {
+                message: format!(
+                    "${{{}}}: whitelist is only supported for yb_string_t",
+                    prop.name
+                ),
+            });
+        }
+        (yo_type, None) => {
+            let append_fn = match yo_type {
+                "int64_t" => "yb_string_append_int",
+                "double" => "yb_string_append_double",
+                _ => {
+                    return Err(GenCError {
+                        message: format!("unsupported type: {}", yo_type),
+                    });
+                }
+            };
+            // if (<null>) {
+            //     if (yb_string_append_c_str(<valname>, "NULL") != YB_OK) {
+            //         goto oom;
+            //     }
+            // } else if (yb_string_append_int(<valname>, n->p) != YB_OK) {
+            //     goto oom;
+            // }
+            let line = format!(
+                "{sp}if ({null}) {{\n{sp}    if (yb_string_append_c_str({dest}, \"NULL\") != YB_OK) {{\n{sp}        goto oom;\n{sp}    }}\n{sp}}} else if ({append}({dest}, {p}) != YB_OK) {{\n{sp}    goto oom;\n{sp}}}\n",
+                sp = spaces(indent),
+                null = prop.null_cond,
+                dest = valname,
+                append = append_fn,
+                p = prop.c_expr
+            );
+            mapper_c_file.write_all(line.as_bytes()).unwrap();
+        }
+    }
 
-    return Ok(());
+    Ok(())
 }
 
 fn write_sql_gen_stmt(
     mapper_c_file: &mut File,
     indent: usize,
-    table: &mapper::Mapper,
-    elems: &Vec<Box<mapper::SqlElement>>,
+    params: &StmtParams,
+    elems: &Vec<mapper::SqlElement>,
     valname: &String,
     inc: &mut i32,
 ) -> Result<()> {
     for elem in elems {
-        match &**elem {
+        match elem {
             mapper::SqlElement::YoIf(ref if_elem) => {
-                write_append_if_stmt(mapper_c_file, indent, table, if_elem, valname, inc)?;
+                write_append_if_stmt(mapper_c_file, indent, params, if_elem, valname, inc)?;
+            }
+            mapper::SqlElement::Choose(ref choose_elem) => {
+                write_append_choose_stmt(mapper_c_file, indent, params, choose_elem, valname, inc)?;
             }
-            mapper::SqlElement::YoInclude(inc) => {
-                let inc_sql = match table.sqls.get(&inc.refid) {
-                    Some(sql) => sql,
-                    None => {
-                        return Err(GenCError {
-                            message: format!("include sql not found: {}", inc.refid),
-                        });
-                    }
-                };
-                let line = format!(
-                    "{}yb_string_append_c_str({}, \"{}\")",
-                    spaces(indent),
+            mapper::SqlElement::YoInclude(inc_elem) => {
+                // content resolved by mapper::resolve_includes
+                write_sql_gen_stmt(
+                    mapper_c_file,
+                    indent,
+                    params,
+                    &inc_elem.content,
                     valname,
-                    inc_sql.text.replace("\n", "\\n").replace("\r", "\\r")
-                );
-                mapper_c_file.write(line.as_bytes()).unwrap();
+                    inc,
+                )?;
             }
             mapper::SqlElement::YoText(ref tex) => {
-                let line = format!(
-                    "{}yb_string_append_c_str({}, \"{}\");\n",
-                    spaces(indent),
+                let (text, names) = mapper::split_placeholders(tex);
+                let call = format!(
+                    "yb_string_append_c_str({}, \"{}\")",
                     valname,
-                    tex.replace("\n", "\\n").replace("\r", "\\r")
+                    c_escape(&text)
                 );
-                mapper_c_file.write(line.as_bytes()).unwrap();
+                write_checked_call(mapper_c_file, indent, &call);
+                for name in names {
+                    write_bind(
+                        mapper_c_file,
+                        indent,
+                        "bind_num",
+                        placeholder_prop(params, name)?,
+                    )?;
+                    let line = format!("{}++bind_num;\n", spaces(indent));
+                    mapper_c_file.write_all(line.as_bytes()).unwrap();
+                }
+            }
+            mapper::SqlElement::YoSubst(ref elem) => {
+                write_subst_stmt(mapper_c_file, indent, params, elem, valname)?;
             }
             mapper::SqlElement::YoTrim(ref elem) => {
-                write_trim_stmt(mapper_c_file, indent, table, elem, valname, inc)?;
+                write_trim_stmt(mapper_c_file, indent, params, elem, valname, inc)?;
             }
         }
     }
 
-    return Ok(());
+    Ok(())
 }
 
-fn write_cmd_to_prepare_sql(
-    mapper_c_file: &mut File,
-    result_map: &mapper::YoResultMap,
-    table: &mapper::Mapper,
-) -> Result<()> {
-    // for
-    let line = format!(
-        "{}for (int64_t i = 0; i < yb_string_length(cmd); ++i) {{\n",
-        spaces(4)
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+/// a property a statement can use in #{}, ${} and test expressions
+struct ParamProp {
+    name: String,
+    /// C expression of the value
+    c_expr: String,
+    yo_type: String,
+    /// C condition that holds when the value is NULL
+    null_cond: String,
+    /// the parameterType the property is a field of, None for scalar
+    /// parameters
+    struct_type: Option<String>,
+}
 
-    let line = format!(
-        "{}if (i < yb_string_length(cmd) - 1 && yb_string_data(cmd)[i] == '#' && yb_string_data(cmd)[i+1] == '{{') {{\n",
-        spaces(8)
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-
-    let line = format!(
-        "{}yb_string_append_data(prepare_sql, yb_string_data(cmd)+pre, i-pre);\n{}pre = i + 2;\n",
-        spaces(12),
-        spaces(12)
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-
-    let line = format!(
-        "{}while (pre < yb_string_length(cmd) && yb_string_data(cmd)[pre] != '}}') {{\n{}++pre;\n{}}}\n",
-        spaces(12),
-        spaces(16),
-        spaces(12)
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+impl ParamProp {
+    /// `<type>_field_<property>` of a struct field
+    fn field_enum(&self) -> Option<(&str, String)> {
+        self.struct_type
+            .as_deref()
+            .map(|ty| (ty, format!("{}_field_{}", ty, self.name)))
+    }
+}
 
-    let line = format!(
-        "{}yb_string_t val_tmp = yb_string_new();\n{}yb_string_ref_data(val_tmp, yb_string_data(cmd)+i+2, pre - i - 2);\n",
-        spaces(12),
-        spaces(12)
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+/// the parameters of the C function generated for a statement
+struct StmtParams {
+    /// C parameter declarations following `MYSQL* conn`, may be empty
+    decl: String,
+    /// the parameter names, to pass them on to another function
+    args: String,
+    props: Vec<ParamProp>,
+    /// where the properties come from, for error messages
+    source: String,
+    /// the statement id, for the errors the generated code sets
+    id: String,
+}
 
-    let line = format!("{}i = pre;\n", spaces(12));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+impl StmtParams {
+    fn get(&self, name: &str) -> Option<&ParamProp> {
+        self.props.iter().find(|p| p.name == name)
+    }
 
-    for row in &result_map.results {
-        let line = format!(
-            "{}if (yb_string_compare_cstr(val_tmp, \"{}\")) {{\n",
-            spaces(12),
-            row.property
-        );
-        mapper_c_file.write(line.as_bytes()).unwrap();
-        let line = format!(
-            "{}yb_string_append_c_str(prepare_sql, \"?\");\n",
-            spaces(16),
-        );
-        mapper_c_file.write(line.as_bytes()).unwrap();
+    /// `MYSQL* conn` followed by the parameter declarations
+    fn c_args(&self) -> String {
+        if self.decl.is_empty() {
+            "MYSQL* conn".to_string()
+        } else {
+            format!("MYSQL* conn, {}", self.decl)
+        }
+    }
 
-        // bind
-        if row.yo_type == "int64_t" {
-            let line = format!(
-                "
-                bind[bind_num].buffer_type = MYSQL_TYPE_LONGLONG;
-                bind[bind_num].buffer = &n->{};
-                ++bind_num;\n",
-                row.property,
-            );
-            mapper_c_file.write(line.as_bytes()).unwrap();
-        } else if row.yo_type == "double" {
-            let line = format!(
-                "
-                bind[bind_num].buffer_type = MYSQL_TYPE_DOUBLE;
-                bind[bind_num].buffer = &n->{};
-                ++bind_num;\n",
-                row.property,
-            );
-            mapper_c_file.write(line.as_bytes()).unwrap();
+    /// `conn` followed by the parameter names
+    fn c_call_args(&self) -> String {
+        if self.args.is_empty() {
+            "conn".to_string()
         } else {
-            let line = format!(
-                "
-                bind[bind_num].buffer_type = MYSQL_TYPE_STRING;
-                bind[bind_num].buffer = yb_string_data(n->{field});
-                bind[bind_num].buffer_length = yb_string_length(n->{field});
-                ++bind_num;\n",
-                field = row.property
-            );
-            mapper_c_file.write(line.as_bytes()).unwrap();
+            format!("conn, {}", self.args)
         }
+    }
+}
 
-        let line = format!("{}}}\n", spaces(12));
-        mapper_c_file.write(line.as_bytes()).unwrap();
+/// the parameters of a statement: the resultMap type named by parameterType
+/// is passed as `n`, scalar parameters are passed by their own names, see
+/// scalar_param.
+fn stmt_params(
+    table: &mapper::Mapper,
+    id: &str,
+    parameter_type: &str,
+    params: &[mapper::YoParam],
+) -> Result<StmtParams> {
+    if !params.is_empty() {
+        let scalars = params
+            .iter()
+            .map(scalar_param)
+            .collect::<Result<Vec<(String, String, String)>>>()?;
+        return Ok(StmtParams {
+            decl: scalars
+                .iter()
+                .map(|(decl, _, _)| decl.clone())
+                .collect::<Vec<String>>()
+                .join(", "),
+            args: params
+                .iter()
+                .map(|p| p.name.clone())
+                .collect::<Vec<String>>()
+                .join(", "),
+            props: params
+                .iter()
+                .zip(scalars)
+                .map(|(p, (_, value, null_cond))| ParamProp {
+                    name: p.name.clone(),
+                    c_expr: value,
+                    yo_type: p.yo_type.clone(),
+                    null_cond,
+                    struct_type: None,
+                })
+                .collect(),
+            source: format!("parameters of {}", id),
+            id: id.to_string(),
+        });
     }
-    let line = format!("{}}}\n", spaces(8));
-    mapper_c_file.write(line.as_bytes()).unwrap();
 
-    let line = format!("{}}}\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    if parameter_type.is_empty() {
+        return Ok(StmtParams {
+            decl: String::new(),
+            args: String::new(),
+            props: Vec::new(),
+            source: format!("parameters of {}", id),
+            id: id.to_string(),
+        });
+    }
 
+    match table.type_maps.get(parameter_type) {
+        Some(result_map) => Ok(StmtParams {
+            decl: format!("{} n", parameter_type),
+            args: "n".to_string(),
+            props: result_map
+                .results
+                .iter()
+                .map(|r| ParamProp {
+                    name: r.property.clone(),
+                    c_expr: format!("n->{}", r.property),
+                    yo_type: r.yo_type.clone(),
+                    null_cond: format!(
+                        "{ty}_is_null(n, {ty}_field_{prop})",
+                        ty = parameter_type,
+                        prop = r.property
+                    ),
+                    struct_type: Some(parameter_type.to_string()),
+                })
+                .collect(),
+            source: format!("resultMap {}", result_map.id),
+            id: id.to_string(),
+        }),
+        None => Err(GenCError {
+            message: format!(
+                "{}: parameterType {} is not the type of any resultMap",
+                id, parameter_type
+            ),
+        }),
+    }
+}
+
+/// write the bind entry of a #{} property, index is a C expression. A NULL
+/// value is bound as MYSQL_TYPE_NULL.
+fn write_bind(
+    mapper_c_file: &mut File,
+    indent: usize,
+    index: &str,
+    prop: &ParamProp,
+) -> Result<()> {
     let line = format!(
-        "{}yb_string_append_data(prepare_sql, yb_string_data(cmd)+pre, yb_string_length(cmd)-pre);\n",
-        spaces(4)
+        "{sp}if ({null}) {{\n{sp}    bind[{i}].buffer_type = MYSQL_TYPE_NULL;\n{sp}}} else {{\n",
+        sp = spaces(indent),
+        null = prop.null_cond,
+        i = index
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    return Ok(());
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    let indent = indent + 4;
+    let line = match prop.yo_type.as_str() {
+        "int64_t" | "double" => format!(
+            "{sp}bind[{i}].buffer_type = {ty};\n{sp}bind[{i}].buffer = (void*)&{field};\n",
+            sp = spaces(indent),
+            i = index,
+            ty = if prop.yo_type == "int64_t" {
+                "MYSQL_TYPE_LONGLONG"
+            } else {
+                "MYSQL_TYPE_DOUBLE"
+            },
+            field = prop.c_expr
+        ),
+        "yb_string_t" => format!(
+            "{sp}bind[{i}].buffer_type = MYSQL_TYPE_STRING;\n{sp}bind[{i}].buffer = (void*)yb_string_data({field});\n{sp}bind[{i}].buffer_length = yb_string_length({field});\n",
+            sp = spaces(indent),
+            i = index,
+            field = prop.c_expr
+        ),
+        yo_type => {
+            return Err(GenCError {
+                message: format!("#{{{}}}: unsupported type: {}", prop.name, yo_type),
+            })
+        }
+    };
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    let line = format!("{}}}\n", spaces(indent - 4));
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
 }
 
-fn write_insert_fn(
-    mapper_h_file: &mut File,
+/// the #{} property of a name
+fn placeholder_prop<'a>(params: &'a StmtParams, name: &str) -> Result<&'a ParamProp> {
+    params.get(name).ok_or_else(|| GenCError {
+        message: format!("#{{{}}}: unknown property, not in {}", name, params.source),
+    })
+}
+
+/// the #{} count of all branches, the most a statement can bind
+fn count_placeholders(elems: &[mapper::SqlElement]) -> usize {
+    elems
+        .iter()
+        .map(|elem| match elem {
+            mapper::SqlElement::YoText(text) => mapper::split_placeholders(text).1.len(),
+            mapper::SqlElement::YoSubst(_) => 0,
+            mapper::SqlElement::YoIf(if_elem) => count_placeholders(&if_elem.content),
+            mapper::SqlElement::Choose(choose) => {
+                choose
+                    .whens
+                    .iter()
+                    .map(|when| count_placeholders(&when.content))
+                    .sum::<usize>()
+                    + choose
+                        .otherwise
+                        .as_ref()
+                        .map_or(0, |otherwise| count_placeholders(otherwise))
+            }
+            mapper::SqlElement::YoTrim(trim) => count_placeholders(&trim.content),
+            mapper::SqlElement::YoInclude(inc) => count_placeholders(&inc.content),
+        })
+        .sum()
+}
+
+/// the <trim> count of all branches, each has its tmp string
+fn count_trims(elems: &[mapper::SqlElement]) -> usize {
+    elems
+        .iter()
+        .map(|elem| match elem {
+            mapper::SqlElement::YoText(_) | mapper::SqlElement::YoSubst(_) => 0,
+            mapper::SqlElement::YoIf(if_elem) => count_trims(&if_elem.content),
+            mapper::SqlElement::Choose(choose) => {
+                choose
+                    .whens
+                    .iter()
+                    .map(|when| count_trims(&when.content))
+                    .sum::<usize>()
+                    + choose
+                        .otherwise
+                        .as_ref()
+                        .map_or(0, |otherwise| count_trims(otherwise))
+            }
+            mapper::SqlElement::YoTrim(trim) => 1 + count_trims(&trim.content),
+            mapper::SqlElement::YoInclude(inc) => count_trims(&inc.content),
+        })
+        .sum()
+}
+
+/// the sql text and #{} properties of content without dynamic elements,
+/// None if it has any
+fn static_sql<'a>(
+    elems: &'a [mapper::SqlElement],
+    sql: &mut String,
+    names: &mut Vec<&'a str>,
+) -> Option<()> {
+    for elem in elems {
+        match elem {
+            mapper::SqlElement::YoText(text) => {
+                let (text, text_names) = mapper::split_placeholders(text);
+                sql.push_str(&text);
+                names.extend(text_names);
+            }
+            mapper::SqlElement::YoInclude(inc) => static_sql(&inc.content, sql, names)?,
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
+/// write the sql text of a statement and its bind entries. A statement
+/// without dynamic elements is a constant `cmd` with a fixed bind sequence,
+/// otherwise `cmd` is built into a yb_string_t and each text fragment binds
+/// its #{} properties as it is appended. Returns whether `cmd` is built.
+fn write_cmd_gen_stmt(
     mapper_c_file: &mut File,
-    insert_m: &mapper::YoInsert,
-    table: &mapper::Mapper,
-) -> Result<()> {
-    {
-        // header file
-        // declare insert
-        let insert_fn = format!(
-            "int {}(MYSQL* conn, {} n);\n",
-            insert_m.id, insert_m.parameter_type
+    params: &StmtParams,
+    content: &Vec<mapper::SqlElement>,
+) -> Result<bool> {
+    // int rc = YB_OK;
+    // MYSQL_BIND bind[<#{} count>];
+    // memset(bind, 0, sizeof(bind));
+    let bind_count = count_placeholders(content);
+    let line = format!(
+        "{sp}int rc = YB_OK;\n{sp}MYSQL_BIND bind[{n}];\n{sp}memset(bind, 0, sizeof(bind));\n",
+        sp = spaces(4),
+        n = std::cmp::max(bind_count, 1)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    let mut sql = String::new();
+    let mut names = Vec::new();
+    if static_sql(content, &mut sql, &mut names).is_some() {
+        // static const char cmd[] = "SELECT a, b FROM XXX WHERE c=?";
+        // bind[0] ...
+        let line = format!(
+            "{}static const char cmd[] = \"{}\";\n",
+            spaces(4),
+            c_escape(&sql)
         );
-        mapper_h_file.write(insert_fn.as_bytes()).unwrap();
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+        for (i, name) in names.iter().enumerate() {
+            write_bind(
+                mapper_c_file,
+                4,
+                &i.to_string(),
+                placeholder_prop(params, name)?,
+            )?;
+        }
+        return Ok(false);
     }
 
-    // impl insert
-    //      int <insert_id>(MYSQL* conn, <parameter_type> n) {
-    let insert_fn_line = format!(
-        "int {}(MYSQL* conn, {} n) {{\n",
-        insert_m.id, insert_m.parameter_type
+    // int bind_num = 0;
+    // yb_string_t tmp_0 = NULL;
+    // yb_string_t cmd = yb_string_new();
+    // if (cmd == NULL) {
+    //     goto oom;
+    // }
+    // ... append to cmd and bind, ${} substitutions may set rc and err ...
+    // goto built;
+    // oom:
+    // yb_error_set(err, "<id>", "out of memory");
+    // rc = YB_FAIL;
+    // built:
+    // if (rc != YB_OK) {
+    //     yb_string_free(tmp_0);
+    //     yb_string_free(cmd);
+    //     return rc;
+    // }
+    if bind_count > 0 {
+        let line = format!("{}int bind_num = 0;\n", spaces(4));
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let tmps = (0..count_trims(content))
+        .map(|i| format!("tmp_{}", i))
+        .collect::<Vec<String>>();
+    for tmp in &tmps {
+        let line = format!("{}yb_string_t {} = NULL;\n", spaces(4), tmp);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!(
+        "{sp}yb_string_t cmd = yb_string_new();\n{sp}if (cmd == NULL) {{\n{sp}    goto oom;\n{sp}}}\n",
+        sp = spaces(4)
     );
-    mapper_c_file.write(insert_fn_line.as_bytes()).unwrap();
-    // statements generating
-    //          yb_string_t sql = yb_string_new();
-    let line = format!("{}yb_string_t cmd = yb_string_new();\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    // generate cmd="INSERT INTO XXX(a,b,c) VALUES (#{xxx}, #{yyy}, #{xxx})"
     write_sql_gen_stmt(
         mapper_c_file,
         4,
-        table,
-        &insert_m.content,
+        params,
+        content,
         &String::from("cmd"),
         &mut 0,
     )?;
 
-    let result_map = table.type_maps.get(&insert_m.parameter_type).unwrap();
+    let line = format!(
+        "{sp}goto built;\noom:\n{sp}yb_error_set(err, \"{id}\", \"out of memory\");\n{sp}rc = YB_FAIL;\nbuilt:\n{sp}if (rc != YB_OK) {{\n",
+        sp = spaces(4),
+        id = params.id
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    for tmp in &tmps {
+        let line = format!("{}yb_string_free({});\n", spaces(8), tmp);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!(
+        "{sp}    yb_string_free(cmd);\n{sp}    return rc;\n{sp}}}\n",
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
+    Ok(true)
+}
+
+/// get `stmt` of `cmd` from the statement cache of conn and run it with
+/// `bind`, `rc` is set to YB_FAIL if any call fails. dynamic is what
+/// write_cmd_gen_stmt returned, attrs are the `mysql_stmt_attr_set` calls
+/// before the execute.
+fn write_stmt_execute(mapper_c_file: &mut File, dynamic: bool, attrs: &[&str]) -> Result<()> {
+    let (data, length) = if dynamic {
+        ("yb_string_data(cmd)", "yb_string_length(cmd)")
+    } else {
+        ("cmd", "sizeof(cmd) - 1")
+    };
     let line = format!(
-        "{}MYSQL_BIND bind[{}*2];\n",
-        spaces(4),
-        result_map.results.len()
+        "{sp}MYSQL_STMT* stmt = NULL;\n{sp}if (yb_stmt_cache_prepare(conn, {data}, {length}, &stmt) != YB_OK\n",
+        sp = spaces(4),
+        data = data,
+        length = length
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    for attr in attrs {
+        let line = format!("{}    || {}\n", spaces(4), attr);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
     let line = format!(
-        "{}memset(bind, 0, sizeof(MYSQL_BIND)*{}*2);\n",
-        spaces(4),
-        result_map.results.len()
+        "{sp}    || mysql_stmt_bind_param(stmt, bind)
+{sp}    || mysql_stmt_execute(stmt) != 0) {{
+{sp}    rc = YB_FAIL;
+{sp}}}
+",
+        sp = spaces(4)
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int bind_num = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
+}
 
-    let line = format!("{}yb_string_t prepare_sql = yb_string_new();\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int64_t pre = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+/// report a YB_FAIL `rc` to `err` and replace it by the fail value of the
+/// MySQL error, before `stmt` is closed
+fn write_stmt_error(mapper_c_file: &mut File, id: &str) -> Result<()> {
+    let line = format!(
+        "{sp}if (rc == YB_FAIL) {{
+{sp}    if (stmt == NULL) {{
+{sp}        rc = yb_error_from_conn(err, \"{id}\", conn);
+{sp}    }} else {{
+{sp}        rc = yb_error_from_stmt(err, \"{id}\", stmt);
+{sp}    }}
+{sp}}}
+",
+        sp = spaces(4),
+        id = id
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
+}
 
-    // #{} -> ? sql prepare
-    write_cmd_to_prepare_sql(mapper_c_file, result_map, table)?;
+/// return `stmt` to the statement cache, or close it after a failure, and
+/// free a built `cmd`
+fn write_stmt_close(mapper_c_file: &mut File, dynamic: bool) -> Result<()> {
+    let line = format!(
+        "{sp}if (rc < 0) {{\n{sp}    yb_stmt_cache_close(conn, stmt);\n{sp}}} else {{\n{sp}    yb_stmt_cache_release(conn, stmt);\n{sp}}}\n",
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    if dynamic {
+        let line = format!("{}yb_string_free(cmd);\n", spaces(4));
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    Ok(())
+}
 
-    let line = format!("{}MYSQL_STMT* stmt = mysql_stmt_init(NULL);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+/// write `static int <insert_id>_select_key(...)`, it runs the <selectKey>
+/// query on conn and stores the first column of its row to the key property.
+/// It returns YB_NO_ROW if the query has no row.
+fn write_select_key_fn(
+    mapper_c_file: &mut File,
+    insert_m: &mapper::YoInsert,
+    select_key: &mapper::YoSelectKey,
+    params: &StmtParams,
+) -> Result<()> {
+    // the key can only be stored to a field of the parameter struct
+    let (key, (ty, field)) = match params.get(&select_key.key_property) {
+        Some(key) if key.struct_type.is_some() => (key, key.field_enum().unwrap()),
+        _ => {
+            return Err(GenCError {
+                message: format!(
+                    "{}: keyProperty {} is not a property of parameterType",
+                    insert_m.id, select_key.key_property
+                ),
+            })
+        }
+    };
 
     let line = format!(
-        "{}mysql_stmt_prepare(stmt, yb_string_data(prepare_sql), yb_string_length(prepare_sql));\n",
-        spaces(4)
+        "static int {}_select_key({}, yb_error_t* err) {{\n",
+        insert_m.id,
+        params.c_args()
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_bind_param(stmt, bind);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_execute(stmt);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_close(stmt);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    let line = format!("{}yb_string_free(prepare_sql);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}yb_string_free(cmd);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    let dynamic = write_cmd_gen_stmt(mapper_c_file, params, &select_key.content)?;
 
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    write_stmt_execute(mapper_c_file, dynamic, &[])?;
 
+    // the key is the first column of the row, assigned like a setter does
+    let get = match key.yo_type.as_str() {
+        "int64_t" => "yb_stmt_result_get_int",
+        "double" => "yb_stmt_result_get_double",
+        _ => "yb_stmt_result_get_string",
+    };
+    let line = format!(
+        "{sp}yb_stmt_result_t key = NULL;
+{sp}if (rc == YB_OK && (key = yb_stmt_result_new(stmt)) == NULL) {{
+{sp}    rc = YB_FAIL;
+{sp}}}
+{sp}if (rc == YB_OK && (rc = yb_stmt_result_fetch(key)) == YB_NO_ROW) {{
+{sp}    yb_error_set(err, \"{id}\", \"the <selectKey> query returned no row\");
+{sp}}}
+{sp}if (rc == YB_OK && {get}(key, 0, &{value}) != YB_OK) {{
+{sp}    rc = YB_FAIL;
+{sp}}}
+{sp}if (rc == YB_OK) {{
+{sp}    {ty}_set_null(n, {field}, yb_stmt_result_is_null(key, 0));
+{sp}}}
+",
+        sp = spaces(4),
+        id = insert_m.id,
+        get = get,
+        value = key.c_expr,
+        ty = ty,
+        field = field
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    write_stmt_error(mapper_c_file, &insert_m.id)?;
+    mapper_c_file
+        .write_all("    yb_stmt_result_free(key);\n".as_bytes())
+        .unwrap();
+    write_stmt_close(mapper_c_file, dynamic)?;
     mapper_c_file
-        .write("    return YB_OK;\n}\n\n".as_bytes())
+        .write_all("    return rc;\n}\n\n".as_bytes())
         .unwrap();
-    return Ok(());
+    Ok(())
 }
 
-fn write_update_fn(
+fn write_insert_fn(
     mapper_h_file: &mut File,
     mapper_c_file: &mut File,
-    update_m: &mapper::YoUpdate,
+    insert_m: &mapper::YoInsert,
     table: &mapper::Mapper,
 ) -> Result<()> {
+    let params = stmt_params(
+        table,
+        &insert_m.id,
+        &insert_m.parameter_type,
+        &insert_m.params,
+    )?;
+
     {
         // header file
         // declare insert
-        let line = format!(
-            "int {}(MYSQL* conn, {} n);\n",
-            update_m.id, update_m.parameter_type
+        let doc = if insert_m.select_key.is_some() {
+            "/// @retval YB_OK if the operation is successful.\n/// @retval YB_NO_ROW if the <selectKey> query returns no row.\n/// @retval YB_FAIL or a YB_ERR_* value if the operation is failed, see err.\n"
+        } else {
+            ""
+        };
+        let insert_fn = format!(
+            "{}int {}({}, yb_error_t* err);\n",
+            doc,
+            insert_m.id,
+            params.c_args()
         );
-        mapper_h_file.write(line.as_bytes()).unwrap();
+        mapper_h_file.write_all(insert_fn.as_bytes()).unwrap();
+    }
+
+    if let Some(select_key) = &insert_m.select_key {
+        write_select_key_fn(mapper_c_file, insert_m, select_key, &params)?;
     }
 
     // impl insert
-    //      int <insert_id>(MYSQL* conn, <parameter_type> n) {
+    //      int <insert_id>(MYSQL* conn, <parameters>, yb_error_t* err) {
     let insert_fn_line = format!(
-        "int {}(MYSQL* conn, {} n) {{\n",
-        update_m.id, update_m.parameter_type
+        "int {}({}, yb_error_t* err) {{\n",
+        insert_m.id,
+        params.c_args()
     );
-    mapper_c_file.write(insert_fn_line.as_bytes()).unwrap();
-    // statements generating
-    //          yb_string_t sql = yb_string_new();
-    let line = format!("{}yb_string_t cmd = yb_string_new();\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(insert_fn_line.as_bytes()).unwrap();
 
-    // generate cmd="UPDATE XXX FFF SET a=#{xxx}, b=#{yyy}"
-    write_sql_gen_stmt(
-        mapper_c_file,
-        4,
+    // the key the INSERT may use
+    if insert_m.select_key.as_ref().is_some_and(|k| k.before) {
+        let line = format!(
+            "{sp}int key_rc = {id}_select_key({args}, err);\n{sp}if (key_rc != YB_OK) {{\n{sp}    return key_rc;\n{sp}}}\n",
+            sp = spaces(4),
+            id = insert_m.id,
+            args = params.c_call_args()
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+
+    // generate cmd="INSERT INTO XXX(a,b,c) VALUES (#{xxx}, #{yyy}, #{xxx})"
+    let dynamic = write_cmd_gen_stmt(mapper_c_file, &params, &insert_m.content)?;
+
+    write_stmt_execute(mapper_c_file, dynamic, &[])?;
+    write_stmt_error(mapper_c_file, &insert_m.id)?;
+    write_stmt_close(mapper_c_file, dynamic)?;
+
+    // the key generated by the INSERT
+    if insert_m.select_key.as_ref().is_some_and(|k| !k.before) {
+        let line = format!(
+            "{sp}if (rc == YB_OK) {{\n{sp}    rc = {id}_select_key({args}, err);\n{sp}}}\n",
+            sp = spaces(4),
+            id = insert_m.id,
+            args = params.c_call_args()
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+
+    mapper_c_file
+        .write_all("    return rc;\n}\n\n".as_bytes())
+        .unwrap();
+    Ok(())
+}
+
+fn write_update_fn(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    update_m: &mapper::YoUpdate,
+    table: &mapper::Mapper,
+) -> Result<()> {
+    let params = stmt_params(
         table,
-        &update_m.content,
-        &String::from("cmd"),
-        &mut 0,
+        &update_m.id,
+        &update_m.parameter_type,
+        &update_m.params,
     )?;
 
-    let result_map = table.type_maps.get(&update_m.parameter_type).unwrap();
+    {
+        // header file
+        // declare update
+        let line = format!(
+            "int {}({}, yb_error_t* err);\n",
+            update_m.id,
+            params.c_args()
+        );
+        mapper_h_file.write_all(line.as_bytes()).unwrap();
+    }
 
-    let line = format!(
-        "{}MYSQL_BIND bind[{}*2];\n",
-        spaces(4),
-        result_map.results.len()
-    );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!(
-        "{}memset(bind, 0, sizeof(MYSQL_BIND)*{}*2);\n",
-        spaces(4),
-        result_map.results.len()
+    // impl update
+    //      int <update_id>(MYSQL* conn, <parameters>, yb_error_t* err) {
+    let insert_fn_line = format!(
+        "int {}({}, yb_error_t* err) {{\n",
+        update_m.id,
+        params.c_args()
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int bind_num = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(insert_fn_line.as_bytes()).unwrap();
 
-    let line = format!("{}yb_string_t prepare_sql = yb_string_new();\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int64_t pre = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    // generate cmd="UPDATE XXX FFF SET a=#{xxx}, b=#{yyy}"
+    let dynamic = write_cmd_gen_stmt(mapper_c_file, &params, &update_m.content)?;
 
-    // #{} -> ? sql prepare
-    write_cmd_to_prepare_sql(mapper_c_file, result_map, table)?;
+    write_stmt_execute(mapper_c_file, dynamic, &[])?;
+    write_stmt_error(mapper_c_file, &update_m.id)?;
+    write_stmt_close(mapper_c_file, dynamic)?;
 
-    let line = format!("{}MYSQL_STMT* stmt = mysql_stmt_init(NULL);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!(
-        "{}mysql_stmt_prepare(stmt, yb_string_data(prepare_sql), yb_string_length(prepare_sql));\n",
-        spaces(4)
+    mapper_c_file
+        .write_all("    return rc;\n}\n\n".as_bytes())
+        .unwrap();
+    Ok(())
+}
+
+/// the delete function returns the number of deleted rows, or a fail value
+fn write_delete_fn(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    delete_m: &mapper::YoDelete,
+    table: &mapper::Mapper,
+) -> Result<()> {
+    let params = stmt_params(
+        table,
+        &delete_m.id,
+        &delete_m.parameter_type,
+        &delete_m.params,
+    )?;
+
+    {
+        // header file
+        // declare delete
+        let line = format!(
+            "int64_t {}({}, yb_error_t* err);\n",
+            delete_m.id,
+            params.c_args()
+        );
+        mapper_h_file.write_all(line.as_bytes()).unwrap();
+    }
+
+    // impl delete
+    //      int64_t <delete_id>(MYSQL* conn, <parameters>, yb_error_t* err) {
+    let delete_fn_line = format!(
+        "int64_t {}({}, yb_error_t* err) {{\n",
+        delete_m.id,
+        params.c_args()
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_bind_param(stmt, bind);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_execute(stmt);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_close(stmt);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(delete_fn_line.as_bytes()).unwrap();
 
-    let line = format!("{}yb_string_free(prepare_sql);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}yb_string_free(cmd);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    // generate cmd="DELETE FROM XXX WHERE a=#{xxx}"
+    let dynamic = write_cmd_gen_stmt(mapper_c_file, &params, &delete_m.content)?;
 
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    write_stmt_execute(mapper_c_file, dynamic, &[])?;
+    write_stmt_error(mapper_c_file, &delete_m.id)?;
+    let line = format!(
+        "{sp}int64_t affected = rc == YB_OK ? (int64_t)mysql_stmt_affected_rows(stmt) : rc;\n",
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    write_stmt_close(mapper_c_file, dynamic)?;
 
     mapper_c_file
-        .write("    return YB_OK;\n}\n\n".as_bytes())
+        .write_all("    return affected;\n}\n\n".as_bytes())
         .unwrap();
-    return Ok(());
+    Ok(())
 }
 
 fn write_select_fn(
@@ -612,155 +1936,494 @@ fn write_select_fn(
     select_m: &mapper::YoSelect,
     table: &mapper::Mapper,
 ) -> Result<()> {
-    let result_out_map = table.result_maps.get(&select_m.result_map).unwrap();
+    let result_out_map = table
+        .result_maps
+        .get(&select_m.result_map)
+        .ok_or_else(|| GenCError {
+            message: format!(
+                "{}: resultMap {} not found",
+                select_m.id, select_m.result_map
+            ),
+        })?;
+
+    let params = stmt_params(
+        table,
+        &select_m.id,
+        &select_m.parameter_type,
+        &select_m.params,
+    )?;
+
+    let many = select_m.return_many.unwrap_or(false);
+    if select_m.return_many.is_none() && sql::returns_many_rows(&select_m.content, result_out_map) {
+        warn!(
+            "{}: the SELECT may return several rows but only the first one is fetched, set returnMany=\"true\" to fetch them all",
+            select_m.id
+        );
+    }
+    let out_type = if many {
+        "yb_list_t"
+    } else {
+        &result_out_map.type_
+    };
 
     {
         // header file
-        // declare insert
+        // declare select
+        let doc = if many {
+            format!(
+                "/// the rows are appended to out as {}, see {}_list_get().\n/// @retval YB_OK if the operation is successful.\n",
+                result_out_map.type_, result_out_map.type_
+            )
+        } else {
+            "/// @retval YB_OK if out is filled from the first row.\n/// @retval YB_NO_ROW if there is no row.\n".to_string()
+        };
         let line = format!(
-            "int {}(MYSQL* conn, {} n, {} out);\n",
-            select_m.id, select_m.parameter_type, result_out_map.type_
+            "{}/// @retval YB_FAIL or a YB_ERR_* value if the operation is failed, see err.\nint {}({}, {} out, yb_error_t* err);\n",
+            doc,
+            select_m.id,
+            params.c_args(),
+            out_type
         );
-        mapper_h_file.write(line.as_bytes()).unwrap();
+        mapper_h_file.write_all(line.as_bytes()).unwrap();
     }
 
-    // impl insert
-    //      int <insert_id>(MYSQL* conn, <parameter_type> n) {
-    let insert_fn_line = format!(
-        "int {}(MYSQL* conn, {} n) {{\n",
-        select_m.id, select_m.parameter_type
+    // impl select
+    //      int <select_id>(MYSQL* conn, <parameters>, <result_type> out, yb_error_t* err) {
+    let select_fn_line = format!(
+        "int {}({}, {} out, yb_error_t* err) {{\n",
+        select_m.id,
+        params.c_args(),
+        out_type
     );
-    mapper_c_file.write(insert_fn_line.as_bytes()).unwrap();
-    // statements generating
-    //          yb_string_t sql = yb_string_new();
-    let line = format!("{}yb_string_t cmd = yb_string_new();\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(select_fn_line.as_bytes()).unwrap();
 
-    // generate cmd="UPDATE XXX FFF SET a=#{xxx}, b=#{yyy}"
-    write_sql_gen_stmt(
+    // generate cmd="SELECT a, b FROM XXX WHERE c=#{xxx}"
+    let dynamic = write_cmd_gen_stmt(mapper_c_file, &params, &select_m.content)?;
+    write_stmt_execute(mapper_c_file, dynamic, &[])?;
+
+    let line = format!(
+        "{sp}yb_stmt_result_t result = rc == YB_OK ? yb_stmt_result_new(stmt) : NULL;
+{sp}if (result == NULL) {{
+{sp}    rc = YB_FAIL;
+{sp}}}
+",
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+
+    if many {
+        write_select_many_fetch(mapper_c_file, result_out_map)?;
+    } else {
+        write_select_one_fetch(mapper_c_file, result_out_map)?;
+    }
+
+    write_stmt_error(mapper_c_file, &select_m.id)?;
+    let line = format!("{}yb_stmt_result_free(result);\n", spaces(4));
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    write_stmt_close(mapper_c_file, dynamic)?;
+
+    mapper_c_file
+        .write_all("    return rc;\n}\n\n".as_bytes())
+        .unwrap();
+
+    write_select_cursor_fns(
+        mapper_h_file,
         mapper_c_file,
-        4,
-        table,
-        &select_m.content,
-        &String::from("cmd"),
-        &mut 0,
+        select_m,
+        result_out_map,
+        &params,
     )?;
+    Ok(())
+}
 
-    let result_map = table.type_maps.get(&select_m.parameter_type).unwrap();
+/// write the streaming cursor of a select:
+///
+/// ```c
+/// typedef struct <id>_cursor_s* <id>_cursor_t;
+/// int <id>_open(MYSQL* conn, <parameters>, unsigned long prefetch_rows, <id>_cursor_t* cursor, yb_error_t* err);
+/// int <id>_next(<id>_cursor_t cursor, <result_type> out, yb_error_t* err);
+/// void <id>_close(<id>_cursor_t cursor);
+/// ```
+///
+/// rows are fetched one at a time, from a server side cursor when
+/// prefetch_rows is not 0, and never stored in the client. For a resultMap
+/// with collections the first row of the next object stays in the statement
+/// until the following `_next`.
+fn write_select_cursor_fns(
+    mapper_h_file: &mut File,
+    mapper_c_file: &mut File,
+    select_m: &mapper::YoSelect,
+    result_out_map: &mapper::YoResultMap,
+    params: &StmtParams,
+) -> Result<()> {
+    let id = &select_m.id;
+    let ty = &result_out_map.type_;
 
     let line = format!(
-        "{}MYSQL_BIND bind[{}*2];\n",
-        spaces(4),
-        result_map.results.len()
+        "typedef struct {id}_cursor_s* {id}_cursor_t;
+
+/// @brief run {id} and read its rows one {ty} at a time, see {id}_next().
+/// @param prefetch_rows the rows fetched from a server side cursor at a time,
+/// 0 streams the result without a cursor and the connection can not run other
+/// statements until the cursor is closed.
+/// @param cursor set to the new cursor, closed by {id}_close().
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL or a YB_ERR_* value if the operation is failed, see err.
+int {id}_open({args}, unsigned long prefetch_rows, {id}_cursor_t* cursor, yb_error_t* err);
+/// @brief fill out with the next {ty} of the cursor.
+/// @param out a new {ty}, see {ty}_new().
+/// @retval YB_OK if out is filled.
+/// @retval YB_NO_ROW if there are no more rows.
+/// @retval YB_FAIL or a YB_ERR_* value if the operation is failed, see err.
+int {id}_next({id}_cursor_t cursor, {ty} out, yb_error_t* err);
+/// @brief free the cursor and return its statement to the statement cache.
+void {id}_close({id}_cursor_t cursor);
+",
+        id = id,
+        ty = ty,
+        args = params.c_args()
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_h_file.write_all(line.as_bytes()).unwrap();
+
+    // struct <id>_cursor_s
     let line = format!(
-        "{}memset(bind, 0, sizeof(MYSQL_BIND)*{}*2);\n",
-        spaces(4),
-        result_map.results.len()
+        "struct {id}_cursor_s {{
+{sp}MYSQL* conn;
+{sp}MYSQL_STMT* stmt;
+{sp}yb_stmt_result_t result;
+{sp}// the current row of the statement is not read yet
+{sp}int has_row;
+}};
+
+",
+        id = id,
+        sp = spaces(4)
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int bind_num = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
+    // <id>_open
     let line = format!(
-        "{}MYSQL_BIND bind_out[{}*2];\n",
-        spaces(4),
-        result_out_map.results.len()
+        "int {}_open({}, unsigned long prefetch_rows, {}_cursor_t* cursor, yb_error_t* err) {{\n",
+        id,
+        params.c_args(),
+        id
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    let dynamic = write_cmd_gen_stmt(mapper_c_file, params, &select_m.content)?;
     let line = format!(
-        "{}memset(bind, 0, sizeof(MYSQL_BIND)*{}*2);\n",
-        spaces(4),
-        result_out_map.results.len()
+        "{}unsigned long cursor_type = prefetch_rows > 0 ? CURSOR_TYPE_READ_ONLY : CURSOR_TYPE_NO_CURSOR;\n",
+        spaces(4)
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int bind_out_num = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-
-    let line = format!("{}yb_string_t prepare_sql = yb_string_new();\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}int64_t pre = 0;\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-
-    // #{} -> ? sql prepare
-    // and bind in
-    write_cmd_to_prepare_sql(mapper_c_file, result_map, table)?;
-
-    let line = format!("{}MYSQL_STMT* stmt = mysql_stmt_init(NULL);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    write_stmt_execute(
+        mapper_c_file,
+        dynamic,
+        &[
+            "mysql_stmt_attr_set(stmt, STMT_ATTR_CURSOR_TYPE, &cursor_type)",
+            "(prefetch_rows > 0 && mysql_stmt_attr_set(stmt, STMT_ATTR_PREFETCH_ROWS, &prefetch_rows))",
+        ],
+    )?;
     let line = format!(
-        "{}mysql_stmt_prepare(stmt, yb_string_data(prepare_sql), yb_string_length(prepare_sql));\n",
-        spaces(4)
+        "{sp}yb_stmt_result_t result = rc == YB_OK ? yb_stmt_result_stream(stmt) : NULL;
+{sp}if (result == NULL) {{
+{sp}    rc = YB_FAIL;
+{sp}}}
+",
+        sp = spaces(4)
     );
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}mysql_stmt_bind_param(stmt, bind);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-
-    let line = format!("{}mysql_stmt_execute(stmt);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    write_stmt_error(mapper_c_file, id)?;
+    // the cursor owns stmt from here
+    let line = format!(
+        "{sp}{id}_cursor_t c = rc == YB_OK ? malloc(sizeof(struct {id}_cursor_s)) : NULL;
+{sp}if (c != NULL) {{
+{sp}    c->conn = conn;
+{sp}    c->stmt = stmt;
+{sp}    c->result = result;
+{sp}    c->has_row = 0;
+{sp}    *cursor = c;
+{sp}    stmt = NULL;
+{sp}}} else {{
+{sp}    if (rc == YB_OK) {{
+{sp}        rc = YB_FAIL;
+{sp}        yb_error_set(err, \"{id}\", \"out of memory\");
+{sp}    }}
+{sp}    yb_stmt_result_free(result);
+{sp}}}
+",
+        id = id,
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    write_stmt_close(mapper_c_file, dynamic)?;
+    mapper_c_file
+        .write_all("    return rc;\n}\n\n".as_bytes())
+        .unwrap();
 
-    // TODO: check result
+    // <id>_next
+    let line = format!(
+        "int {id}_next({id}_cursor_t cursor, {ty} out, yb_error_t* err) {{
+{sp}int rc = cursor->has_row ? YB_OK : yb_stmt_result_fetch(cursor->result);
+{sp}cursor->has_row = 0;
+{sp}if (rc == YB_OK) {{
+{sp}    rc = {ty}_fetch(out, cursor->result, \"\");
+{sp}}}
+",
+        id = id,
+        ty = ty,
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    if !result_out_map.collections.is_empty() {
+        // merge the joined rows of out, keep the row of the next object
+        let line = format!(
+            "{sp}while (rc == YB_OK) {{
+{sp}    int fetched = yb_stmt_result_fetch(cursor->result);
+{sp}    if (fetched != YB_OK) {{
+{sp}        rc = fetched == YB_NO_ROW ? YB_OK : fetched;
+{sp}        break;
+{sp}    }}
+{sp}    {ty} row = {ty}_new();
+{sp}    if (row == NULL) {{
+{sp}        rc = YB_FAIL;
+{sp}        break;
+{sp}    }}
+{sp}    int merged = {ty}_fetch(row, cursor->result, \"\");
+{sp}    if (merged == YB_OK) {{
+{sp}        merged = {ty}_merge(out, row);
+{sp}    }}
+{sp}    if (merged != 1) {{
+{sp}        {ty}_free(row);
+{sp}        cursor->has_row = merged == 0;
+{sp}        rc = merged == 0 ? YB_OK : YB_FAIL;
+{sp}        break;
+{sp}    }}
+{sp}}}
+",
+            ty = ty,
+            sp = spaces(4)
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!(
+        "{sp}if (rc == YB_FAIL) {{\n{sp}    rc = yb_error_from_stmt(err, \"{id}\", cursor->stmt);\n{sp}}}\n{sp}return rc;\n}}\n\n",
+        sp = spaces(4),
+        id = id
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    let line = format!("{}mysql_stmt_close(stmt);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    // <id>_close
+    let line = format!(
+        "void {id}_close({id}_cursor_t cursor) {{
+{sp}if (cursor == NULL) {{
+{sp}    return;
+{sp}}}
+{sp}yb_stmt_result_free(cursor->result);
+{sp}yb_stmt_cache_release(cursor->conn, cursor->stmt);
+{sp}free(cursor);
+}}
+
+",
+        id = id,
+        sp = spaces(4)
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
+}
 
-    let line = format!("{}yb_string_free(prepare_sql);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
-    let line = format!("{}yb_string_free(cmd);\n", spaces(4));
-    mapper_c_file.write(line.as_bytes()).unwrap();
+/// fill `out` from the first row of `result`
+fn write_select_one_fetch(
+    mapper_c_file: &mut File,
+    result_out_map: &mapper::YoResultMap,
+) -> Result<()> {
+    let line = format!(
+        "{sp}if (rc == YB_OK) {{
+{sp}    rc = yb_stmt_result_fetch(result);
+{sp}}}
+{sp}if (rc == YB_OK) {{
+{sp}    rc = {ty}_fetch(out, result, \"\");
+{sp}}}
+",
+        sp = spaces(4),
+        ty = result_out_map.type_
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
 
-    mapper_c_file.write(line.as_bytes()).unwrap();
+    // the joined rows of the same object fill its collections
+    if !result_out_map.collections.is_empty() {
+        let line = format!(
+            "{sp}while (rc == YB_OK) {{
+{sp}    int fetched = yb_stmt_result_fetch(result);
+{sp}    if (fetched != YB_OK) {{
+{sp}        rc = fetched == YB_NO_ROW ? YB_OK : fetched;
+{sp}        break;
+{sp}    }}
+{sp}    {ty} row = {ty}_new();
+{sp}    if (row == NULL) {{
+{sp}        rc = YB_FAIL;
+{sp}        break;
+{sp}    }}
+{sp}    int merged = {ty}_fetch(row, result, \"\");
+{sp}    if (merged == YB_OK) {{
+{sp}        merged = {ty}_merge(out, row);
+{sp}    }}
+{sp}    if (merged != 1) {{
+{sp}        // another object, or a failure
+{sp}        {ty}_free(row);
+{sp}        rc = merged == 0 ? YB_OK : YB_FAIL;
+{sp}        break;
+{sp}    }}
+{sp}}}
+",
+            sp = spaces(4),
+            ty = result_out_map.type_
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    Ok(())
+}
 
-    mapper_c_file
-        .write("    return YB_OK;\n}\n\n".as_bytes())
-        .unwrap();
-    return Ok(());
+/// append every row of `result` to the list `out`, the joined rows of an
+/// object with collections are merged into it
+fn write_select_many_fetch(
+    mapper_c_file: &mut File,
+    result_out_map: &mapper::YoResultMap,
+) -> Result<()> {
+    let ty = &result_out_map.type_;
+    let merges = !result_out_map.collections.is_empty();
+    if merges {
+        let line = format!("{}{} last = NULL;\n", spaces(4), ty);
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!(
+        "{sp}while (rc == YB_OK) {{
+{sp}    int fetched = yb_stmt_result_fetch(result);
+{sp}    if (fetched != YB_OK) {{
+{sp}        rc = fetched == YB_NO_ROW ? YB_OK : fetched;
+{sp}        break;
+{sp}    }}
+{sp}    {ty} row = {ty}_new();
+{sp}    if (row == NULL) {{
+{sp}        rc = YB_FAIL;
+{sp}        break;
+{sp}    }}
+{sp}    if ({ty}_fetch(row, result, \"\") != YB_OK) {{
+{sp}        {ty}_free(row);
+{sp}        rc = YB_FAIL;
+{sp}        break;
+{sp}    }}
+",
+        sp = spaces(4),
+        ty = ty
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    if merges {
+        let line = format!(
+            "{sp}    int merged = last != NULL ? {ty}_merge(last, row) : 0;
+{sp}    if (merged == 1) {{
+{sp}        continue;
+{sp}    }}
+{sp}    if (merged == YB_FAIL) {{
+{sp}        {ty}_free(row);
+{sp}        rc = YB_FAIL;
+{sp}        break;
+{sp}    }}
+",
+            sp = spaces(4),
+            ty = ty
+        );
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!(
+        "{sp}    if (yb_list_push(out, row) != YB_OK) {{
+{sp}        {ty}_free(row);
+{sp}        rc = YB_FAIL;
+{sp}        break;
+{sp}    }}
+",
+        sp = spaces(4),
+        ty = ty
+    );
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    if merges {
+        let line = format!("{}last = row;\n", spaces(8));
+        mapper_c_file.write_all(line.as_bytes()).unwrap();
+    }
+    let line = format!("{}}}\n", spaces(4));
+    mapper_c_file.write_all(line.as_bytes()).unwrap();
+    Ok(())
 }
 
 fn write_result_map(
     mapper_h_file: &mut File,
     mapper_c_file: &mut File,
     result_map: &mapper::YoResultMap,
+    mapper: &mapper::Mapper,
 ) -> Result<()> {
     debug!(
         "writing result map: {}::{}",
         result_map.id, result_map.type_
     );
 
-    write_result_map_define(mapper_h_file, mapper_c_file, result_map)?;
+    write_result_map_define(mapper_h_file, mapper_c_file, result_map, mapper)?;
+    write_result_map_fields(mapper_h_file, mapper_c_file, result_map)?;
     write_result_map_new(mapper_h_file, mapper_c_file, result_map)?;
-    write_result_map_free(mapper_h_file, mapper_c_file, result_map)?;
-    return Ok(());
+    write_result_map_free(mapper_h_file, mapper_c_file, result_map, mapper)?;
+    write_result_map_push(mapper_h_file, mapper_c_file, result_map, mapper)?;
+    write_result_map_merge(mapper_h_file, mapper_c_file, result_map, mapper)?;
+    write_result_map_fetch(mapper_h_file, mapper_c_file, result_map, mapper)?;
+    write_result_map_list(mapper_h_file, mapper_c_file, result_map)?;
+    Ok(())
 }
 
 fn gen_mapper_src(path: &Path, mapper: &mapper::Mapper) -> Result<()> {
+    // a mapper holding only <sql> fragments for other mappers to include
+    // has nothing to generate
+    if mapper.result_maps.is_empty()
+        && mapper.inserts.is_empty()
+        && mapper.updates.is_empty()
+        && mapper.deletes.is_empty()
+        && mapper.selects.is_empty()
+    {
+        return Ok(());
+    }
+    // yb_common.c/h are the runtime files
+    if mapper.namespace == "common" {
+        return Err(GenCError {
+            message: "namespace common is reserved for <sql> fragments, it can not have resultMaps or statements".to_string(),
+        });
+    }
+
     let filename_c = format!("yb_{}.c", mapper.namespace);
     let filename_h = format!("yb_{}.h", mapper.namespace);
-    let mut mapper_c_file = File::create(path.join(&filename_c)).unwrap();
-    let mut mapper_h_file = File::create(path.join(&filename_h)).unwrap();
+    let dir = path.join(&mapper.dir);
+    fs::create_dir_all(&dir).unwrap();
+    let mut mapper_c_file = File::create(dir.join(&filename_c)).unwrap();
+    let mut mapper_h_file = File::create(dir.join(&filename_h)).unwrap();
 
     write_guard_start(&mut mapper_h_file, mapper)?;
-    write_includes(&mut mapper_c_file, &filename_h)?;
+    write_includes(&mut mapper_c_file, &filename_h, &mapper.dir)?;
 
-    for (_, result_map) in &mapper.result_maps {
-        write_result_map(&mut mapper_h_file, &mut mapper_c_file, &result_map)?;
+    // the structs refer to each other by the typedefs
+    for result_map in mapper.result_maps.values() {
+        write_result_map_typedef(&mut mapper_h_file, result_map)?;
     }
-
-    for (_, insert) in &mapper.inserts {
-        write_insert_fn(&mut mapper_h_file, &mut mapper_c_file, &insert, &mapper)?;
+    for result_map in mapper.result_maps.values() {
+        write_result_map(&mut mapper_h_file, &mut mapper_c_file, result_map, mapper)?;
     }
 
-    for (_, update) in &mapper.updates {
-        write_update_fn(&mut mapper_h_file, &mut mapper_c_file, &update, &mapper)?;
-    }
-
-    for (_, select) in &mapper.selects {
-        write_select_fn(&mut mapper_h_file, &mut mapper_c_file, &select, &mapper)?;
+    for statement in mapper.statements() {
+        let (h, c) = (&mut mapper_h_file, &mut mapper_c_file);
+        match statement.kind {
+            "insert" => write_insert_fn(h, c, &mapper.inserts[statement.id], mapper)?,
+            "update" => write_update_fn(h, c, &mapper.updates[statement.id], mapper)?,
+            "delete" => write_delete_fn(h, c, &mapper.deletes[statement.id], mapper)?,
+            _ => write_select_fn(h, c, &mapper.selects[statement.id], mapper)?,
+        }
     }
 
     write_guard_end(&mut mapper_h_file, mapper)?;
-    return Ok(());
+    Ok(())
 }
 
 pub fn gen_c(mappers: Vec<mapper::Mapper>, dir: &str) -> Result<()> {
@@ -770,8 +2433,1226 @@ pub fn gen_c(mappers: Vec<mapper::Mapper>, dir: &str) -> Result<()> {
     gen_common(path).unwrap();
 
     for table in mappers {
-        gen_mapper_src(path, &table).unwrap();
+        gen_mapper_src(path, &table)?;
+    }
+
+    Ok(())
+}
+
+/// generate C code in tests, and build and run it against the fake
+/// libmysqlclient of yb_common/tests
+#[cfg(test)]
+pub mod test_util {
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    use super::gen_c;
+    use crate::gen::mapper::test_util::{load, TempDir};
+
+    const FAKE_MYSQL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/yb_common/tests");
+    const CFLAGS: &[&str] = &[
+        "-Wall",
+        "-Wextra",
+        "-Werror",
+        "-Wundef",
+        "-Wvla",
+        "-pedantic",
+    ];
+
+    /// the C code of the *-mapper.xml files
+    pub fn gen(files: &[(&str, &str)]) -> TempDir {
+        let (mappers, errors) = load(files);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let out = TempDir::new(&[]);
+        gen_c(mappers, &out.input()).unwrap();
+        out
+    }
+
+    /// a generated file
+    pub fn read(out: &TempDir, name: &str) -> String {
+        fs::read_to_string(out.path.join(name)).unwrap()
+    }
+
+    /// the C compiler, None if there is none
+    fn cc() -> Option<String> {
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        match Command::new(&cc).arg("--version").output() {
+            Ok(output) if output.status.success() => Some(cc),
+            _ => None,
+        }
+    }
+
+    /// the flags of the real MySQL headers, None if mysql_config is missing
+    fn mysql_cflags() -> Option<Vec<String>> {
+        let output = Command::new("mysql_config").arg("--cflags").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let cflags = String::from_utf8_lossy(&output.stdout);
+        Some(cflags.split_whitespace().map(String::from).collect())
+    }
+
+    fn sources(dir: &Path, out: &mut Vec<String>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                sources(&path, out);
+            } else if path.extension() == Some(OsStr::new("c")) {
+                out.push(path.display().to_string());
+            }
+        }
+    }
+
+    fn compile(cc: &str, args: &[String]) {
+        let output = Command::new(cc).args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{} {}\n{}",
+            cc,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    /// build the generated code of out with main_c and run it, its stdout.
+    /// The generated code is checked against the real MySQL headers too when
+    /// mysql_config is there. None if there is no C compiler.
+    pub fn run(out: &TempDir, main_c: &str) -> Option<String> {
+        let cc = match cc() {
+            Some(cc) => cc,
+            None => {
+                eprintln!("no C compiler, the generated code is not built");
+                return None;
+            }
+        };
+        let mut srcs = Vec::new();
+        sources(&out.path, &mut srcs);
+
+        if let Some(mysql) = mysql_cflags() {
+            for src in &srcs {
+                let mut args: Vec<String> = CFLAGS.iter().map(|f| f.to_string()).collect();
+                args.extend(mysql.iter().cloned());
+                args.extend(vec!["-fsyntax-only".to_string(), src.clone()]);
+                compile(&cc, &args);
+            }
+        }
+
+        fs::write(out.path.join("main.c"), main_c).unwrap();
+        let exe = out.path.join("main").display().to_string();
+        let mut args: Vec<String> = CFLAGS.iter().map(|f| f.to_string()).collect();
+        args.extend(vec![
+            format!("-I{}", FAKE_MYSQL),
+            format!("-I{}", out.input()),
+        ]);
+        args.extend(srcs);
+        args.extend(vec![
+            out.path.join("main.c").display().to_string(),
+            format!("{}/fake_mysql.c", FAKE_MYSQL),
+            "-lpthread".to_string(),
+            "-o".to_string(),
+            exe.clone(),
+        ]);
+        compile(&cc, &args);
+
+        let output = Command::new(&exe).output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(
+            output.status.success(),
+            "{}\n{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Some(stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::gen_c;
+    use super::test_util::{gen, read, run};
+    use crate::gen::mapper::test_util::{load, mapper_xml, TempDir};
+
+    /// the user mapper of `yobatis init`, without the statements
+    const USER: &str = r#"<resultMap id="BaseResultMap" type="yb_user_t">
+    <result column="id" property="id" yo_type="int64_t" />
+    <result column="name" property="name" yo_type="yb_string_t" />
+    <result column="score" property="score" yo_type="double" />
+</resultMap>"#;
+
+    #[test]
+    fn selective_insert_update() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<insert id="user_insert_selective" parameterType="yb_user_t">
+    INSERT INTO `user`
+    <trim prefix="(" suffix=")" suffixOverrides=",">
+        <if test="dirty(id)">`id`,
+        </if><if test="dirty(name)">`name`,
+        </if><if test="dirty(score)">`score`,
+        </if>
+    </trim>
+    <trim prefix="VALUES (" suffix=")" suffixOverrides=",">
+        <if test="dirty(id)">#{id},
+        </if><if test="dirty(name)">#{name},
+        </if><if test="dirty(score)">#{score},
+        </if>
+    </trim>
+</insert>
+<update id="user_update_by_id_selective" parameterType="yb_user_t">
+    UPDATE `user`
+    <trim prefix="SET" suffixOverrides=",">
+        <if test="dirty(name)">`name` = #{name},
+        </if><if test="dirty(score)">`score` = #{score},
+        </if>
+    </trim>
+    WHERE id = #{id}
+</update>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        assert!(read(&out, "yb_t.c").contains(
+            "    if (yb_string_trim(tmp_0, \"SET\", \"\", \"\", \",\", cmd) != YB_OK) {\n\
+             \x20       goto oom;\n\
+             \x20   }\n"
+        ));
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+static void write(MYSQL* conn, yb_user_t n) {
+    yb_error_t err;
+    printf("%d\n", user_insert_selective(conn, n, &err));
+    printf("%d\n", user_update_by_id_selective(conn, n, &err));
+}
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_user_t n = yb_user_t_new();
+    write(conn, n);
+    yb_user_t_set_name(n, yb_string_from_cstr("a"));
+    write(conn, n);
+    yb_user_t_set_id(n, 1);
+    yb_user_t_set_score(n, 0.5);
+    write(conn, n);
+    yb_user_t_free(n);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "INSERT INTO `user` []\n\
+             0\n\
+             UPDATE `user` WHERE id = ? [NULL]\n\
+             0\n\
+             INSERT INTO `user` ( `name` ) VALUES ( ? ) ['a']\n\
+             0\n\
+             UPDATE `user` SET `name` = ? WHERE id = ? ['a', NULL]\n\
+             0\n\
+             INSERT INTO `user` ( `id`, `name`, `score` ) VALUES ( ?, ?, ? ) [1, 'a', 0.5]\n\
+             0\n\
+             UPDATE `user` SET `name` = ?, `score` = ? WHERE id = ? ['a', 0.5, 1]\n\
+             0\n"
+        );
+    }
+
+    #[test]
+    fn out_of_memory_is_checked() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<select id="user_find" parameterType="yb_user_t" resultMap="BaseResultMap">
+    SELECT * FROM `user`
+    <trim prefix="WHERE" prefixOverrides="AND|OR">
+        <if test="id != null">AND id = #{id}</if>
+        <choose>
+            <when test="name != null">AND name = #{name}</when>
+            <otherwise>AND score > ${score}</otherwise>
+        </choose>
+        <trim prefix="AND (" suffix=")" prefixOverrides="OR">
+            <if test="score != null">OR score = #{score}</if>
+        </trim>
+    </trim>
+    ORDER BY ${name, whitelist=id|name}
+</select>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let c = read(&out, "yb_t.c");
+        // each call that allocates is the condition of a check
+        let calls = ["yb_string_new(", "yb_string_append", "yb_string_trim("];
+        for line in c
+            .lines()
+            .filter(|l| calls.iter().any(|call| l.contains(call)))
+        {
+            let line = line.trim();
+            assert!(
+                line.starts_with("if (")
+                    || line.starts_with("} else if (")
+                    || line.ends_with("= yb_string_new();"),
+                "{}",
+                line
+            );
+        }
+        assert!(c.contains(
+            "    yb_string_t tmp_0 = NULL;\n\
+             \x20   yb_string_t tmp_1 = NULL;\n\
+             \x20   yb_string_t cmd = yb_string_new();\n\
+             \x20   if (cmd == NULL) {\n\
+             \x20       goto oom;\n\
+             \x20   }\n"
+        ));
+        assert!(c.contains(
+            "oom:\n\
+             \x20   yb_error_set(err, \"user_find\", \"out of memory\");\n\
+             \x20   rc = YB_FAIL;\n\
+             built:\n\
+             \x20   if (rc != YB_OK) {\n\
+             \x20       yb_string_free(tmp_0);\n\
+             \x20       yb_string_free(tmp_1);\n\
+             \x20       yb_string_free(cmd);\n\
+             \x20       return rc;\n\
+             \x20   }\n"
+        ));
+        // the new strings are checked on the next line
+        let lines: Vec<&str> = c.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            if let Some(var) = line.trim().strip_suffix(" = yb_string_new();") {
+                let var = var.trim_start_matches("yb_string_t ");
+                assert_eq!(lines[i + 1].trim(), format!("if ({} == NULL) {{", var));
+            }
+        }
+
+        let main_c = r#"#include "yb_t.h"
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t n = yb_user_t_new();
+    yb_user_t out = yb_user_t_new();
+    yb_user_t_set_name(n, yb_string_from_cstr("id"));
+    yb_user_t_set_score(n, 2);
+    int rc = user_find(conn, n, out, &err);
+    yb_user_t_free(n);
+    yb_user_t_free(out);
+    mysql_close(conn);
+    return rc == YB_NO_ROW ? 0 : 1;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "SELECT * FROM `user` WHERE name = ? AND ( score = ? ) ORDER BY id ['id', 2]\n"
+        );
+    }
+
+    #[test]
+    fn scalar_params_by_value() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<select id="user_select_by_id" parameterType="int64_t" resultMap="BaseResultMap">
+    SELECT * FROM `user` WHERE id = #{id}
+</select>
+<delete id="user_delete_by_score">
+    <param name="min" type="double" nullable="true"/>
+    <param name="max" type="double"/>
+    <param name="name" type="yb_string_t"/>
+    DELETE FROM `user` WHERE name = #{name}
+    <if test="min != null">AND score >= #{min}</if>
+    AND score &lt; #{max}
+</delete>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let h = read(&out, "yb_t.h");
+        assert!(h.contains(
+            "int user_select_by_id(MYSQL* conn, int64_t id, yb_user_t out, yb_error_t* err);\n"
+        ));
+        assert!(h.contains(
+            "int user_select_by_id_open(MYSQL* conn, int64_t id, unsigned long prefetch_rows, user_select_by_id_cursor_t* cursor, yb_error_t* err);\n"
+        ));
+        assert!(h.contains(
+            "int64_t user_delete_by_score(MYSQL* conn, const double* min, double max, yb_string_t name, yb_error_t* err);\n"
+        ));
+
+        let main_c = r#"#include "yb_t.h"
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t out = yb_user_t_new();
+    yb_string_t name = yb_string_from_cstr("a");
+    double min = 1.5;
+    user_select_by_id(conn, 7, out, &err);
+    user_delete_by_score(conn, NULL, 2, name, &err);
+    user_delete_by_score(conn, &min, 2, name, &err);
+    user_delete_by_score(conn, &min, 2, NULL, &err);
+    yb_string_free(name);
+    yb_user_t_free(out);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "SELECT * FROM `user` WHERE id = ? [7]\n\
+             DELETE FROM `user` WHERE name = ? AND score < ? ['a', 2]\n\
+             DELETE FROM `user` WHERE name = ? AND score >= ? AND score < ? ['a', 1.5, 2]\n\
+             DELETE FROM `user` WHERE name = ? AND score >= ? AND score < ? [NULL, 1.5, 2]\n"
+        );
+    }
+
+    #[test]
+    fn choose_chain() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<delete id="user_delete" parameterType="yb_user_t">
+    DELETE FROM `user`
+    <choose>
+        <when test="id != null">WHERE id = #{id}</when>
+        <when test="name != null">WHERE name = #{name}</when>
+        <otherwise>WHERE score &lt; 0</otherwise>
+    </choose>
+</delete>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let c = read(&out, "yb_t.c");
+        assert!(c.contains("    if (!yb_user_t_is_null(n, yb_user_t_field_id)) {\n"));
+        assert!(c.contains("    } else if (!yb_user_t_is_null(n, yb_user_t_field_name)) {\n"));
+        assert!(c.contains("    } else {\n"));
+
+        let main_c = r#"#include "yb_t.h"
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t n = yb_user_t_new();
+    user_delete(conn, n, &err);
+    yb_user_t_set_name(n, yb_string_from_cstr("a"));
+    user_delete(conn, n, &err);
+    yb_user_t_set_id(n, 1);
+    user_delete(conn, n, &err);
+    yb_user_t_free(n);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "DELETE FROM `user` WHERE score < 0 []\n\
+             DELETE FROM `user` WHERE name = ? ['a']\n\
+             DELETE FROM `user` WHERE id = ? [1]\n"
+        );
+    }
+
+    #[test]
+    fn include_across_mappers() {
+        let common = r#"<?xml version="1.0" encoding="utf-8"?>
+<mapper namespace="common">
+<sql id="by_name"><if test="name != null">AND `${col}` = #{name}</if> <include refid="tail"/></sql>
+<sql id="tail">ORDER BY ${col}</sql>
+</mapper>
+"#;
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<delete id="user_delete" parameterType="yb_user_t">
+    DELETE FROM `user` WHERE score &lt; 0
+    <include refid="common.by_name"><property name="col" value="name"/></include>
+</delete>"#
+        );
+        let out = gen(&[
+            ("common-mapper.xml", common),
+            ("user-mapper.xml", &mapper_xml(&body)),
+        ]);
+        // a mapper of fragments only has no C file
+        assert!(!out.path.join("yb_common_mapper.c").exists());
+
+        let main_c = r#"#include "yb_t.h"
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t n = yb_user_t_new();
+    user_delete(conn, n, &err);
+    yb_user_t_set_name(n, yb_string_from_cstr("a"));
+    user_delete(conn, n, &err);
+    yb_user_t_free(n);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "DELETE FROM `user` WHERE score < 0 ORDER BY name []\n\
+             DELETE FROM `user` WHERE score < 0 AND `name` = ? ORDER BY name ['a']\n"
+        );
+    }
+
+    /// the dept mapper of the <association> and <collection> tests
+    const DEPT: &str = r#"<resultMap id="DeptMap" type="yb_dept_t">
+    <id column="id" property="id" yo_type="int64_t" />
+    <result column="name" property="name" yo_type="yb_string_t" />
+    <association property="manager" resultMap="EmpMap" columnPrefix="mgr_" />
+    <collection property="emps" resultMap="EmpMap" columnPrefix="emp_" />
+</resultMap>
+<resultMap id="EmpMap" type="yb_emp_t">
+    <id column="id" property="id" yo_type="int64_t" />
+    <result column="name" property="name" yo_type="yb_string_t" />
+</resultMap>
+<select id="dept_list" resultMap="DeptMap" returnMany="true">
+    SELECT d.id, d.name, m.id AS mgr_id, m.name AS mgr_name, e.id AS emp_id, e.name AS emp_name
+    FROM dept d LEFT JOIN emp m ON m.id = d.manager_id LEFT JOIN emp e ON e.dept_id = d.id
+</select>"#;
+
+    #[test]
+    fn nested_result_maps() {
+        let out = gen(&[("dept-mapper.xml", &mapper_xml(DEPT))]);
+        let h = read(&out, "yb_t.h");
+        assert!(h.contains(
+            "    yb_emp_t manager;\n\
+             \x20   yb_emp_t* emps;\n\
+             \x20   int64_t emps_len;\n"
+        ));
+        assert!(h.contains("int yb_dept_t_emps_push(yb_dept_t n, yb_emp_t child);\n"));
+        assert!(h.contains("int dept_list(MYSQL* conn, yb_list_t out, yb_error_t* err);\n"));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+static void print_emp(const char* what, yb_emp_t e) {
+    if (e == NULL) {
+        printf("  %s NULL\n", what);
+    } else {
+        printf("  %s %lld %s\n", what, (long long)e->id, yb_string_cstr(e->name));
+    }
+}
+
+int main(void) {
+    static const char* const cells[] = {
+        "id", "name", "mgr_id", "mgr_name", "emp_id", "emp_name",
+        "1", "a", "10", "x", "10", "x",
+        "1", "a", "10", "x", "11", "y",
+        "2", "b", NULL, NULL, "20", "z",
+        "3", "c", NULL, NULL, NULL, NULL,
+    };
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_list_t depts = yb_list_new();
+    int64_t i, j;
+    fake_mysql_push_rows(6, 4, cells);
+    if (dept_list(conn, depts, &err) != YB_OK) {
+        return 1;
+    }
+    for (i = 0; i < yb_list_len(depts); i++) {
+        yb_dept_t d = yb_dept_t_list_get(depts, i);
+        printf("%lld %s\n", (long long)d->id, yb_string_cstr(d->name));
+        print_emp("manager", d->manager);
+        for (j = 0; j < d->emps_len; j++) {
+            print_emp("emp", d->emps[j]);
+        }
+    }
+    yb_dept_t_list_free(depts);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        // the rows of a dept are merged by its <id>, an all NULL nested
+        // object is left out
+        assert_eq!(
+            output,
+            "SELECT d.id, d.name, m.id AS mgr_id, m.name AS mgr_name, e.id AS emp_id, e.name AS emp_name \
+             FROM dept d LEFT JOIN emp m ON m.id = d.manager_id LEFT JOIN emp e ON e.dept_id = d.id []\n\
+             1 a\n\
+             \x20 manager 10 x\n\
+             \x20 emp 10 x\n\
+             \x20 emp 11 y\n\
+             2 b\n\
+             \x20 manager NULL\n\
+             \x20 emp 20 z\n\
+             3 c\n\
+             \x20 manager NULL\n"
+        );
     }
 
-    return Ok(());
+    #[test]
+    fn nested_result_map_not_found() {
+        let (mappers, errors) = load(&[(
+            "dept-mapper.xml",
+            &mapper_xml(
+                r#"<resultMap id="DeptMap" type="yb_dept_t">
+    <id column="id" property="id" yo_type="int64_t" />
+    <collection property="emps" resultMap="EmpMap" columnPrefix="emp_" />
+</resultMap>"#,
+            ),
+        )]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let out = TempDir::new(&[]);
+        assert_eq!(
+            gen_c(mappers, &out.input()).unwrap_err().message,
+            "resultMap EmpMap not found for property emps of resultMap DeptMap"
+        );
+    }
+
+    #[test]
+    fn extended_result_map() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<resultMap id="DetailMap" type="yb_user_detail_t" extends="BaseResultMap">
+    <result column="bio" property="bio" yo_type="yb_string_t" />
+</resultMap>
+<select id="user_detail_by_id" parameterType="int64_t" resultMap="DetailMap">
+    SELECT * FROM `user` WHERE id = #{id}
+</select>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        assert!(read(&out, "yb_t.h").contains(
+            "struct yb_user_detail_t_s {\n\
+             \x20   int64_t id;\n\
+             \x20   yb_string_t name;\n\
+             \x20   double score;\n\
+             \x20   yb_string_t bio;\n"
+        ));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+int main(void) {
+    static const char* const cells[] = {"id", "name", "score", "bio", "7", "a", "0.5", "b"};
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_detail_t out = yb_user_detail_t_new();
+    fake_mysql_push_rows(4, 1, cells);
+    if (user_detail_by_id(conn, 7, out, &err) != YB_OK) {
+        return 1;
+    }
+    printf("%lld %s %g %s\n", (long long)out->id, yb_string_cstr(out->name), out->score,
+           yb_string_cstr(out->bio));
+    yb_user_detail_t_free(out);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(output, "SELECT * FROM `user` WHERE id = ? [7]\n7 a 0.5 b\n");
+    }
+
+    #[test]
+    fn select_key() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<insert id="user_insert_seq" parameterType="yb_user_t">
+    <selectKey keyProperty="id" order="BEFORE">SELECT next_val FROM seq WHERE name = #{name}</selectKey>
+    INSERT INTO `user` (id, name) VALUES (#{id}, #{name})
+</insert>
+<insert id="user_insert_auto" parameterType="yb_user_t">
+    INSERT INTO `user` (name) VALUES (#{name})
+    <selectKey keyProperty="id">SELECT LAST_INSERT_ID()</selectKey>
+</insert>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let c = read(&out, "yb_t.c");
+        assert!(c.contains(
+            "static int user_insert_seq_select_key(MYSQL* conn, yb_user_t n, yb_error_t* err) {\n"
+        ));
+        assert!(c.contains(
+            "int user_insert_seq(MYSQL* conn, yb_user_t n, yb_error_t* err) {\n\
+             \x20   int key_rc = user_insert_seq_select_key(conn, n, err);\n\
+             \x20   if (key_rc != YB_OK) {\n\
+             \x20       return key_rc;\n\
+             \x20   }\n"
+        ));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+int main(void) {
+    static const char* const seq[] = {"next_val", "42"};
+    static const char* const last_id[] = {"LAST_INSERT_ID()", "43"};
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t n = yb_user_t_new();
+    int rc;
+    yb_user_t_set_name(n, yb_string_from_cstr("a"));
+    fake_mysql_push_rows(1, 1, seq);
+    rc = user_insert_seq(conn, n, &err);
+    printf("%d %lld\n", rc, (long long)n->id);
+    fake_mysql_push_affected(1);
+    fake_mysql_push_rows(1, 1, last_id);
+    rc = user_insert_auto(conn, n, &err);
+    printf("%d %lld\n", rc, (long long)n->id);
+    /* no row: the INSERT is not run */
+    fake_mysql_push_rows(1, 0, seq);
+    rc = user_insert_seq(conn, n, &err);
+    printf("%d %s\n", rc == YB_NO_ROW, err.message);
+    yb_user_t_free(n);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "SELECT next_val FROM seq WHERE name = ? ['a']\n\
+             INSERT INTO `user` (id, name) VALUES (?, ?) [42, 'a']\n\
+             0 42\n\
+             INSERT INTO `user` (name) VALUES (?) ['a']\n\
+             SELECT LAST_INSERT_ID() []\n\
+             0 43\n\
+             SELECT next_val FROM seq WHERE name = ? ['a']\n\
+             1 the <selectKey> query returned no row\n"
+        );
+    }
+
+    #[test]
+    fn delete_affected_rows() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<delete id="user_delete_by_id" parameterType="int64_t">
+    DELETE FROM `user` WHERE id = #{id}
+</delete>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        assert!(read(&out, "yb_t.h")
+            .contains("int64_t user_delete_by_id(MYSQL* conn, int64_t id, yb_error_t* err);\n"));
+        assert!(read(&out, "yb_t.c").contains(
+            "    int64_t affected = rc == YB_OK ? (int64_t)mysql_stmt_affected_rows(stmt) : rc;\n"
+        ));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    fake_mysql_push_affected(3);
+    printf("%lld\n", (long long)user_delete_by_id(conn, 1, &err));
+    fake_mysql_push_affected(0);
+    printf("%lld\n", (long long)user_delete_by_id(conn, 2, &err));
+    fake_mysql_push_error(1451, "23000", "a foreign key constraint fails");
+    printf("%lld\n", (long long)user_delete_by_id(conn, 3, &err));
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "DELETE FROM `user` WHERE id = ? [1]\n\
+             3\n\
+             DELETE FROM `user` WHERE id = ? [2]\n\
+             0\n\
+             DELETE FROM `user` WHERE id = ? [3]\n\
+             -3\n"
+        );
+    }
+
+    #[test]
+    fn select_fetches_the_first_row() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<select id="user_select_by_id" parameterType="int64_t" resultMap="BaseResultMap">
+    SELECT id, name, score FROM `user` WHERE id = #{id}
+</select>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let c = read(&out, "yb_t.c");
+        assert!(c.contains(
+            "int user_select_by_id(MYSQL* conn, int64_t id, yb_user_t out, yb_error_t* err) {\n"
+        ));
+        assert!(!c.contains("TODO"));
+
+        let main_c = r#"#include <stdio.h>
+#include <string.h>
+#include "yb_t.h"
+
+static void print(int rc, yb_user_t out) {
+    printf("%d", rc);
+    if (rc == YB_OK) {
+        printf(" %lld", (long long)out->id);
+        if (yb_user_t_is_null(out, yb_user_t_field_name)) {
+            printf(" NULL");
+        } else {
+            printf(" %lld:%.3s", (long long)yb_string_length(out->name), yb_string_cstr(out->name));
+        }
+        if (yb_user_t_is_null(out, yb_user_t_field_score)) {
+            printf(" NULL");
+        } else {
+            printf(" %g", out->score);
+        }
+    }
+    printf("\n");
+}
+
+int main(void) {
+    static char long_name[1001];
+    const char* cells[] = {"id", "name", "score", "1", long_name, "0.5", "2", "b", NULL};
+    const char* null_cells[] = {"id", "name", "score", "3", NULL, NULL};
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t out = yb_user_t_new();
+    int rc;
+    memset(long_name, 'x', sizeof(long_name) - 1);
+    /* a string longer than any initial buffer, only the first row is read */
+    fake_mysql_push_rows(3, 2, cells);
+    rc = user_select_by_id(conn, 1, out, &err);
+    print(rc, out);
+    fake_mysql_push_rows(3, 1, null_cells);
+    rc = user_select_by_id(conn, 3, out, &err);
+    print(rc, out);
+    fake_mysql_push_rows(3, 0, cells);
+    rc = user_select_by_id(conn, 4, out, &err);
+    print(rc, out);
+    yb_user_t_free(out);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "SELECT id, name, score FROM `user` WHERE id = ? [1]\n\
+             0 1 1000:xxx 0.5\n\
+             SELECT id, name, score FROM `user` WHERE id = ? [3]\n\
+             0 3 NULL NULL\n\
+             SELECT id, name, score FROM `user` WHERE id = ? [4]\n\
+             1\n"
+        );
+    }
+
+    #[test]
+    fn select_into_list() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<select id="user_select_all" resultMap="BaseResultMap" returnMany="true">
+    SELECT id, name, score FROM `user`
+</select>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let h = read(&out, "yb_t.h");
+        assert!(h.contains("int user_select_all(MYSQL* conn, yb_list_t out, yb_error_t* err);\n"));
+        assert!(h.contains("yb_user_t yb_user_t_list_get(yb_list_t l, int64_t i);\n"));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+static void print(void* elem, void* arg) {
+    yb_user_t n = (yb_user_t)elem;
+    printf("%s%lld %s\n", (const char*)arg, (long long)n->id, yb_string_cstr(n->name));
+}
+
+int main(void) {
+    static const char* const cells[] = {"id", "name", "score", "1", "a", "0.5", "2", "b", "1", "3", "c", NULL};
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_list_t l = yb_list_new();
+    int rc;
+    fake_mysql_push_rows(3, 3, cells);
+    rc = user_select_all(conn, l, &err);
+    printf("%d %lld\n", rc, (long long)yb_list_len(l));
+    yb_list_foreach(l, print, "- ");
+    printf("%d\n", yb_user_t_list_get(l, 3) == NULL);
+    /* the rows are appended */
+    fake_mysql_push_rows(3, 1, cells);
+    rc = user_select_all(conn, l, &err);
+    printf("%d %lld\n", rc, (long long)yb_list_len(l));
+    yb_user_t_list_free(l);
+    /* no row is not an error */
+    l = yb_list_new();
+    rc = user_select_all(conn, l, &err);
+    printf("%d %lld\n", rc, (long long)yb_list_len(l));
+    yb_user_t_list_free(l);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "SELECT id, name, score FROM `user` []\n\
+             0 3\n\
+             - 1 a\n\
+             - 2 b\n\
+             - 3 c\n\
+             1\n\
+             SELECT id, name, score FROM `user` []\n\
+             0 4\n\
+             SELECT id, name, score FROM `user` []\n\
+             0 0\n"
+        );
+    }
+
+    #[test]
+    fn cursor() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<select id="user_select_all" resultMap="BaseResultMap" returnMany="true">
+    SELECT id, name, score FROM `user` WHERE score > #{min}
+    <param name="min" type="double"/>
+</select>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let h = read(&out, "yb_t.h");
+        assert!(h.contains(
+            "int user_select_all_open(MYSQL* conn, double min, unsigned long prefetch_rows, user_select_all_cursor_t* cursor, yb_error_t* err);\n"
+        ));
+        assert!(h.contains(
+            "int user_select_all_next(user_select_all_cursor_t cursor, yb_user_t out, yb_error_t* err);\n"
+        ));
+        assert!(h.contains("void user_select_all_close(user_select_all_cursor_t cursor);\n"));
+        // the rows are read as they come, not stored
+        let c = read(&out, "yb_t.c");
+        assert!(c.contains(
+            "    unsigned long cursor_type = prefetch_rows > 0 ? CURSOR_TYPE_READ_ONLY : CURSOR_TYPE_NO_CURSOR;\n"
+        ));
+        assert!(c.contains(
+            "    yb_stmt_result_t result = rc == YB_OK ? yb_stmt_result_stream(stmt) : NULL;\n"
+        ));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+static void read_all(MYSQL* conn, unsigned long prefetch_rows, int rows) {
+    user_select_all_cursor_t cursor;
+    yb_error_t err;
+    yb_user_t out = yb_user_t_new();
+    int rc = user_select_all_open(conn, 0.5, prefetch_rows, &cursor, &err);
+    int i;
+    if (rc != YB_OK) {
+        printf("open %d\n", rc);
+        return;
+    }
+    for (i = 0; i < rows && (rc = user_select_all_next(cursor, out, &err)) == YB_OK; i++) {
+        printf("%lld %s\n", (long long)out->id, yb_string_cstr(out->name));
+    }
+    printf("%d\n", rc);
+    user_select_all_close(cursor);
+    yb_user_t_free(out);
+}
+
+int main(void) {
+    static const char* const cells[] = {"id", "name", "score", "1", "a", "0.5", "2", "b", "1", "3", "c", NULL};
+    MYSQL* conn = mysql_init(NULL);
+    fake_mysql_push_rows(3, 3, cells);
+    read_all(conn, 0, 10);
+    fake_mysql_push_rows(3, 3, cells);
+    read_all(conn, 100, 10);
+    /* closed before the last row */
+    fake_mysql_push_rows(3, 3, cells);
+    read_all(conn, 2, 1);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "SELECT id, name, score FROM `user` WHERE score > ? [0.5]\n\
+             1 a\n\
+             2 b\n\
+             3 c\n\
+             1\n\
+             SELECT id, name, score FROM `user` WHERE score > ? [0.5] (cursor, prefetch 100)\n\
+             1 a\n\
+             2 b\n\
+             3 c\n\
+             1\n\
+             SELECT id, name, score FROM `user` WHERE score > ? [0.5] (cursor, prefetch 2)\n\
+             1 a\n\
+             0\n"
+        );
+    }
+
+    #[test]
+    fn error_categories() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<insert id="user_insert" parameterType="yb_user_t">
+    INSERT INTO `user` (id, name) VALUES (#{id}, #{name})
+</insert>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+int main(void) {
+    static const unsigned int codes[] = {1062, 1586, 1216, 1217, 1451, 1452, 1213, 1205, 2006, 2013,
+                                         2055, 1406, 1048, 1364, 1264, 1146};
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t n = yb_user_t_new();
+    size_t i;
+    int rc;
+    for (i = 0; i < sizeof(codes) / sizeof(codes[0]); i++) {
+        printf("%u %d\n", codes[i], yb_error_category(codes[i]));
+    }
+    yb_user_t_set_id(n, 1);
+    fake_mysql_push_error(1062, "23000", "Duplicate entry '1' for key 'PRIMARY'");
+    rc = user_insert(conn, n, &err);
+    printf("%d %u %s %s %s\n", rc, err.code, err.sqlstate, err.stmt_id, err.message);
+    printf("%d %d %d\n", yb_error_is_duplicate_key(&err), yb_error_is_deadlock(&err),
+           yb_error_is_duplicate_key(NULL));
+    fake_mysql_push_error(1213, "40001", "Deadlock found");
+    rc = user_insert(conn, n, &err);
+    printf("%d %d %d\n", rc, yb_error_is_deadlock(&err), yb_error_is_lock_wait_timeout(&err));
+    fake_mysql_push_error(1146, "42S02", "Table 't.user' doesn't exist");
+    rc = user_insert(conn, n, &err);
+    printf("%d %u\n", rc, err.code);
+    yb_user_t_free(n);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        let insert = "INSERT INTO `user` (id, name) VALUES (?, ?) [1, NULL]\n";
+        assert_eq!(
+            output,
+            format!(
+                "1062 -2\n1586 -2\n1216 -3\n1217 -3\n1451 -3\n1452 -3\n1213 -4\n1205 -5\n\
+                 2006 -6\n2013 -6\n2055 -6\n1406 -7\n1048 -8\n1364 -8\n1264 -9\n1146 -1\n\
+                 {insert}\
+                 -2 1062 23000 user_insert Duplicate entry '1' for key 'PRIMARY'\n\
+                 1 0 0\n\
+                 {insert}\
+                 -4 1 0\n\
+                 {insert}\
+                 -1 1146\n",
+                insert = insert
+            )
+        );
+    }
+
+    #[test]
+    fn placeholders_resolved_at_generate_time() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<update id="user_rename" parameterType="yb_user_t">
+    UPDATE `user` SET name = #{name} WHERE id = #{id} OR name = #{name}
+</update>
+<delete id="user_delete" parameterType="yb_user_t">
+    DELETE FROM `user` WHERE score > #{score}
+    <if test="name != null">AND name = #{name}</if>
+    AND id &lt; #{id}
+</delete>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let c = read(&out, "yb_t.c");
+        assert!(!c.contains("#{"));
+        // a static statement is a constant with fixed binds
+        assert!(c.contains(
+            "    MYSQL_BIND bind[3];\n\
+             \x20   memset(bind, 0, sizeof(bind));\n\
+             \x20   static const char cmd[] = \"\\n    UPDATE `user` SET name = ? WHERE id = ? OR name = ?\\n\";\n"
+        ));
+        assert!(c.contains("        bind[2].buffer = (void*)yb_string_data(n->name);\n"));
+        // a dynamic one binds next to its text
+        assert!(c.contains(
+            "        if (yb_string_append_c_str(cmd, \"AND name = ?\") != YB_OK) {\n\
+             \x20           goto oom;\n\
+             \x20       }\n\
+             \x20       if (yb_user_t_is_null(n, yb_user_t_field_name)) {\n\
+             \x20           bind[bind_num].buffer_type = MYSQL_TYPE_NULL;\n"
+        ));
+
+        let main_c = r#"#include "yb_t.h"
+
+int main(void) {
+    MYSQL* conn = mysql_init(NULL);
+    yb_error_t err;
+    yb_user_t n = yb_user_t_new();
+    yb_user_t_set_id(n, 1);
+    yb_user_t_set_score(n, 0.5);
+    user_delete(conn, n, &err);
+    yb_user_t_set_name(n, yb_string_from_cstr("a"));
+    user_delete(conn, n, &err);
+    user_rename(conn, n, &err);
+    yb_user_t_free(n);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(
+            output,
+            "DELETE FROM `user` WHERE score > ? AND id < ? [0.5, 1]\n\
+             DELETE FROM `user` WHERE score > ? AND name = ? AND id < ? [0.5, 'a', 1]\n\
+             UPDATE `user` SET name = ? WHERE id = ? OR name = ? ['a', 1, 'a']\n"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholder() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<delete id="user_delete" parameterType="yb_user_t">DELETE FROM `user` WHERE age = #{age}</delete>"#
+        );
+        let (mappers, errors) = load(&[("user-mapper.xml", &mapper_xml(&body))]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let out = TempDir::new(&[]);
+        assert_eq!(
+            gen_c(mappers, &out.input()).unwrap_err().message,
+            "#{age}: unknown property, not in resultMap BaseResultMap"
+        );
+    }
+
+    #[test]
+    fn statement_cache() {
+        let body = format!(
+            "{}{}",
+            USER,
+            r#"
+<select id="user_select_by_id" parameterType="int64_t" resultMap="BaseResultMap">
+    SELECT id, name, score FROM `user` WHERE id = #{id}
+</select>
+<delete id="user_delete_by_id" parameterType="int64_t">DELETE FROM `user` WHERE id = #{id}</delete>
+<update id="user_touch" parameterType="int64_t">UPDATE `user` SET score = 0 WHERE id = #{id}</update>"#
+        );
+        let out = gen(&[("user-mapper.xml", &mapper_xml(&body))]);
+        let c = read(&out, "yb_t.c");
+        assert!(!c.contains("mysql_stmt_init("));
+        assert!(!c.contains("mysql_stmt_close("));
+
+        let main_c = r#"#include <stdio.h>
+#include "yb_t.h"
+
+static MYSQL* conn;
+
+static void prepared(const char* what) {
+    printf("%s: %lu\n", what, fake_mysql_prepared());
+}
+
+int main(void) {
+    yb_error_t err;
+    yb_user_t out = yb_user_t_new();
+    user_select_by_id_cursor_t cursor;
+    yb_stmt_cache_set_capacity(2);
+    conn = mysql_init(NULL);
+    user_delete_by_id(conn, 1, &err);
+    user_delete_by_id(conn, 1, &err);
+    prepared("reused");
+    user_touch(conn, 1, &err);
+    user_select_by_id(conn, 1, out, &err);
+    prepared("three statements");
+    user_touch(conn, 1, &err);
+    user_delete_by_id(conn, 1, &err);
+    prepared("the least recently used is evicted");
+    fake_mysql_push_error(1213, "40001", "Deadlock found");
+    user_delete_by_id(conn, 1, &err);
+    user_delete_by_id(conn, 1, &err);
+    prepared("a failed statement is closed");
+    fake_mysql_reconnect(conn);
+    user_delete_by_id(conn, 1, &err);
+    prepared("reconnected");
+    /* the cursor attributes are reset when the statement is released */
+    user_select_by_id_open(conn, 1, 100, &cursor, &err);
+    user_select_by_id_close(cursor);
+    user_select_by_id(conn, 1, out, &err);
+    prepared("cursor");
+    yb_stmt_cache_clear(conn);
+    user_select_by_id(conn, 1, out, &err);
+    prepared("cleared");
+    yb_stmt_cache_clear(conn);
+    yb_user_t_free(out);
+    mysql_close(conn);
+    return 0;
+}
+"#;
+        let output = match run(&out, main_c) {
+            Some(output) => output,
+            None => return,
+        };
+        let delete = "DELETE FROM `user` WHERE id = ? [1]\n";
+        let touch = "UPDATE `user` SET score = 0 WHERE id = ? [1]\n";
+        let select = "SELECT id, name, score FROM `user` WHERE id = ? [1]";
+        assert_eq!(
+            output,
+            format!(
+                "{d}{d}reused: 1\n\
+                 {t}{s}\n\
+                 three statements: 3\n\
+                 {t}{d}the least recently used is evicted: 4\n\
+                 {d}{d}a failed statement is closed: 5\n\
+                 {d}reconnected: 6\n\
+                 {s} (cursor, prefetch 100)\n\
+                 {s}\n\
+                 cursor: 7\n\
+                 {s}\n\
+                 cleared: 8\n",
+                d = delete,
+                t = touch,
+                s = select
+            )
+        );
+    }
 }
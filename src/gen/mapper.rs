@@ -3,57 +3,452 @@ extern crate quick_xml;
 extern crate serde;
 
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
-use log::{debug, error, info, trace, warn};
+use indexmap::IndexMap;
+use log::{debug, error, warn};
+use xml::common::Position;
 
-/// result
+/// result, <id> is a result identifying the row
 #[derive(Debug, Clone)]
 pub struct YoResult {
     pub column: String,
     pub property: String,
     pub yo_type: String,
+    pub is_id: bool,
 }
 
-/// <resultMap> - the mapper from sql query result column to C struct
+/// <association> or <collection> - a nested struct (or list of them) filled
+/// from the columns of a joined table by another resultMap. `column_prefix`
+/// is prepended to the column names of that resultMap.
+#[derive(Debug, Clone)]
+pub struct YoNestedMap {
+    pub property: String,
+    pub result_map: String,
+    pub column_prefix: String,
+}
+
+/// <resultMap> - the mapper from sql query result column to C struct.
+/// `extends` names the parent resultMap, `namespace.id` for one in another
+/// mapper; its results are flattened into this one by `resolve_extends`.
 #[derive(Debug, Clone)]
 pub struct YoResultMap {
     pub id: String,
     pub type_: String,
+    pub extends: Option<String>,
     pub results: Vec<YoResult>,
+    pub associations: Vec<YoNestedMap>,
+    pub collections: Vec<YoNestedMap>,
 }
 
-/// <include> - embed a <sql> element to a mysql statement
-#[derive(Debug)]
+impl YoResultMap {
+    /// the results joined rows are grouped by: the <id> results, or all the
+    /// results if there is no <id>
+    pub fn id_results(&self) -> Vec<&YoResult> {
+        if self.results.iter().any(|r| r.is_id) {
+            self.results.iter().filter(|r| r.is_id).collect()
+        } else {
+            self.results.iter().collect()
+        }
+    }
+}
+
+/// <include> - embed a <sql> element to a mysql statement.
+/// refid is either `id` of a <sql> in the same namespace, or
+/// `namespace.id` of a <sql> in any mapper. Each <property name value/>
+/// child replaces ${name} inside the included fragment.
+#[derive(Debug, Clone)]
 pub struct YoInclude {
     pub refid: String,
+    pub properties: Vec<(String, String)>,
+    /// line/column of the <include> element
+    pub line: u64,
+    pub column: u64,
+    /// the fragment content, filled by resolve_includes
+    pub content: Vec<SqlElement>,
+}
+
+/// ${property} - raw text substitution. The value is spliced into the sql
+/// text instead of being bound, so a string value must either match one of
+/// the whitelist entries, e.g. `${sort, whitelist=name|created_at}`, or it
+/// is escaped by mysql_real_escape_string.
+#[derive(Debug, Clone)]
+pub struct YoSubst {
+    pub property: String,
+    pub whitelist: Option<Vec<String>>,
 }
 
 /// <sql> - the sql statement, or part of it.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct YoSql {
     pub id: String,
-    pub text: String,
+    pub content: Vec<SqlElement>,
+}
+
+/// comparison operator in a test expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// the C operator
+    pub fn c_op(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+}
+
+/// AST of the test attribute of <if> and <when>, a subset of OGNL:
+/// `and`/`&&`, `or`/`||`, `not`/`!`, comparisons (`==`, `!=`, `<`, `<=`,
+/// `>`, `>=` and `eq`, `neq`, `lt`, `lte`, `gt`, `gte`), parentheses,
+/// property names and literals. `null` is the null literal, older mappers
+/// spell it `YB_INT_NULL`, `YB_FLOAT_NULL` or `YB_STRING_NULL`, which are still
+/// read as null. `dirty(property)` holds when the
+/// property was assigned since the struct was fetched or created.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestExpr {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Property(String),
+    Dirty(String),
+    Not(Box<TestExpr>),
+    And(Box<TestExpr>, Box<TestExpr>),
+    Or(Box<TestExpr>, Box<TestExpr>),
+    Compare(CompareOp, Box<TestExpr>, Box<TestExpr>),
+}
+
+impl TestExpr {
+    /// call f on every property name referenced by the expression
+    pub fn for_each_property<F: FnMut(&str)>(&self, f: &mut F) {
+        match self {
+            TestExpr::Property(name) | TestExpr::Dirty(name) => f(name),
+            TestExpr::Not(e) => e.for_each_property(f),
+            TestExpr::And(l, r) | TestExpr::Or(l, r) | TestExpr::Compare(_, l, r) => {
+                l.for_each_property(f);
+                r.for_each_property(f);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// token of a test expression
+#[derive(Debug, Clone, PartialEq)]
+enum TestToken {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Cmp(CompareOp),
+}
+
+fn tokenize_test(test: &str) -> Result<Vec<TestToken>> {
+    let err = |msg: String| ParseMapperError::new(format!("test \"{}\": {}", test, msg));
+    let chars: Vec<char> = test.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            ' ' | '\t' | '\n' | '\r' => {
+                i += 1;
+            }
+            '(' => {
+                tokens.push(TestToken::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(TestToken::RParen);
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(TestToken::And);
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(TestToken::Or);
+                i += 2;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(TestToken::Cmp(CompareOp::Eq));
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(TestToken::Cmp(CompareOp::Ne));
+                i += 2;
+            }
+            '!' => {
+                tokens.push(TestToken::Not);
+                i += 1;
+            }
+            '<' | '>' => {
+                let op = match (c, next == Some('=')) {
+                    ('<', false) => CompareOp::Lt,
+                    ('<', true) => CompareOp::Le,
+                    ('>', false) => CompareOp::Gt,
+                    _ => CompareOp::Ge,
+                };
+                tokens.push(TestToken::Cmp(op));
+                i += if next == Some('=') { 2 } else { 1 };
+            }
+            '\'' | '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(err("unterminated string literal".to_string())),
+                        Some('\\') if i + 1 < chars.len() => {
+                            text.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&ch) if ch == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            text.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(TestToken::Str(text));
+            }
+            _ if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let num: String = chars[start..i].iter().collect();
+                if num.contains('.') {
+                    let v = num
+                        .parse::<f64>()
+                        .map_err(|_| err(format!("invalid number {}", num)))?;
+                    tokens.push(TestToken::Float(v));
+                } else {
+                    let v = num
+                        .parse::<i64>()
+                        .map_err(|_| err(format!("invalid number {}", num)))?;
+                    tokens.push(TestToken::Int(v));
+                }
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let token = match word.as_str() {
+                    "and" => TestToken::And,
+                    "or" => TestToken::Or,
+                    "not" => TestToken::Not,
+                    "eq" => TestToken::Cmp(CompareOp::Eq),
+                    "neq" => TestToken::Cmp(CompareOp::Ne),
+                    "lt" => TestToken::Cmp(CompareOp::Lt),
+                    "lte" => TestToken::Cmp(CompareOp::Le),
+                    "gt" => TestToken::Cmp(CompareOp::Gt),
+                    "gte" => TestToken::Cmp(CompareOp::Ge),
+                    _ => TestToken::Ident(word),
+                };
+                tokens.push(token);
+            }
+            _ => return Err(err(format!("unexpected character '{}'", c))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// recursive descent parser over the tokens of a test expression
+struct TestParser<'a> {
+    test: &'a str,
+    tokens: Vec<TestToken>,
+    pos: usize,
+}
+
+impl<'a> TestParser<'a> {
+    fn error(&self, msg: &str) -> ParseMapperError {
+        ParseMapperError::new(format!("test \"{}\": {}", self.test, msg))
+    }
+
+    fn peek(&self) -> Option<&TestToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<TestToken> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    // or := and (("or" | "||") and)*
+    fn parse_or(&mut self) -> Result<TestExpr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&TestToken::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = TestExpr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // and := not (("and" | "&&") not)*
+    fn parse_and(&mut self) -> Result<TestExpr> {
+        let mut left = self.parse_not()?;
+        while self.peek() == Some(&TestToken::And) {
+            self.pos += 1;
+            let right = self.parse_not()?;
+            left = TestExpr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    // not := ("not" | "!") not | compare
+    fn parse_not(&mut self) -> Result<TestExpr> {
+        if self.peek() == Some(&TestToken::Not) {
+            self.pos += 1;
+            let e = self.parse_not()?;
+            return Ok(TestExpr::Not(Box::new(e)));
+        }
+        self.parse_compare()
+    }
+
+    // compare := primary (op primary)?
+    fn parse_compare(&mut self) -> Result<TestExpr> {
+        let left = self.parse_primary()?;
+        if let Some(TestToken::Cmp(op)) = self.peek() {
+            let op = *op;
+            self.pos += 1;
+            let right = self.parse_primary()?;
+            return Ok(TestExpr::Compare(op, Box::new(left), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    // primary := literal | property | "dirty" "(" property ")" | "(" or ")"
+    fn parse_primary(&mut self) -> Result<TestExpr> {
+        match self.next() {
+            Some(TestToken::LParen) => {
+                let e = self.parse_or()?;
+                if self.next() != Some(TestToken::RParen) {
+                    return Err(self.error("expected ')'"));
+                }
+                Ok(e)
+            }
+            Some(TestToken::Int(v)) => Ok(TestExpr::Int(v)),
+            Some(TestToken::Float(v)) => Ok(TestExpr::Float(v)),
+            Some(TestToken::Str(v)) => Ok(TestExpr::Str(v)),
+            Some(TestToken::Ident(word))
+                if word == "dirty" && self.peek() == Some(&TestToken::LParen) =>
+            {
+                self.pos += 1;
+                let name = match self.next() {
+                    Some(TestToken::Ident(name)) => name,
+                    _ => return Err(self.error("expected a property in dirty()")),
+                };
+                if self.next() != Some(TestToken::RParen) {
+                    return Err(self.error("expected ')'"));
+                }
+                Ok(TestExpr::Dirty(name))
+            }
+            Some(TestToken::Ident(word)) => Ok(match word.as_str() {
+                "null" | "YB_INT_NULL" | "YB_FLOAT_NULL" | "YB_STRING_NULL" => TestExpr::Null,
+                "true" => TestExpr::Bool(true),
+                "false" => TestExpr::Bool(false),
+                _ => TestExpr::Property(word),
+            }),
+            Some(token) => Err(self.error(&format!("unexpected {:?}", token))),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+}
+
+/// parse the test attribute of <if>/<when>
+pub fn parse_test_expr(test: &str) -> Result<TestExpr> {
+    let mut parser = TestParser {
+        test,
+        tokens: tokenize_test(test)?,
+        pos: 0,
+    };
+    let expr = parser.parse_or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(parser.error(&format!("unexpected {:?}", parser.tokens[parser.pos])));
+    }
+    Ok(expr)
 }
 
 /// <if> - if condition for sql statement
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct YoIf {
+    /// the test attribute as written in the mapper
     pub test: String,
-    pub content: Vec<Box<SqlElement>>,
+    pub expr: TestExpr,
+    pub content: Vec<SqlElement>,
+}
+
+/// <choose> - mutually exclusive branches, the first <when> whose test
+/// holds is used, <otherwise> if none of them does.
+/// <when> has the same shape as <if>, so the branches reuse YoIf.
+#[derive(Debug, Clone)]
+pub struct YoChoose {
+    pub whens: Vec<YoIf>,
+    pub otherwise: Option<Vec<SqlElement>>,
 }
 
 /// <trim> - trim the sql statement
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct YoTrim {
     pub prefix: String,
     pub suffix: String,
     pub suffix_overrides: String,
     pub prefix_overrides: String,
-    pub content: Vec<Box<SqlElement>>,
+    pub content: Vec<SqlElement>,
+}
+
+/// a parameter of the generated C function of a statement.
+/// Declared by <param name type nullable/> children, or implied by a scalar
+/// parameterType.
+#[derive(Debug, Clone)]
+pub struct YoParam {
+    pub name: String,
+    pub yo_type: String,
+    /// whether the value can be NULL, a number is passed by pointer then
+    pub nullable: bool,
+}
+
+/// <selectKey> - the query of a generated key, run before or after the
+/// INSERT, its result is stored to the property key_property
+#[derive(Debug)]
+pub struct YoSelectKey {
+    pub key_property: String,
+    pub before: bool,
+    pub content: Vec<SqlElement>,
 }
 
 /// <insert> - INSERT statement
@@ -61,7 +456,9 @@ pub struct YoTrim {
 pub struct YoInsert {
     pub id: String,
     pub parameter_type: String,
-    pub content: Vec<Box<SqlElement>>,
+    pub params: Vec<YoParam>,
+    pub select_key: Option<YoSelectKey>,
+    pub content: Vec<SqlElement>,
 }
 
 /// <update> - UPDATE statement
@@ -69,7 +466,8 @@ pub struct YoInsert {
 pub struct YoUpdate {
     pub id: String,
     pub parameter_type: String,
-    pub content: Vec<Box<SqlElement>>,
+    pub params: Vec<YoParam>,
+    pub content: Vec<SqlElement>,
 }
 
 /// <select> - SELECT statement
@@ -77,8 +475,12 @@ pub struct YoUpdate {
 pub struct YoSelect {
     pub id: String,
     pub parameter_type: String,
+    pub params: Vec<YoParam>,
     pub result_map: String,
-    pub content: Vec<Box<SqlElement>>,
+    /// returnMany, whether all the rows are fetched into a yb_list_t. When
+    /// it is not given, only the first row is fetched.
+    pub return_many: Option<bool>,
+    pub content: Vec<SqlElement>,
 }
 
 /// <delete> - DELETE statement
@@ -86,78 +488,420 @@ pub struct YoSelect {
 pub struct YoDelete {
     pub id: String,
     pub parameter_type: String,
-    pub content: Vec<Box<SqlElement>>,
+    pub params: Vec<YoParam>,
+    pub content: Vec<SqlElement>,
 }
 
 /// option for sql statement elements
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SqlElement {
     YoInclude(YoInclude),
     YoText(String),
+    YoSubst(YoSubst),
     YoTrim(YoTrim),
     YoIf(YoIf),
+    Choose(YoChoose),
 }
 
-/// <mapper> - the root of an xml file
+/// <mapper> - the root of an xml file. The elements are kept in document
+/// order, so the generated code does not change between runs.
 pub struct Mapper {
+    pub file: String,
+    /// the dir of file relative to its input dir, the generated files go to
+    /// the same dir under the output dir
+    pub dir: PathBuf,
     pub namespace: String,
-    pub result_maps: HashMap<String, YoResultMap>,
-    pub type_maps: HashMap<String, YoResultMap>,
-    pub sqls: HashMap<String, YoSql>,
-    pub inserts: HashMap<String, YoInsert>,
-    pub updates: HashMap<String, YoUpdate>,
-    pub deletes: HashMap<String, YoDelete>,
-    pub selects: HashMap<String, YoSelect>,
+    pub result_maps: IndexMap<String, YoResultMap>,
+    pub type_maps: IndexMap<String, YoResultMap>,
+    pub sqls: IndexMap<String, YoSql>,
+    pub inserts: IndexMap<String, YoInsert>,
+    pub updates: IndexMap<String, YoUpdate>,
+    pub deletes: IndexMap<String, YoDelete>,
+    pub selects: IndexMap<String, YoSelect>,
+    /// line/column of the top level elements by (kind, id), kind is
+    /// "statement" for <insert>, <update>, <delete> and <select>
+    pub locations: HashMap<(String, String), (u64, u64)>,
+    /// the problems found while parsing that did not stop it
+    pub warnings: Vec<ParseMapperError>,
+}
+
+/// a statement of any kind, for the passes that treat them alike
+pub struct Statement<'a> {
+    /// insert, update, delete or select
+    pub kind: &'static str,
+    pub id: &'a str,
+    pub parameter_type: &'a str,
+    pub params: &'a [YoParam],
+    pub result_map: Option<&'a str>,
+    pub content: &'a [SqlElement],
 }
 
 impl Mapper {
     pub fn new() -> Mapper {
-        return Mapper {
+        Mapper {
+            file: String::new(),
+            dir: PathBuf::new(),
             namespace: String::new(),
-            result_maps: HashMap::new(),
-            type_maps: HashMap::new(),
-            sqls: HashMap::new(),
-            inserts: HashMap::new(),
-            updates: HashMap::new(),
-            deletes: HashMap::new(),
-            selects: HashMap::new(),
-        };
+            result_maps: IndexMap::new(),
+            type_maps: IndexMap::new(),
+            sqls: IndexMap::new(),
+            inserts: IndexMap::new(),
+            updates: IndexMap::new(),
+            deletes: IndexMap::new(),
+            selects: IndexMap::new(),
+            locations: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// all the statements, in document order
+    pub fn statements(&self) -> Vec<Statement<'_>> {
+        let mut statements = Vec::new();
+        for s in self.inserts.values() {
+            statements.push(Statement {
+                kind: "insert",
+                id: &s.id,
+                parameter_type: &s.parameter_type,
+                params: &s.params,
+                result_map: None,
+                content: &s.content,
+            });
+        }
+        for s in self.updates.values() {
+            statements.push(Statement {
+                kind: "update",
+                id: &s.id,
+                parameter_type: &s.parameter_type,
+                params: &s.params,
+                result_map: None,
+                content: &s.content,
+            });
+        }
+        for s in self.deletes.values() {
+            statements.push(Statement {
+                kind: "delete",
+                id: &s.id,
+                parameter_type: &s.parameter_type,
+                params: &s.params,
+                result_map: None,
+                content: &s.content,
+            });
+        }
+        for s in self.selects.values() {
+            statements.push(Statement {
+                kind: "select",
+                id: &s.id,
+                parameter_type: &s.parameter_type,
+                params: &s.params,
+                result_map: Some(&s.result_map),
+                content: &s.content,
+            });
+        }
+        statements.sort_by_key(|s| {
+            self.locations
+                .get(&("statement".to_string(), s.id.to_string()))
+                .cloned()
+        });
+        statements
     }
 }
 
+/// a mapper error, located by file, line/column (1-based, 0 if unknown) and
+/// element when they are known
 #[derive(Debug, Clone)]
 pub struct ParseMapperError {
-    message: String,
+    pub message: String,
+    pub file: String,
+    pub line: u64,
+    pub column: u64,
+    pub element: String,
+    /// the diagnostic code `check` reports it with, if it is None "parse"
+    /// for an error and "unknown-element" for a warning
+    pub code: Option<&'static str>,
+}
+
+impl ParseMapperError {
+    fn new(message: String) -> ParseMapperError {
+        ParseMapperError {
+            message,
+            file: String::new(),
+            line: 0,
+            column: 0,
+            element: String::new(),
+            code: None,
+        }
+    }
 }
 
 impl fmt::Display for ParseMapperError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+            if self.line > 0 {
+                write!(f, "{}:{}:", self.line, self.column)?;
+            }
+            write!(f, " ")?;
+        }
+        if !self.element.is_empty() {
+            write!(f, "<{}>: ", self.element)?;
+        }
         write!(f, "{}", self.message)
     }
 }
 
+/// a parsed mapper file, with the line/column of each of its elements
+struct Source {
+    path: String,
+    positions: HashMap<*const minidom::Element, (u64, u64)>,
+    warnings: RefCell<Vec<ParseMapperError>>,
+}
+
+impl Source {
+    /// parse the xml of a mapper file. minidom does not keep positions, so
+    /// the document is read again with xml-rs, whose start elements come in
+    /// the same order as a pre-order walk of the minidom tree.
+    fn parse(path: &Path) -> Result<(Source, minidom::Element)> {
+        let mut source = Source {
+            path: path.display().to_string(),
+            positions: HashMap::new(),
+            warnings: RefCell::new(Vec::new()),
+        };
+        let contents = fs::read_to_string(path)
+            .map_err(|e| source.locate_file(ParseMapperError::new(e.to_string())))?;
+
+        let mut starts = Vec::new();
+        let mut reader = xml::reader::EventReader::new(contents.as_bytes());
+        loop {
+            match reader.next() {
+                Ok(xml::reader::XmlEvent::StartElement { .. }) => {
+                    let pos = reader.position();
+                    starts.push((pos.row + 1, pos.column + 1));
+                }
+                Ok(xml::reader::XmlEvent::EndDocument) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    let mut err = source.locate_file(ParseMapperError::new(e.msg().to_string()));
+                    err.line = e.position().row + 1;
+                    err.column = e.position().column + 1;
+                    return Err(err);
+                }
+            }
+        }
+
+        let root: minidom::Element = contents.parse().map_err(|e: minidom::Error| {
+            source.locate_file(ParseMapperError::new(e.to_string()))
+        })?;
+        let mut stack = vec![&root];
+        let mut starts = starts.into_iter();
+        while let Some(node) = stack.pop() {
+            if let Some(pos) = starts.next() {
+                source
+                    .positions
+                    .insert(node as *const minidom::Element, pos);
+            }
+            let children: Vec<&minidom::Element> = node.children().collect();
+            stack.extend(children.into_iter().rev());
+        }
+        Ok((source, root))
+    }
+
+    fn locate_file(&self, mut err: ParseMapperError) -> ParseMapperError {
+        if err.file.is_empty() {
+            err.file = self.path.clone();
+        }
+        err
+    }
+
+    /// set the location of an error raised while parsing node, unless an
+    /// inner element already did
+    fn locate(&self, node: &minidom::Element, mut err: ParseMapperError) -> ParseMapperError {
+        if err.element.is_empty() {
+            err.element = node.name().to_string();
+            let (line, column) = self.position(node);
+            err.line = line;
+            err.column = column;
+        }
+        self.locate_file(err)
+    }
+
+    fn error(&self, node: &minidom::Element, message: String) -> ParseMapperError {
+        self.locate(node, ParseMapperError::new(message))
+    }
+
+    /// log a problem that does not stop the parsing, `check` reports it
+    fn warn(&self, node: &minidom::Element, message: String) {
+        let warning = self.error(node, message);
+        error!("{}", warning);
+        self.warnings.borrow_mut().push(warning);
+    }
+
+    fn position(&self, node: &minidom::Element) -> (u64, u64) {
+        match self.positions.get(&(node as *const minidom::Element)) {
+            Some(position) => *position,
+            None => (0, 0),
+        }
+    }
+
+    /// a required attribute
+    fn attr<'a>(&self, node: &'a minidom::Element, name: &str) -> Result<&'a str> {
+        node.attr(name)
+            .ok_or_else(|| self.error(node, format!("missing attribute {}", name)))
+    }
+}
+
 pub type Result<T> = std::result::Result<T, ParseMapperError>;
 
-/// parse <include>, <if>, <trim>
-fn parse_sql_elements(node: &minidom::Element) -> Result<Vec<Box<SqlElement>>> {
+/// parse the content of ${...}: a property name and optional options
+fn parse_subst(inner: &str) -> Result<YoSubst> {
+    let mut parts = inner.split(',').map(|p| p.trim());
+    let property = parts.next().unwrap_or("");
+    if property.is_empty()
+        || !property
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(ParseMapperError::new(format!(
+            "invalid property in ${{{}}}",
+            inner
+        )));
+    }
+
+    let mut whitelist = None;
+    for option in parts {
+        match option.split_once('=') {
+            Some((key, values)) if key.trim() == "whitelist" => {
+                let values: Vec<String> = values
+                    .split('|')
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+                    .collect();
+                if values.is_empty() {
+                    return Err(ParseMapperError::new(format!(
+                        "empty whitelist in ${{{}}}",
+                        inner
+                    )));
+                }
+                whitelist = Some(values);
+            }
+            _ => {
+                return Err(ParseMapperError::new(format!(
+                    "unknown option \"{}\" in ${{{}}}",
+                    option, inner
+                )));
+            }
+        }
+    }
+
+    Ok(YoSubst {
+        property: property.to_string(),
+        whitelist,
+    })
+}
+
+/// split a text node into plain text and ${...} substitutions
+fn parse_text(text: &str) -> Result<Vec<SqlElement>> {
+    let mut elements = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => {
+                return Err(ParseMapperError::new(format!(
+                    "unterminated ${{ in \"{}\"",
+                    text
+                )));
+            }
+        };
+        if start > 0 {
+            elements.push(SqlElement::YoText(rest[..start].to_string()));
+        }
+        elements.push(SqlElement::YoSubst(parse_subst(&rest[start + 2..end])?));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        elements.push(SqlElement::YoText(rest.to_string()));
+    }
+
+    Ok(elements)
+}
+
+/// parse <choose>, with its <when> and <otherwise> children
+fn parse_choose(src: &Source, node: &minidom::Element) -> Result<YoChoose> {
+    let mut whens = Vec::new();
+    let mut otherwise = None;
+
+    for child in node.children() {
+        match child.name() {
+            "when" => {
+                let test = src.attr(child, "test")?;
+                let contents = parse_sql_elements(src, child)?;
+                whens.push(YoIf {
+                    test: test.to_string(),
+                    expr: parse_test_expr(test).map_err(|e| src.locate(child, e))?,
+                    content: contents,
+                });
+            }
+            "otherwise" => {
+                if otherwise.is_some() {
+                    return Err(src.error(node, "more than one <otherwise>".to_string()));
+                }
+                otherwise = Some(parse_sql_elements(src, child)?);
+            }
+            _ => {
+                src.warn(child, "unknown element in <choose>".to_string());
+            }
+        }
+    }
+
+    if whens.is_empty() {
+        return Err(src.error(node, "requires at least one <when>".to_string()));
+    }
+
+    Ok(YoChoose { whens, otherwise })
+}
+
+/// parse <include>, <if>, <choose>, <trim>
+fn parse_sql_elements(src: &Source, node: &minidom::Element) -> Result<Vec<SqlElement>> {
     let mut elements = Vec::new();
 
     for child in node.nodes() {
         match child {
             minidom::Node::Element(element) => match element.name() {
                 "include" => {
-                    let refid = element.attr("refid").unwrap();
-                    elements.push(Box::new(SqlElement::YoInclude(YoInclude {
+                    let refid = src.attr(element, "refid")?;
+                    let mut properties = Vec::new();
+                    for property in element.children().filter(|c| c.name() == "property") {
+                        let name = src.attr(property, "name")?;
+                        let value = src.attr(property, "value")?;
+                        properties.push((name.to_string(), value.to_string()));
+                    }
+                    let (line, column) = src.position(element);
+                    elements.push(SqlElement::YoInclude(YoInclude {
                         refid: refid.to_string(),
-                    })));
+                        properties,
+                        line,
+                        column,
+                        content: Vec::new(),
+                    }));
                 }
                 "if" => {
-                    let test = element.attr("test").unwrap();
-                    let contents = parse_sql_elements(element)?;
-                    elements.push(Box::new(SqlElement::YoIf(YoIf {
+                    let test = src.attr(element, "test")?;
+                    let contents = parse_sql_elements(src, element)?;
+                    elements.push(SqlElement::YoIf(YoIf {
                         test: test.to_string(),
+                        expr: parse_test_expr(test).map_err(|e| src.locate(element, e))?,
                         content: contents,
-                    })));
+                    }));
+                }
+                "param" => {
+                    // statement parameters, see parse_params
+                }
+                "selectKey" => {
+                    // the key query of <insert>, see parse_insert
+                }
+                "choose" => {
+                    elements.push(SqlElement::Choose(parse_choose(src, element)?));
                 }
                 "trim" => {
                     let prefix = match element.attr("prefix") {
@@ -176,190 +920,1566 @@ fn parse_sql_elements(node: &minidom::Element) -> Result<Vec<Box<SqlElement>>> {
                         Some(prefix_overrides) => prefix_overrides.to_string(),
                         None => String::new(),
                     };
-                    let contents = parse_sql_elements(element)?;
-                    elements.push(Box::new(SqlElement::YoTrim(YoTrim {
+                    let contents = parse_sql_elements(src, element)?;
+                    elements.push(SqlElement::YoTrim(YoTrim {
                         prefix: prefix.to_string(),
                         suffix: suffix.to_string(),
                         suffix_overrides: suffix_overrides.to_string(),
                         prefix_overrides: prefix_overrides.to_string(),
                         content: contents,
-                    })));
+                    }));
                 }
                 _ => {
-                    error!("unkown sql element: {:?}", element);
+                    src.warn(element, "unknown sql element".to_string());
                 }
             },
             minidom::Node::Text(text) => {
-                elements.push(Box::new(SqlElement::YoText(text.to_string())));
+                elements.extend(parse_text(text).map_err(|e| src.locate(node, e))?);
             }
 
             _ => {}
         }
     }
 
-    return Ok(elements);
+    Ok(elements)
 }
 
 // parse <sql>
-fn parse_sql(node: &minidom::Element) -> Result<YoSql> {
-    let id = node.attr("id").unwrap();
-    let contents = parse_sql_elements(node)?;
-    let text = contents
-        .iter()
-        .map(|e| match &**e {
-            SqlElement::YoText(text) => text.to_string(),
-            _ => String::new(),
-        })
-        .collect::<Vec<String>>()
-        .join("");
+fn parse_sql(src: &Source, node: &minidom::Element) -> Result<YoSql> {
+    let id = src.attr(node, "id")?;
+    let contents = parse_sql_elements(src, node)?;
     Ok(YoSql {
         id: id.to_string(),
-        text: text,
+        content: contents,
     })
 }
 
 /// parse <resultMap>
-fn parse_result_map(node: &minidom::Element) -> Result<YoResultMap> {
-    let id = node.attr("id").unwrap();
-    let type_ = node.attr("type").unwrap();
+fn parse_result_map(src: &Source, node: &minidom::Element) -> Result<YoResultMap> {
+    let id = src.attr(node, "id")?;
+    let type_ = src.attr(node, "type")?;
     let mut results = Vec::new();
+    let mut associations = Vec::new();
+    let mut collections = Vec::new();
     for child in node.children() {
-        if child.name() == "result" {
-            let column = child.attr("column").unwrap();
-            let property = child.attr("property").unwrap();
-            let yo_type = child.attr("yo_type").unwrap();
-            results.push(YoResult {
-                column: column.to_string(),
-                property: property.to_string(),
-                yo_type: yo_type.to_string(),
-            });
+        match child.name() {
+            "id" | "result" => {
+                let column = src.attr(child, "column")?;
+                let property = src.attr(child, "property")?;
+                let yo_type = src.attr(child, "yo_type")?;
+                results.push(YoResult {
+                    column: column.to_string(),
+                    property: property.to_string(),
+                    yo_type: yo_type.to_string(),
+                    is_id: child.name() == "id",
+                });
+            }
+            "association" | "collection" => {
+                let nested = YoNestedMap {
+                    property: src.attr(child, "property")?.to_string(),
+                    result_map: src.attr(child, "resultMap")?.to_string(),
+                    column_prefix: child.attr("columnPrefix").unwrap_or("").to_string(),
+                };
+                if child.name() == "association" {
+                    associations.push(nested);
+                } else {
+                    collections.push(nested);
+                }
+            }
+            _ => {
+                src.warn(child, format!("unknown element in resultMap {}", id));
+            }
         }
     }
     Ok(YoResultMap {
         id: id.to_string(),
         type_: type_.to_string(),
-        results: results,
+        extends: node.attr("extends").map(|e| e.to_string()),
+        results,
+        associations,
+        collections,
     })
 }
 
+/// whether yo_type is a scalar C type rather than the type of a resultMap
+pub fn is_scalar_type(yo_type: &str) -> bool {
+    matches!(yo_type, "int64_t" | "double" | "yb_string_t")
+}
+
+/// the text with `?` for each #{}, and the #{} properties in order. The
+/// options after a comma, e.g. `#{name,jdbcType=VARCHAR}`, are ignored.
+pub fn split_placeholders(text: &str) -> (String, Vec<&str>) {
+    let mut sql = String::new();
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("#{") {
+        sql.push_str(&rest[..start]);
+        sql.push('?');
+        let inner = &rest[start + 2..];
+        let end = inner.find('}').unwrap_or(inner.len());
+        names.push(inner[..end].split(',').next().unwrap_or("").trim());
+        rest = if end < inner.len() {
+            &inner[end + 1..]
+        } else {
+            ""
+        };
+    }
+    sql.push_str(rest);
+    (sql, names)
+}
+
+/// collect the distinct property names referenced by #{}, ${} and tests
+pub fn collect_properties(elements: &[SqlElement], names: &mut Vec<String>) {
+    fn add(names: &mut Vec<String>, name: &str) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    for elem in elements {
+        match elem {
+            SqlElement::YoText(text) => {
+                for name in split_placeholders(text).1 {
+                    add(names, name);
+                }
+            }
+            SqlElement::YoSubst(subst) => add(names, &subst.property),
+            SqlElement::YoIf(if_elem) => {
+                if_elem.expr.for_each_property(&mut |n| add(names, n));
+                collect_properties(&if_elem.content, names);
+            }
+            SqlElement::Choose(choose) => {
+                for when in &choose.whens {
+                    when.expr.for_each_property(&mut |n| add(names, n));
+                    collect_properties(&when.content, names);
+                }
+                if let Some(otherwise) = &choose.otherwise {
+                    collect_properties(otherwise, names);
+                }
+            }
+            SqlElement::YoTrim(trim) => collect_properties(&trim.content, names),
+            SqlElement::YoInclude(inc) => collect_properties(&inc.content, names),
+        }
+    }
+}
+
+/// parse the parameters of a statement: <param name type/> children, or a
+/// single scalar parameterType. The scalar parameter is named by
+/// parameterName, or else by the only property the statement references,
+/// or else `value`.
+fn parse_params(
+    src: &Source,
+    node: &minidom::Element,
+    parameter_type: &str,
+    contents: &[SqlElement],
+) -> Result<Vec<YoParam>> {
+    let mut params = Vec::new();
+    for child in node.children().filter(|c| c.name() == "param") {
+        let name = src.attr(child, "name")?;
+        let yo_type = src.attr(child, "type")?;
+        if !is_scalar_type(yo_type) {
+            return Err(src.error(child, format!("{}: unsupported type {}", name, yo_type)));
+        }
+        if params.iter().any(|p: &YoParam| p.name == name) {
+            return Err(src.error(child, format!("duplicate parameter {}", name)));
+        }
+        let nullable = match child.attr("nullable") {
+            Some("true") => true,
+            Some("false") | None => false,
+            Some(value) => {
+                return Err(src.error(
+                    child,
+                    format!("nullable must be true or false, not {}", value),
+                ))
+            }
+        };
+        params.push(YoParam {
+            name: name.to_string(),
+            yo_type: yo_type.to_string(),
+            nullable,
+        });
+    }
+
+    if !params.is_empty() {
+        if !parameter_type.is_empty() {
+            return Err(src.error(
+                node,
+                "parameterType and <param> can not be used together".to_string(),
+            ));
+        }
+        return Ok(params);
+    }
+
+    if is_scalar_type(parameter_type) {
+        let name = match node.attr("parameterName") {
+            Some(name) => name.to_string(),
+            None => {
+                let mut names = Vec::new();
+                collect_properties(contents, &mut names);
+                if names.len() == 1 {
+                    names.remove(0)
+                } else {
+                    "value".to_string()
+                }
+            }
+        };
+        params.push(YoParam {
+            name,
+            yo_type: parameter_type.to_string(),
+            nullable: false,
+        });
+    }
+
+    Ok(params)
+}
+
 /// parse <insert>
-fn parse_insert(node: &minidom::Element) -> Result<YoInsert> {
-    let id = node.attr("id").unwrap();
-    let parameter_type = node.attr("parameterType").unwrap();
-    let contents = parse_sql_elements(node)?;
+fn parse_insert(src: &Source, node: &minidom::Element) -> Result<YoInsert> {
+    let id = src.attr(node, "id")?;
+    let parameter_type = node.attr("parameterType").unwrap_or("");
+    let contents = parse_sql_elements(src, node)?;
+    let params = parse_params(src, node, parameter_type, &contents)?;
+
+    let mut select_key = None;
+    for child in node.children().filter(|c| c.name() == "selectKey") {
+        if select_key.is_some() {
+            return Err(src.error(child, "more than one <selectKey>".to_string()));
+        }
+        let key_property = src.attr(child, "keyProperty")?;
+        // AFTER is the default of MyBatis
+        let before = match child.attr("order").unwrap_or("AFTER") {
+            "BEFORE" => true,
+            "AFTER" => false,
+            order => {
+                return Err(src.error(
+                    child,
+                    format!("order must be BEFORE or AFTER, not {}", order),
+                ))
+            }
+        };
+        select_key = Some(YoSelectKey {
+            key_property: key_property.to_string(),
+            before,
+            content: parse_sql_elements(src, child)?,
+        });
+    }
+
     Ok(YoInsert {
         id: id.to_string(),
         parameter_type: parameter_type.to_string(),
+        params,
+        select_key,
         content: contents,
     })
 }
 
 /// parse <update>
-fn parse_update(node: &minidom::Element) -> Result<YoUpdate> {
-    let id = node.attr("id").unwrap();
-    let parameter_type = node.attr("parameterType").unwrap();
-    let contents = parse_sql_elements(node)?;
+fn parse_update(src: &Source, node: &minidom::Element) -> Result<YoUpdate> {
+    let id = src.attr(node, "id")?;
+    let parameter_type = node.attr("parameterType").unwrap_or("");
+    let contents = parse_sql_elements(src, node)?;
+    let params = parse_params(src, node, parameter_type, &contents)?;
     Ok(YoUpdate {
         id: id.to_string(),
         parameter_type: parameter_type.to_string(),
+        params,
         content: contents,
     })
 }
 
 /// parse <delete>
-fn parse_delete(node: &minidom::Element) -> Result<YoDelete> {
-    let id = node.attr("id").unwrap();
-    let parameter_type = node.attr("parameterType").unwrap();
-    let contents = parse_sql_elements(node)?;
+fn parse_delete(src: &Source, node: &minidom::Element) -> Result<YoDelete> {
+    let id = src.attr(node, "id")?;
+    let parameter_type = node.attr("parameterType").unwrap_or("");
+    let contents = parse_sql_elements(src, node)?;
+    let params = parse_params(src, node, parameter_type, &contents)?;
     Ok(YoDelete {
         id: id.to_string(),
         parameter_type: parameter_type.to_string(),
+        params,
         content: contents,
     })
 }
 
 /// parse <select>
-fn parse_select(node: &minidom::Element) -> Result<YoSelect> {
-    let id = node.attr("id").unwrap();
-    let parameter_type = node.attr("parameterType").unwrap();
-    let result_map = node.attr("resultMap").unwrap();
-    let contents = parse_sql_elements(node)?;
+fn parse_select(src: &Source, node: &minidom::Element) -> Result<YoSelect> {
+    let id = src.attr(node, "id")?;
+    let parameter_type = node.attr("parameterType").unwrap_or("");
+    let result_map = src.attr(node, "resultMap")?;
+    let return_many = match node.attr("returnMany") {
+        Some("true") => Some(true),
+        Some("false") => Some(false),
+        Some(value) => {
+            return Err(src.error(
+                node,
+                format!("returnMany must be true or false, not {}", value),
+            ))
+        }
+        None => None,
+    };
+    let contents = parse_sql_elements(src, node)?;
+    let params = parse_params(src, node, parameter_type, &contents)?;
     Ok(YoSelect {
         id: id.to_string(),
         parameter_type: parameter_type.to_string(),
+        params,
         result_map: result_map.to_string(),
+        return_many,
         content: contents,
     })
 }
 
-/// parse one mapper file
-fn parse_mapper_file(path: &Path) -> Result<Mapper> {
+/// parse one mapper file. An element with errors is skipped and the errors
+/// are added to errors, so that those of all the elements are reported.
+/// None if the file can not be read as xml. Of the elements with the same
+/// id, the one whose databaseId is database_id is used, falling back to the
+/// one without databaseId; those for other databases are skipped.
+fn parse_mapper_file(
+    path: &Path,
+    database_id: &str,
+    errors: &mut Vec<ParseMapperError>,
+) -> Option<Mapper> {
     let mut mapper = Mapper::new();
-    let contents = std::fs::read_to_string(path).unwrap();
     debug!("file: {:?}", path.to_str());
-    let root: minidom::Element = contents.parse().unwrap();
+    let (src, root) = match Source::parse(path) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            errors.push(e);
+            return None;
+        }
+    };
 
+    mapper.file = src.path.clone();
     mapper.namespace = match root.attr("namespace") {
         Some(namespace) => namespace.to_string(),
         None => String::new(),
     };
-    root.children().for_each(|child| match child.name() {
-        "resultMap" => {
-            let result_map = parse_result_map(&child).unwrap();
-            debug!("result_map: {:?}", result_map);
-            mapper
-                .result_maps
-                .insert(result_map.id.clone(), result_map.clone());
+    // the statements are all C functions, so they share the ids
+    let key = |child: &minidom::Element, id: &str| {
+        let kind = match child.name() {
+            "insert" | "update" | "delete" | "select" => "statement",
+            name => name,
+        };
+        (kind.to_string(), id.to_string())
+    };
+    let vendor_specific: HashSet<(String, String)> = root
+        .children()
+        .filter(|child| child.attr("databaseId") == Some(database_id))
+        .filter_map(|child| child.attr("id").map(|id| key(child, id)))
+        .collect();
+
+    for child in root.children() {
+        if let Some(id) = child.attr("id") {
+            let key = key(child, id);
+            match child.attr("databaseId") {
+                Some(d) if d != database_id => continue,
+                None if vendor_specific.contains(&key) => continue,
+                _ => {}
+            }
+            if mapper.locations.contains_key(&key) {
+                errors.push(src.error(child, format!("duplicate {} id {}", key.0, id)));
+                continue;
+            }
+            mapper.locations.insert(key, src.position(child));
+        }
+        let parsed = match child.name() {
+            "resultMap" => parse_result_map(&src, child).map(|result_map| {
+                debug!("result_map: {:?}", result_map);
+                mapper
+                    .result_maps
+                    .insert(result_map.id.clone(), result_map.clone());
+                mapper
+                    .type_maps
+                    .insert(result_map.type_.clone(), result_map);
+            }),
+            "sql" => parse_sql(&src, child).map(|sql| {
+                debug!("sql_elements: {:?}", sql);
+                mapper.sqls.insert(sql.id.clone(), sql);
+            }),
+            "insert" => parse_insert(&src, child).map(|insert| {
+                debug!("insert: {:?}", insert);
+                mapper.inserts.insert(insert.id.clone(), insert);
+            }),
+            "update" => parse_update(&src, child).map(|update| {
+                debug!("update: {:?}", update);
+                mapper.updates.insert(update.id.clone(), update);
+            }),
+            "delete" => parse_delete(&src, child).map(|delete| {
+                debug!("delete: {:?}", delete);
+                mapper.deletes.insert(delete.id.clone(), delete);
+            }),
+            "select" => parse_select(&src, child).map(|select| {
+                debug!("select: {:?}", select);
+                mapper.selects.insert(select.id.clone(), select);
+            }),
+            _ => {
+                src.warn(child, "unknown element in <mapper>".to_string());
+                Ok(())
+            }
+        };
+        if let Err(e) = parsed {
+            errors.push(src.locate(child, e));
+        }
+    }
+
+    mapper.warnings = src.warnings.into_inner();
+    Some(mapper)
+}
+
+/// <sql> fragments of all mappers, by namespace and id, with the file they
+/// are in
+type Fragments = HashMap<String, HashMap<String, (String, Vec<SqlElement>)>>;
+
+/// replace ${name} in text with include properties
+fn substitute_properties(text: &str, properties: &HashMap<String, String>) -> String {
+    let mut text = text.to_string();
+    for (name, value) in properties {
+        text = text.replace(&format!("${{{}}}", name), value);
+    }
+    text
+}
+
+/// fill the content of the <include>s in elements. namespace is the one of
+/// the mapper the elements are written in, properties are those of the
+/// enclosing <include>s, stack holds the fragments being expanded.
+fn expand_includes(
+    elements: &mut [SqlElement],
+    namespace: &str,
+    properties: &HashMap<String, String>,
+    fragments: &Fragments,
+    stack: &mut Vec<String>,
+) -> Result<()> {
+    for elem in elements.iter_mut() {
+        match elem {
+            SqlElement::YoInclude(inc) => {
+                let refid = substitute_properties(&inc.refid, properties);
+                let (ns, id) = match refid.rfind('.') {
+                    Some(pos) => (&refid[..pos], &refid[pos + 1..]),
+                    None => (namespace, refid.as_str()),
+                };
+                let at_include = |message| ParseMapperError {
+                    line: inc.line,
+                    column: inc.column,
+                    element: "include".to_string(),
+                    ..ParseMapperError::new(message)
+                };
+                let (file, fragment) = match fragments.get(ns).and_then(|sqls| sqls.get(id)) {
+                    Some(fragment) => fragment,
+                    None => {
+                        return Err(ParseMapperError {
+                            code: Some("unknown-include"),
+                            ..at_include(format!("{}: include sql not found: {}", namespace, refid))
+                        });
+                    }
+                };
+
+                let qualified = format!("{}.{}", ns, id);
+                if stack.contains(&qualified) {
+                    return Err(at_include(format!(
+                        "include cycle: {} -> {}",
+                        stack.join(" -> "),
+                        qualified
+                    )));
+                }
+
+                // nested includes see the outer properties too
+                let mut inner_properties = properties.clone();
+                for (name, value) in &inc.properties {
+                    inner_properties.insert(name.clone(), substitute_properties(value, properties));
+                }
+
+                let mut content = fragment.clone();
+                stack.push(qualified);
+                expand_includes(&mut content, ns, &inner_properties, fragments, stack).map_err(
+                    |mut e| {
+                        // raised inside the fragment, which may be in another file
+                        if e.file.is_empty() {
+                            e.file = file.clone();
+                        }
+                        e
+                    },
+                )?;
+                stack.pop();
+                inc.content = content;
+            }
+            SqlElement::YoSubst(subst) => {
+                if let Some(value) = properties.get(&subst.property) {
+                    *elem = SqlElement::YoText(value.clone());
+                }
+            }
+            SqlElement::YoIf(if_elem) => {
+                expand_includes(
+                    &mut if_elem.content,
+                    namespace,
+                    properties,
+                    fragments,
+                    stack,
+                )?;
+            }
+            SqlElement::Choose(choose) => {
+                for when in choose.whens.iter_mut() {
+                    expand_includes(&mut when.content, namespace, properties, fragments, stack)?;
+                }
+                if let Some(otherwise) = choose.otherwise.as_mut() {
+                    expand_includes(otherwise, namespace, properties, fragments, stack)?;
+                }
+            }
+            SqlElement::YoTrim(trim) => {
+                expand_includes(&mut trim.content, namespace, properties, fragments, stack)?;
+            }
+            SqlElement::YoText(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// resolve every <include> of every statement against the <sql> fragments
+/// of all mappers.
+pub fn resolve_includes(mappers: &mut [Mapper]) -> std::result::Result<(), Vec<ParseMapperError>> {
+    let mut fragments: Fragments = HashMap::new();
+    for mapper in mappers.iter() {
+        let sqls = fragments.entry(mapper.namespace.clone()).or_default();
+        for (id, sql) in &mapper.sqls {
+            sqls.insert(id.clone(), (mapper.file.clone(), sql.content.clone()));
+        }
+    }
+
+    let no_properties = HashMap::new();
+    let mut errors = Vec::new();
+    for mapper in mappers.iter_mut() {
+        let namespace = mapper.namespace.clone();
+        let file = mapper.file.clone();
+        let contents = mapper
+            .inserts
+            .values_mut()
+            .flat_map(|s| {
+                let select_key = s.select_key.as_mut().map(|k| &mut k.content);
+                std::iter::once(&mut s.content).chain(select_key)
+            })
+            .chain(mapper.updates.values_mut().map(|s| &mut s.content))
+            .chain(mapper.deletes.values_mut().map(|s| &mut s.content))
+            .chain(mapper.selects.values_mut().map(|s| &mut s.content));
+        for content in contents {
+            if let Err(mut e) = expand_includes(
+                content,
+                &namespace,
+                &no_properties,
+                &fragments,
+                &mut Vec::new(),
+            ) {
+                if e.file.is_empty() {
+                    e.file = file.clone();
+                }
+                errors.push(e);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// the resultMap `qualified` (namespace.id) with the results of the maps it
+/// extends flattened in: the parent's results come first, those the child
+/// maps again by property are overridden.
+fn flatten_result_map(
+    qualified: &str,
+    result_maps: &HashMap<String, YoResultMap>,
+    stack: &mut Vec<String>,
+) -> Result<YoResultMap> {
+    let result_map = &result_maps[qualified];
+    let extends = match &result_map.extends {
+        Some(extends) => extends,
+        None => return Ok(result_map.clone()),
+    };
+    let namespace = &qualified[..qualified.rfind('.').unwrap()];
+    let parent_qualified = if extends.contains('.') {
+        extends.clone()
+    } else {
+        format!("{}.{}", namespace, extends)
+    };
+    if !result_maps.contains_key(&parent_qualified) {
+        return Err(ParseMapperError::new(format!(
+            "{}: extended resultMap not found: {}",
+            qualified, extends
+        )));
+    }
+    if stack.contains(&parent_qualified) {
+        return Err(ParseMapperError::new(format!(
+            "resultMap extends cycle: {} -> {}",
+            stack.join(" -> "),
+            parent_qualified
+        )));
+    }
+
+    stack.push(parent_qualified.clone());
+    let parent = flatten_result_map(&parent_qualified, result_maps, stack)?;
+    stack.pop();
+
+    // nested resultMaps are looked up in the mapper of the child
+    let parent_namespace = &parent_qualified[..parent_qualified.rfind('.').unwrap()];
+    if parent_namespace != namespace
+        && !(parent.associations.is_empty() && parent.collections.is_empty())
+    {
+        return Err(ParseMapperError::new(format!(
+            "{}: can not extend {} from another namespace, it has associations or collections",
+            qualified, parent_qualified
+        )));
+    }
+
+    let mut flattened = result_map.clone();
+    flattened.results = parent
+        .results
+        .into_iter()
+        .filter(|p| !result_map.results.iter().any(|r| r.property == p.property))
+        .chain(result_map.results.iter().cloned())
+        .collect();
+    flattened.associations = parent
+        .associations
+        .into_iter()
+        .filter(|p| {
+            !result_map
+                .associations
+                .iter()
+                .any(|a| a.property == p.property)
+        })
+        .chain(result_map.associations.iter().cloned())
+        .collect();
+    flattened.collections = parent
+        .collections
+        .into_iter()
+        .filter(|p| {
+            !result_map
+                .collections
+                .iter()
+                .any(|c| c.property == p.property)
+        })
+        .chain(result_map.collections.iter().cloned())
+        .collect();
+    Ok(flattened)
+}
+
+/// flatten the parent results into every resultMap with `extends`, parents
+/// may be in any of the mappers.
+pub fn resolve_extends(mappers: &mut [Mapper]) -> std::result::Result<(), Vec<ParseMapperError>> {
+    let mut result_maps = HashMap::new();
+    for mapper in mappers.iter() {
+        for (id, result_map) in &mapper.result_maps {
+            result_maps.insert(format!("{}.{}", mapper.namespace, id), result_map.clone());
+        }
+    }
+
+    let mut errors = Vec::new();
+    for mapper in mappers.iter_mut() {
+        for (id, result_map) in mapper.result_maps.iter_mut() {
+            if result_map.extends.is_none() {
+                continue;
+            }
+            let qualified = format!("{}.{}", mapper.namespace, id);
+            match flatten_result_map(&qualified, &result_maps, &mut vec![qualified.clone()]) {
+                Ok(flattened) => *result_map = flattened,
+                Err(mut e) => {
+                    e.file = mapper.file.clone();
+                    errors.push(e);
+                }
+            }
+        }
+        for result_map in mapper.result_maps.values() {
             mapper
                 .type_maps
                 .insert(result_map.type_.clone(), result_map.clone());
         }
-        "sql" => {
-            let sql = parse_sql(&child).unwrap();
-            debug!("sql_elements: {:?}", sql);
-            mapper.sqls.insert(sql.id.clone(), sql);
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// the mapper files to load: the files under the input dirs, at any depth,
+/// that match one of the include patterns and none of the exclude ones. The
+/// glob patterns are matched against the path relative to the input dir.
+pub struct MapperFiles {
+    pub inputs: Vec<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl MapperFiles {
+    fn patterns(patterns: &[String]) -> Result<Vec<glob::Pattern>> {
+        patterns
+            .iter()
+            .map(|p| {
+                glob::Pattern::new(p).map_err(|e| {
+                    ParseMapperError::new(format!("invalid glob pattern {}: {}", p, e))
+                })
+            })
+            .collect()
+    }
+
+    /// (input dir, file) of the matching files, sorted within an input dir
+    fn find(&self) -> std::result::Result<Vec<(String, PathBuf)>, Vec<ParseMapperError>> {
+        let (include, exclude) = match (
+            MapperFiles::patterns(&self.include),
+            MapperFiles::patterns(&self.exclude),
+        ) {
+            (Ok(include), Ok(exclude)) => (include, exclude),
+            (Err(e), _) | (_, Err(e)) => return Err(vec![e]),
+        };
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..glob::MatchOptions::new()
+        };
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        for input in &self.inputs {
+            let mut paths = Vec::new();
+            walk_dir(Path::new(input), &mut paths, &mut errors);
+            paths.sort();
+            for path in paths {
+                let relative = path.strip_prefix(input).unwrap_or(&path);
+                if include
+                    .iter()
+                    .any(|p| p.matches_path_with(relative, options))
+                    && !exclude
+                        .iter()
+                        .any(|p| p.matches_path_with(relative, options))
+                {
+                    files.push((input.clone(), path));
+                }
+            }
         }
-        "insert" => {
-            let insert = parse_insert(&child).unwrap();
-            debug!("insert: {:?}", insert);
-            mapper.inserts.insert(insert.id.clone(), insert);
+        if errors.is_empty() {
+            Ok(files)
+        } else {
+            Err(errors)
         }
-        "update" => {
-            let update = parse_update(&child).unwrap();
-            debug!("update: {:?}", update);
-            mapper.updates.insert(update.id.clone(), update);
+    }
+}
+
+/// all the files under dir, symlinked dirs are not followed
+fn walk_dir(dir: &Path, paths: &mut Vec<PathBuf>, errors: &mut Vec<ParseMapperError>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            errors.push(ParseMapperError {
+                file: dir.display().to_string(),
+                ..ParseMapperError::new(e.to_string())
+            });
+            return;
         }
-        "delete" => {
-            let delete = parse_delete(&child).unwrap();
-            debug!("delete: {:?}", delete);
-            mapper.deletes.insert(delete.id.clone(), delete);
+    };
+    for entry in entries.flatten() {
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk_dir(&entry.path(), paths, errors),
+            Ok(_) => paths.push(entry.path()),
+            Err(_) => continue,
         }
-        "select" => {
-            let select = parse_select(&child).unwrap();
-            debug!("select: {:?}", select);
-            mapper.selects.insert(select.id.clone(), select);
+    }
+}
+
+/// the ${} of elements, with the <include> content
+fn collect_substs<'a>(elements: &'a [SqlElement], substs: &mut Vec<&'a YoSubst>) {
+    for elem in elements {
+        match elem {
+            SqlElement::YoText(_) => {}
+            SqlElement::YoSubst(subst) => substs.push(subst),
+            SqlElement::YoIf(if_elem) => collect_substs(&if_elem.content, substs),
+            SqlElement::Choose(choose) => {
+                for when in &choose.whens {
+                    collect_substs(&when.content, substs);
+                }
+                if let Some(otherwise) = &choose.otherwise {
+                    collect_substs(otherwise, substs);
+                }
+            }
+            SqlElement::YoTrim(trim) => collect_substs(&trim.content, substs),
+            SqlElement::YoInclude(inc) => collect_substs(&inc.content, substs),
         }
-        _ => {}
-    });
+    }
+}
 
-    return Ok(mapper);
+/// warn of the ${} of a string property without a whitelist, its value is
+/// only escaped. Once for each property of a statement, at the statement.
+fn warn_unchecked_substs(mapper: &mut Mapper) {
+    let mut warnings = Vec::new();
+    for statement in mapper.statements() {
+        let mut substs = Vec::new();
+        collect_substs(statement.content, &mut substs);
+        let mut warned: Vec<&str> = Vec::new();
+        for subst in substs.iter().filter(|s| s.whitelist.is_none()) {
+            let name = subst.property.as_str();
+            let yo_type = match statement.params.iter().find(|p| p.name == name) {
+                Some(param) => Some(param.yo_type.as_str()),
+                None => mapper
+                    .type_maps
+                    .get(statement.parameter_type)
+                    .and_then(|m| m.results.iter().find(|r| r.property == name))
+                    .map(|r| r.yo_type.as_str()),
+            };
+            if yo_type != Some("yb_string_t") || warned.contains(&name) {
+                continue;
+            }
+            warned.push(name);
+            let (line, column) = mapper
+                .locations
+                .get(&("statement".to_string(), statement.id.to_string()))
+                .cloned()
+                .unwrap_or((0, 0));
+            let warning = ParseMapperError {
+                message: format!(
+                    "{}: ${{{}}} substitutes a string without a whitelist, the value is only escaped",
+                    statement.id, name
+                ),
+                file: mapper.file.clone(),
+                line,
+                column,
+                element: statement.kind.to_string(),
+                code: Some("subst-without-whitelist"),
+            };
+            warn!("{}", warning);
+            warnings.push(warning);
+        }
+    }
+    mapper.warnings.extend(warnings);
 }
 
-/// Parse all mapper files under folder dir and return a Mapper struct.
-pub fn parse_mappers(dir: &str) -> Result<Vec<Mapper>> {
+/// Parse all the mapper files, with the statements and fragments for the
+/// database database_id. The mappers are returned along with all the errors
+/// found, those with errors are incomplete.
+pub fn load_mappers(
+    files: &MapperFiles,
+    database_id: &str,
+) -> (Vec<Mapper>, Vec<ParseMapperError>) {
     let mut mapper_list = Vec::new();
+    let mut errors = Vec::new();
 
-    let entries = fs::read_dir(dir).unwrap();
-
-    for entry in entries {
-        let entry = entry.unwrap();
-        let path = entry.path();
-        let file_name = path.file_name().unwrap().to_str().unwrap();
-        if file_name.ends_with("-mapper.xml") {
-            let mapper = parse_mapper_file(&path)?;
+    let paths = match files.find() {
+        Ok(paths) => paths,
+        Err(e) => return (mapper_list, e),
+    };
+    for (input, path) in paths {
+        if let Some(mut mapper) = parse_mapper_file(&path, database_id, &mut errors) {
+            mapper.dir = path
+                .parent()
+                .and_then(|dir| dir.strip_prefix(&input).ok())
+                .map(|dir| dir.to_path_buf())
+                .unwrap_or_default();
             mapper_list.push(mapper);
         }
     }
 
-    return Ok(mapper_list);
+    if let Err(e) = resolve_includes(&mut mapper_list) {
+        errors.extend(e);
+    }
+    // before the inherited statements are added, to warn once for each
+    for mapper in &mut mapper_list {
+        warn_unchecked_substs(mapper);
+    }
+    if let Err(e) = resolve_extends(&mut mapper_list) {
+        errors.extend(e);
+    }
+
+    (mapper_list, errors)
+}
+
+/// Parse all the mapper files and return the Mapper structs.
+/// All the errors found are returned together.
+pub fn parse_mappers(
+    files: &MapperFiles,
+    database_id: &str,
+) -> std::result::Result<Vec<Mapper>, Vec<ParseMapperError>> {
+    let (mapper_list, errors) = load_mappers(files, database_id);
+    if errors.is_empty() {
+        Ok(mapper_list)
+    } else {
+        Err(errors)
+    }
+}
+
+/// mapper files written to a temporary dir, for the tests
+#[cfg(test)]
+pub mod test_util {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{load_mappers, Mapper, MapperFiles, ParseMapperError};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    /// a dir under the system temp dir, removed when dropped
+    pub struct TempDir {
+        pub path: PathBuf,
+    }
+
+    impl TempDir {
+        /// a new dir with the (relative path, contents) files
+        pub fn new(files: &[(&str, &str)]) -> TempDir {
+            let path = std::env::temp_dir().join(format!(
+                "yobatis-test-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::SeqCst)
+            ));
+            for (name, contents) in files {
+                let file = path.join(name);
+                fs::create_dir_all(file.parent().unwrap()).unwrap();
+                fs::write(file, contents).unwrap();
+            }
+            fs::create_dir_all(&path).unwrap();
+            TempDir { path }
+        }
+
+        pub fn input(&self) -> String {
+            self.path.display().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// a mapper file with the body inside <mapper namespace="t">
+    pub fn mapper_xml(body: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<mapper namespace=\"t\">\n{}\n</mapper>\n",
+            body
+        )
+    }
+
+    /// load the mappers of the *-mapper.xml files, as `yobatis gen` does by
+    /// default
+    pub fn load(files: &[(&str, &str)]) -> (Vec<Mapper>, Vec<ParseMapperError>) {
+        let dir = TempDir::new(files);
+        load_mappers(
+            &MapperFiles {
+                inputs: vec![dir.input()],
+                include: vec!["**/*-mapper.xml".to_string()],
+                exclude: Vec::new(),
+            },
+            "mysql",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::{load, mapper_xml, TempDir};
+    use super::*;
+
+    fn property(name: &str) -> Box<TestExpr> {
+        Box::new(TestExpr::Property(name.to_string()))
+    }
+
+    #[test]
+    fn test_expr_precedence() {
+        // and binds tighter than or, not tighter than and
+        assert_eq!(
+            parse_test_expr("a == 1 or b != null and not c").unwrap(),
+            TestExpr::Or(
+                Box::new(TestExpr::Compare(
+                    CompareOp::Eq,
+                    property("a"),
+                    Box::new(TestExpr::Int(1))
+                )),
+                Box::new(TestExpr::And(
+                    Box::new(TestExpr::Compare(
+                        CompareOp::Ne,
+                        property("b"),
+                        Box::new(TestExpr::Null)
+                    )),
+                    Box::new(TestExpr::Not(property("c"))),
+                )),
+            )
+        );
+        assert_eq!(
+            parse_test_expr("!(a || b) && c").unwrap(),
+            TestExpr::And(
+                Box::new(TestExpr::Not(Box::new(TestExpr::Or(
+                    property("a"),
+                    property("b")
+                )))),
+                property("c"),
+            )
+        );
+        assert_eq!(
+            parse_test_expr("a and b and c").unwrap(),
+            TestExpr::And(
+                Box::new(TestExpr::And(property("a"), property("b"))),
+                property("c")
+            )
+        );
+        assert!(parse_test_expr("a and").is_err());
+        assert!(parse_test_expr("(a or b").is_err());
+    }
+
+    #[test]
+    fn subst_whitelist() {
+        let subst = parse_subst("sort, whitelist=name | created_at").unwrap();
+        assert_eq!(subst.property, "sort");
+        assert_eq!(
+            subst.whitelist,
+            Some(vec!["name".to_string(), "created_at".to_string()])
+        );
+        assert_eq!(parse_subst("sort").unwrap().whitelist, None);
+        assert!(parse_subst("sort, whitelist=").is_err());
+        assert!(parse_subst("sort, order=name").is_err());
+        assert!(parse_subst("").is_err());
+    }
+
+    #[test]
+    fn placeholder_inside_subst() {
+        assert!(parse_text("ORDER BY ${#{sort}}").is_err());
+        assert!(parse_text("ORDER BY ${sort").is_err());
+    }
+
+    #[test]
+    fn find_include_exclude() {
+        let dir = TempDir::new(&[
+            ("a-mapper.xml", ""),
+            ("b.xml", ""),
+            ("sub/c-mapper.xml", ""),
+            ("sub/skip-mapper.xml", ""),
+        ]);
+        let find = |include: &[&str], exclude: &[&str]| -> Vec<String> {
+            let files = MapperFiles {
+                inputs: vec![dir.input()],
+                include: include.iter().map(|p| p.to_string()).collect(),
+                exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            };
+            files
+                .find()
+                .unwrap()
+                .iter()
+                .map(|(_, path)| {
+                    let relative = path.strip_prefix(&dir.path).unwrap();
+                    relative.to_string_lossy().replace('\\', "/")
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            find(&["**/*-mapper.xml"], &[]),
+            vec!["a-mapper.xml", "sub/c-mapper.xml", "sub/skip-mapper.xml"]
+        );
+        // * does not match across dirs
+        assert_eq!(find(&["*-mapper.xml"], &[]), vec!["a-mapper.xml"]);
+        assert_eq!(
+            find(&["**/*-mapper.xml"], &["sub/skip-*"]),
+            vec!["a-mapper.xml", "sub/c-mapper.xml"]
+        );
+        assert_eq!(
+            find(&["*.xml", "sub/c-*"], &[]),
+            vec!["a-mapper.xml", "b.xml", "sub/c-mapper.xml"]
+        );
+        assert!(MapperFiles {
+            inputs: vec![dir.input()],
+            include: vec!["[".to_string()],
+            exclude: Vec::new(),
+        }
+        .find()
+        .is_err());
+    }
+
+    #[test]
+    fn param_nullable() {
+        let (mappers, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<delete id="d">
+    <param name="a" type="int64_t" nullable="true"/>
+    <param name="b" type="int64_t"/>
+    DELETE FROM t WHERE a = #{a} AND b = #{b}
+</delete>
+<delete id="by_id" parameterType="int64_t">DELETE FROM t WHERE id = #{id}</delete>"#,
+            ),
+        )]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let nullable = |id: &str| {
+            mappers[0].deletes[id]
+                .params
+                .iter()
+                .map(|p| p.nullable)
+                .collect::<Vec<bool>>()
+        };
+        assert_eq!(nullable("d"), vec![true, false]);
+        assert_eq!(nullable("by_id"), vec![false]);
+
+        let (_, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<delete id="d"><param name="a" type="int64_t" nullable="yes"/>DELETE FROM t WHERE a = #{a}</delete>"#,
+            ),
+        )]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "nullable must be true or false, not yes");
+        assert_eq!(errors[0].line, 3);
+    }
+
+    #[test]
+    fn choose_whens_and_otherwise() {
+        let (mappers, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<delete id="d" parameterType="int64_t">DELETE FROM t <choose>
+    <when test="id == 1">WHERE a</when>
+    <when test="id == 2">WHERE b</when>
+    <otherwise>WHERE c</otherwise>
+</choose></delete>"#,
+            ),
+        )]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        match &mappers[0].deletes["d"].content[1] {
+            SqlElement::Choose(choose) => {
+                let tests: Vec<&str> = choose.whens.iter().map(|w| w.test.as_str()).collect();
+                assert_eq!(tests, vec!["id == 1", "id == 2"]);
+                assert!(choose.otherwise.is_some());
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let errors = |choose: &str| {
+            let body = format!("<delete id=\"d\">DELETE FROM t\n{}</delete>", choose);
+            let (_, errors) = load(&[("t-mapper.xml", &mapper_xml(&body))]);
+            errors
+                .iter()
+                .map(|e| (e.line, e.element.clone(), e.message.clone()))
+                .collect::<Vec<(u64, String, String)>>()
+        };
+        assert_eq!(
+            errors("<choose><otherwise>a</otherwise></choose>"),
+            vec![(
+                4,
+                "choose".to_string(),
+                "requires at least one <when>".to_string()
+            )]
+        );
+        assert_eq!(
+            errors("<choose><when test=\"a\">a</when><otherwise>b</otherwise><otherwise>c</otherwise></choose>"),
+            vec![(4, "choose".to_string(), "more than one <otherwise>".to_string())]
+        );
+        assert_eq!(
+            errors("<choose>\n<when>a</when></choose>"),
+            vec![(5, "when".to_string(), "missing attribute test".to_string())]
+        );
+    }
+
+    #[test]
+    fn include_cycle() {
+        let (_, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<sql id="a">a <include refid="b"/></sql>
+<sql id="b">b <include refid="t.a"/></sql>
+<delete id="d">DELETE FROM t WHERE
+    <include refid="a"/></delete>"#,
+            ),
+        )]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        let e = &errors[0];
+        // at the include that closes the cycle
+        assert_eq!(e.message, "include cycle: t.a -> t.b -> t.a");
+        assert_eq!((e.line, e.column, e.element.as_str()), (4, 15, "include"));
+        assert!(e.file.ends_with("t-mapper.xml"), "{}", e.file);
+    }
+
+    #[test]
+    fn nested_result_maps() {
+        let (mappers, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<resultMap id="DeptMap" type="yb_dept_t">
+    <id column="id" property="id" yo_type="int64_t"/>
+    <result column="name" property="name" yo_type="yb_string_t"/>
+    <association property="manager" resultMap="EmpMap" columnPrefix="mgr_"/>
+    <collection property="emps" resultMap="EmpMap"/>
+</resultMap>"#,
+            ),
+        )]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let dept = &mappers[0].result_maps["DeptMap"];
+        let ids: Vec<(&str, bool)> = dept
+            .results
+            .iter()
+            .map(|r| (r.property.as_str(), r.is_id))
+            .collect();
+        assert_eq!(ids, vec![("id", true), ("name", false)]);
+        let nested = |maps: &[YoNestedMap]| -> Vec<(String, String, String)> {
+            maps.iter()
+                .map(|m| {
+                    (
+                        m.property.clone(),
+                        m.result_map.clone(),
+                        m.column_prefix.clone(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            nested(&dept.associations),
+            vec![(
+                "manager".to_string(),
+                "EmpMap".to_string(),
+                "mgr_".to_string()
+            )]
+        );
+        assert_eq!(
+            nested(&dept.collections),
+            vec![("emps".to_string(), "EmpMap".to_string(), String::new())]
+        );
+
+        let (_, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<resultMap id="DeptMap" type="yb_dept_t">
+    <collection property="emps"/>
+</resultMap>"#,
+            ),
+        )]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].message, "missing attribute resultMap");
+        assert_eq!(
+            (errors[0].line, errors[0].element.as_str()),
+            (4, "collection")
+        );
+    }
+
+    #[test]
+    fn extends_across_mappers() {
+        let user = r#"<?xml version="1.0" encoding="utf-8"?>
+<mapper namespace="user">
+<resultMap id="BaseResultMap" type="yb_user_t">
+    <id column="id" property="id" yo_type="int64_t"/>
+    <result column="name" property="name" yo_type="yb_string_t"/>
+    <result column="score" property="score" yo_type="double"/>
+</resultMap>
+</mapper>
+"#;
+        let (mappers, errors) = load(&[
+            ("user-mapper.xml", user),
+            (
+                "t-mapper.xml",
+                &mapper_xml(
+                    r#"<resultMap id="DetailMap" type="yb_user_detail_t" extends="user.BaseResultMap">
+    <result column="bio" property="bio" yo_type="yb_string_t"/>
+    <result column="score" property="score" yo_type="int64_t"/>
+</resultMap>
+<resultMap id="MoreMap" type="yb_user_more_t" extends="DetailMap">
+    <result column="age" property="age" yo_type="int64_t"/>
+</resultMap>"#,
+                ),
+            ),
+        ]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mapper = mappers.iter().find(|m| m.namespace == "t").unwrap();
+        let results = |id: &str| -> Vec<(String, String)> {
+            mapper.result_maps[id]
+                .results
+                .iter()
+                .map(|r| (r.property.clone(), r.yo_type.clone()))
+                .collect()
+        };
+        let pair = |p: &str, t: &str| (p.to_string(), t.to_string());
+        // the parent's results first, score overridden by the child
+        assert_eq!(
+            results("DetailMap"),
+            vec![
+                pair("id", "int64_t"),
+                pair("name", "yb_string_t"),
+                pair("bio", "yb_string_t"),
+                pair("score", "int64_t"),
+            ]
+        );
+        assert_eq!(
+            results("MoreMap"),
+            vec![
+                pair("id", "int64_t"),
+                pair("name", "yb_string_t"),
+                pair("bio", "yb_string_t"),
+                pair("score", "int64_t"),
+                pair("age", "int64_t"),
+            ]
+        );
+        assert!(mapper.result_maps["DetailMap"].results[0].is_id);
+        assert_eq!(
+            mapper.type_maps["yb_user_more_t"].results.len(),
+            5,
+            "the type maps are flattened too"
+        );
+    }
+
+    #[test]
+    fn extends_errors() {
+        let messages = |body: &str| -> Vec<String> {
+            let (_, errors) = load(&[("t-mapper.xml", &mapper_xml(body))]);
+            let mut messages: Vec<String> = errors.iter().map(|e| e.message.clone()).collect();
+            messages.sort();
+            messages
+        };
+        assert_eq!(
+            messages(r#"<resultMap id="a" type="yb_a_t" extends="b"/>"#),
+            vec!["t.a: extended resultMap not found: b"]
+        );
+        assert_eq!(
+            messages(
+                r#"<resultMap id="a" type="yb_a_t" extends="b"/>
+<resultMap id="b" type="yb_b_t" extends="t.a"/>"#
+            ),
+            vec![
+                "resultMap extends cycle: t.a -> t.b -> t.a",
+                "resultMap extends cycle: t.b -> t.a -> t.b",
+            ]
+        );
+
+        let dept = r#"<?xml version="1.0" encoding="utf-8"?>
+<mapper namespace="dept">
+<resultMap id="DeptMap" type="yb_dept_t">
+    <id column="id" property="id" yo_type="int64_t"/>
+    <association property="manager" resultMap="EmpMap"/>
+</resultMap>
+<resultMap id="EmpMap" type="yb_emp_t">
+    <id column="id" property="id" yo_type="int64_t"/>
+</resultMap>
+</mapper>
+"#;
+        let (_, errors) = load(&[
+            ("dept-mapper.xml", dept),
+            (
+                "t-mapper.xml",
+                &mapper_xml(r#"<resultMap id="a" type="yb_a_t" extends="dept.DeptMap"/>"#),
+            ),
+        ]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(
+            errors[0].message,
+            "t.a: can not extend dept.DeptMap from another namespace, it has associations or collections"
+        );
+        assert!(
+            errors[0].file.ends_with("t-mapper.xml"),
+            "{}",
+            errors[0].file
+        );
+    }
+
+    #[test]
+    fn located_parse_errors() {
+        let (mappers, errors) = load(&[
+            (
+                "a-mapper.xml",
+                &mapper_xml(
+                    r#"<select id="s" parameterType="int64_t">SELECT 1</select>
+<resultMap id="m" type="yb_m_t">
+    <result column="id" yo_type="int64_t"/>
+</resultMap>
+<delete id="d">DELETE FROM t</delete>"#,
+                ),
+            ),
+            (
+                "b-mapper.xml",
+                "<mapper namespace=\"b\">\n  <delete id=\"d\">\n</mapper>\n",
+            ),
+            (
+                "c-mapper.xml",
+                &mapper_xml("<delete id=\"d\">DELETE FROM t</delete>"),
+            ),
+        ]);
+        // the good statements are still parsed
+        assert_eq!(mappers.len(), 2);
+
+        let mut errors: Vec<(String, u64, u64, String, String)> = errors
+            .iter()
+            .map(|e| {
+                let file = Path::new(&e.file).file_name().unwrap();
+                (
+                    file.to_string_lossy().to_string(),
+                    e.line,
+                    e.column,
+                    e.element.clone(),
+                    e.message.clone(),
+                )
+            })
+            .collect();
+        errors.sort();
+        let error = |file: &str, line, column, element: &str, message: &str| {
+            (
+                file.to_string(),
+                line,
+                column,
+                element.to_string(),
+                message.to_string(),
+            )
+        };
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!(
+            errors[0],
+            error(
+                "a-mapper.xml",
+                3,
+                1,
+                "select",
+                "missing attribute resultMap"
+            )
+        );
+        // an error inside an element is located at that element
+        assert_eq!(
+            errors[1],
+            error("a-mapper.xml", 5, 5, "result", "missing attribute property")
+        );
+        assert_eq!(errors[2].0, "b-mapper.xml");
+        assert_eq!((errors[2].1, errors[2].3.as_str()), (3, ""));
+    }
+
+    #[test]
+    fn parse_error_display() {
+        let mut e = ParseMapperError::new("missing attribute id".to_string());
+        assert_eq!(e.to_string(), "missing attribute id");
+        e.file = "a-mapper.xml".to_string();
+        assert_eq!(e.to_string(), "a-mapper.xml: missing attribute id");
+        e.line = 3;
+        e.column = 5;
+        e.element = "select".to_string();
+        assert_eq!(
+            e.to_string(),
+            "a-mapper.xml:3:5: <select>: missing attribute id"
+        );
+    }
+
+    #[test]
+    fn database_id_variants() {
+        let dir = TempDir::new(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<sql id="page">LIMIT 10</sql>
+<sql id="page" databaseId="postgresql">FETCH FIRST 10 ROWS ONLY</sql>
+<select id="s" resultMap="m" databaseId="postgresql">SELECT 'pg' <include refid="page"/></select>
+<select id="s" resultMap="m">SELECT 'any' <include refid="page"/></select>
+<delete id="d" databaseId="oracle">DELETE FROM t</delete>
+<resultMap id="m" type="yb_m_t"><result column="a" property="a" yo_type="int64_t"/></resultMap>"#,
+            ),
+        )]);
+        let text = |database_id: &str| -> (Vec<String>, bool) {
+            let (mappers, errors) = load_mappers(
+                &MapperFiles {
+                    inputs: vec![dir.input()],
+                    include: vec!["**/*-mapper.xml".to_string()],
+                    exclude: Vec::new(),
+                },
+                database_id,
+            );
+            assert!(errors.is_empty(), "{:?}", errors);
+            let texts = mappers[0].selects["s"]
+                .content
+                .iter()
+                .flat_map(|e| match e {
+                    SqlElement::YoInclude(include) => include.content.iter().collect(),
+                    e => vec![e],
+                })
+                .filter_map(|e| match e {
+                    SqlElement::YoText(text) => Some(text.trim().to_string()),
+                    _ => None,
+                })
+                .filter(|text| !text.is_empty())
+                .collect();
+            (texts, mappers[0].deletes.contains_key("d"))
+        };
+        // the variant of the database, the unattributed one otherwise; a
+        // statement only for another database is left out
+        assert_eq!(
+            text("postgresql"),
+            (
+                vec![
+                    "SELECT 'pg'".to_string(),
+                    "FETCH FIRST 10 ROWS ONLY".to_string()
+                ],
+                false
+            )
+        );
+        assert_eq!(
+            text("mysql"),
+            (
+                vec!["SELECT 'any'".to_string(), "LIMIT 10".to_string()],
+                false
+            )
+        );
+        assert!(text("oracle").1);
+
+        let (_, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<delete id="d" databaseId="mysql">DELETE FROM a</delete>
+<delete id="d" databaseId="mysql">DELETE FROM b</delete>
+<delete id="d">DELETE FROM c</delete>"#,
+            ),
+        )]);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].message, "duplicate statement id d");
+        assert_eq!(errors[0].line, 4);
+    }
+
+    #[test]
+    fn select_key_order() {
+        let (mappers, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<insert id="a"><selectKey keyProperty="id" order="BEFORE">SELECT UUID()</selectKey>INSERT INTO t (id) VALUES (#{id})</insert>
+<insert id="b">INSERT INTO t (id) VALUES (1)<selectKey keyProperty="id">SELECT LAST_INSERT_ID()</selectKey></insert>"#,
+            ),
+        )]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let inserts = &mappers[0].inserts;
+        let key = |id: &str| inserts[id].select_key.as_ref().unwrap();
+        assert_eq!(
+            (key("a").key_property.as_str(), key("a").before),
+            ("id", true)
+        );
+        // AFTER by default
+        assert!(!key("b").before);
+
+        let errors = |insert: &str| {
+            let (_, errors) = load(&[("t-mapper.xml", &mapper_xml(insert))]);
+            errors
+                .iter()
+                .map(|e| (e.element.clone(), e.message.clone()))
+                .collect::<Vec<(String, String)>>()
+        };
+        let error = |element: &str, message: &str| vec![(element.to_string(), message.to_string())];
+        assert_eq!(
+            errors(
+                r#"<insert id="a"><selectKey keyProperty="id" order="FIRST">SELECT 1</selectKey>INSERT INTO t VALUES (1)</insert>"#
+            ),
+            error("selectKey", "order must be BEFORE or AFTER, not FIRST")
+        );
+        assert_eq!(
+            errors(
+                r#"<insert id="a"><selectKey>SELECT 1</selectKey>INSERT INTO t VALUES (1)</insert>"#
+            ),
+            error("selectKey", "missing attribute keyProperty")
+        );
+        assert_eq!(
+            errors(
+                r#"<insert id="a"><selectKey keyProperty="id">SELECT 1</selectKey><selectKey keyProperty="id">SELECT 2</selectKey>INSERT INTO t VALUES (1)</insert>"#
+            ),
+            error("selectKey", "more than one <selectKey>")
+        );
+    }
+
+    #[test]
+    fn placeholders_to_question_marks() {
+        assert_eq!(
+            split_placeholders("a = #{a} AND b = #{ b, jdbcType=VARCHAR } OR a = #{a}"),
+            ("a = ? AND b = ? OR a = ?".to_string(), vec!["a", "b", "a"])
+        );
+        assert_eq!(
+            split_placeholders("SELECT 1"),
+            ("SELECT 1".to_string(), Vec::<&str>::new())
+        );
+    }
 }
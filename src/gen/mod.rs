@@ -1,3 +1,4 @@
 /// # module to generating C source codes from xml mapper
 pub mod genc;
 pub mod mapper;
+pub mod sql;
@@ -0,0 +1,1065 @@
+//! # check the SQL of the statements with a MySQL dialect parser
+//!
+//! The dynamic SQL of a statement is rendered for the combinations of its
+//! `<if>` and `<choose>` branches, with `?` for `#{}` and sample values for
+//! `${}`, and each rendering is parsed. With the schema of db.xml, the
+//! tables, columns and property types of the parsed SQL are checked too.
+//! The renderings also tell whether a select returns one row at most.
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
+
+use sqlparser::ast::{
+    visit_expressions, AssignmentTarget, BinaryOperator, Expr, Ident, Query, SelectItem, SetExpr,
+    Statement as SqlStatement, TableFactor, Value, Visit, Visitor,
+};
+use sqlparser::dialect::MySqlDialect;
+use sqlparser::parser::Parser;
+
+use crate::check::schema::{Column, Schema, Table};
+
+use super::mapper::{
+    CompareOp, Mapper, ParseMapperError, SqlElement, Statement, TestExpr, YoResultMap, YoTrim,
+};
+
+/// the most branch combinations parsed for a statement, a sample of them is
+/// parsed if it has more
+const MAX_COMBINATIONS: usize = 64;
+
+/// the number of ways of each branch point, in document order: 2 for an
+/// <if> (skipped, taken), one per <when> plus one for <otherwise> (or none
+/// of them) for a <choose>
+fn branch_points(elements: &[SqlElement], points: &mut Vec<usize>) {
+    for elem in elements {
+        match elem {
+            SqlElement::YoIf(if_elem) => {
+                points.push(2);
+                branch_points(&if_elem.content, points);
+            }
+            SqlElement::Choose(choose) => {
+                points.push(choose.whens.len() + 1);
+                for when in &choose.whens {
+                    branch_points(&when.content, points);
+                }
+                if let Some(otherwise) = &choose.otherwise {
+                    branch_points(otherwise, points);
+                }
+            }
+            SqlElement::YoTrim(trim) => branch_points(&trim.content, points),
+            SqlElement::YoInclude(inc) => branch_points(&inc.content, points),
+            SqlElement::YoText(_) | SqlElement::YoSubst(_) => {}
+        }
+    }
+}
+
+/// the branch combinations to render: all of them, or when there are too
+/// many, every branch taken and skipped, and each way of each branch point
+/// with the others taken
+fn combinations(points: &[usize]) -> Vec<Vec<usize>> {
+    let total = points
+        .iter()
+        .try_fold(1usize, |total, ways| total.checked_mul(*ways));
+    match total {
+        Some(total) if total <= MAX_COMBINATIONS => (0..total)
+            .map(|mut n| {
+                points
+                    .iter()
+                    .map(|ways| {
+                        let way = n % ways;
+                        n /= ways;
+                        way
+                    })
+                    .collect()
+            })
+            .collect(),
+        _ => {
+            // an <if> is taken by way 1, a <choose> by its first <when>
+            let taken: Vec<usize> = points
+                .iter()
+                .map(|ways| if *ways == 2 { 1 } else { 0 })
+                .collect();
+            let skipped: Vec<usize> = points
+                .iter()
+                .map(|ways| if *ways == 2 { 0 } else { ways - 1 })
+                .collect();
+            let mut combinations = vec![taken.clone(), skipped];
+            for (i, ways) in points.iter().enumerate() {
+                for way in 0..*ways {
+                    let mut combination = taken.clone();
+                    combination[i] = way;
+                    combinations.push(combination);
+                }
+            }
+            combinations
+        }
+    }
+}
+
+/// the most property value samples tried to find whether the tests of a
+/// combination can hold together
+const MAX_SAMPLES: usize = 4096;
+
+/// a value of a property, to find the combinations of branches the tests
+/// can not lead to, like both of <if test="n > 0"> and <if test="n <= 0">
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sample {
+    Null,
+    Num(f64),
+}
+
+fn sample_operand(expr: &TestExpr, values: &HashMap<&str, Sample>) -> Option<Sample> {
+    match expr {
+        TestExpr::Null => Some(Sample::Null),
+        TestExpr::Int(i) => Some(Sample::Num(*i as f64)),
+        TestExpr::Float(f) => Some(Sample::Num(*f)),
+        TestExpr::Property(name) => values.get(name.as_str()).cloned(),
+        _ => None,
+    }
+}
+
+/// the value of a test with the properties in values, None if it can not
+/// be told: strings, properties without a value, ordering with null
+fn eval(expr: &TestExpr, values: &HashMap<&str, Sample>) -> Option<bool> {
+    match expr {
+        TestExpr::Bool(b) => Some(*b),
+        TestExpr::Not(e) => eval(e, values).map(|b| !b),
+        TestExpr::And(l, r) => match (eval(l, values), eval(r, values)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        TestExpr::Or(l, r) => match (eval(l, values), eval(r, values)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        TestExpr::Compare(op, l, r) => {
+            match (sample_operand(l, values)?, sample_operand(r, values)?) {
+                (Sample::Num(l), Sample::Num(r)) => Some(match op {
+                    CompareOp::Eq => l == r,
+                    CompareOp::Ne => l != r,
+                    CompareOp::Lt => l < r,
+                    CompareOp::Le => l <= r,
+                    CompareOp::Gt => l > r,
+                    CompareOp::Ge => l >= r,
+                }),
+                (l, r) => match op {
+                    CompareOp::Eq => Some(l == r),
+                    CompareOp::Ne => Some(l != r),
+                    _ => None,
+                },
+            }
+        }
+        _ => None,
+    }
+}
+
+/// the numbers and nulls properties are compared with in expr. A property
+/// only compared with numbers is not tried null.
+fn collect_samples<'a>(expr: &'a TestExpr, samples: &mut HashMap<&'a str, Vec<Sample>>) {
+    match expr {
+        TestExpr::Not(e) => collect_samples(e, samples),
+        TestExpr::And(l, r) | TestExpr::Or(l, r) => {
+            collect_samples(l, samples);
+            collect_samples(r, samples);
+        }
+        TestExpr::Compare(_, l, r) => {
+            for (prop, value) in [(l, r), (r, l)] {
+                if let TestExpr::Property(name) = prop.as_ref() {
+                    let values = samples.entry(name).or_default();
+                    let new_values = match sample_operand(value, &HashMap::new()) {
+                        // the number, and a value at each side of it
+                        Some(Sample::Num(n)) => {
+                            vec![Sample::Num(n - 0.5), Sample::Num(n), Sample::Num(n + 0.5)]
+                        }
+                        // null, and a value that is not
+                        Some(Sample::Null) => vec![Sample::Null, Sample::Num(0.0)],
+                        None => Vec::new(),
+                    };
+                    for v in new_values {
+                        if !values.contains(&v) {
+                            values.push(v);
+                        }
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// whether the tests can have the wanted values together. Only numbers and
+/// nulls are tried, the tests that depend on anything else can be either.
+fn feasible(constraints: &[(&TestExpr, bool)]) -> bool {
+    let mut samples = HashMap::new();
+    for (expr, _) in constraints {
+        collect_samples(expr, &mut samples);
+    }
+    let props: Vec<(&str, Vec<Sample>)> = samples
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .collect();
+    let total = props
+        .iter()
+        .try_fold(1usize, |total, (_, values)| total.checked_mul(values.len()));
+    let total = match total {
+        Some(total) if total <= MAX_SAMPLES => total,
+        _ => return true,
+    };
+
+    (0..total).any(|mut n| {
+        let mut values = HashMap::new();
+        for (prop, samples) in &props {
+            values.insert(*prop, samples[n % samples.len()]);
+            n /= samples.len();
+        }
+        constraints
+            .iter()
+            .all(|(expr, wanted)| eval(expr, &values) != Some(!wanted))
+    })
+}
+
+/// what a ${} without a whitelist is rendered as. Its value is only known at
+/// run time, so the name stands for a table, a column or a value alike.
+const SUBST_PLACEHOLDER: &str = "yb_subst";
+
+/// the SQL of a statement for one combination of its branches
+struct Rendering<'a> {
+    sql: String,
+    /// the tests of the branches taken
+    taken: Vec<String>,
+    /// the value each test reached must have
+    constraints: Vec<(&'a TestExpr, bool)>,
+    /// the #{} properties in order
+    properties: Vec<String>,
+    /// a ${} without a whitelist is rendered
+    unresolved: bool,
+    /// #{} is rendered `@yb_<index in properties>` instead of `?`, to tell
+    /// the properties apart in the parsed SQL
+    named: bool,
+}
+
+/// replace #{...} with a placeholder
+fn render_text(text: &str, out: &mut String, r: &mut Rendering) {
+    let mut rest = text;
+    while let Some(start) = rest.find("#{") {
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 2..];
+        let end = inner.find('}').unwrap_or(inner.len());
+        let name = inner[..end].split(',').next().unwrap_or("").trim();
+        if r.named {
+            out.push_str(&format!("@yb_{}", r.properties.len()));
+        } else {
+            out.push('?');
+        }
+        r.properties.push(name.to_string());
+        rest = if end < inner.len() {
+            &inner[end + 1..]
+        } else {
+            ""
+        };
+    }
+    out.push_str(rest);
+}
+
+/// <trim>: the overrides are `|` separated and matched case insensitively,
+/// as yb_string_trim() of yb_common.c does
+fn render_trim(trim: &YoTrim, content: &str, out: &mut String) {
+    let is_space = |c: char| c.is_ascii_whitespace();
+    let mut content = content.trim_matches(is_space);
+    if content.is_empty() {
+        return;
+    }
+    for prefix in trim
+        .prefix_overrides
+        .split('|')
+        .map(|p| p.trim_matches(is_space))
+    {
+        if !prefix.is_empty()
+            && content.len() >= prefix.len()
+            && content.is_char_boundary(prefix.len())
+            && content[..prefix.len()].eq_ignore_ascii_case(prefix)
+        {
+            content = content[prefix.len()..].trim_start_matches(is_space);
+            break;
+        }
+    }
+    for suffix in trim
+        .suffix_overrides
+        .split('|')
+        .map(|s| s.trim_matches(is_space))
+    {
+        let start = content.len().saturating_sub(suffix.len());
+        if !suffix.is_empty()
+            && content.len() >= suffix.len()
+            && content.is_char_boundary(start)
+            && content[start..].eq_ignore_ascii_case(suffix)
+        {
+            content = content[..start].trim_end_matches(is_space);
+            break;
+        }
+    }
+    out.push_str(&format!(" {} {} {} ", trim.prefix, content, trim.suffix));
+}
+
+/// render elements with the ways in combination, starting at branch point
+/// *point, to out. Skipped content is walked too, with emit false, to keep
+/// the branch points numbered.
+fn render<'a>(
+    elements: &'a [SqlElement],
+    combination: &[usize],
+    point: &mut usize,
+    emit: bool,
+    out: &mut String,
+    r: &mut Rendering<'a>,
+) {
+    for elem in elements {
+        match elem {
+            SqlElement::YoText(text) => {
+                if emit {
+                    render_text(text, out, r);
+                }
+            }
+            SqlElement::YoSubst(subst) => {
+                if emit {
+                    match &subst.whitelist {
+                        Some(whitelist) => out.push_str(&whitelist[0]),
+                        None => {
+                            out.push_str(SUBST_PLACEHOLDER);
+                            r.unresolved = true;
+                        }
+                    }
+                }
+            }
+            SqlElement::YoIf(if_elem) => {
+                let take = emit && combination[*point] == 1;
+                *point += 1;
+                if emit {
+                    r.constraints.push((&if_elem.expr, take));
+                }
+                if take {
+                    r.taken.push(format!("<if test=\"{}\">", if_elem.test));
+                }
+                render(&if_elem.content, combination, point, take, out, r);
+            }
+            SqlElement::Choose(choose) => {
+                let way = combination[*point];
+                *point += 1;
+                for (i, when) in choose.whens.iter().enumerate() {
+                    let take = emit && way == i;
+                    // the <when>s before the one taken are false
+                    if emit && i <= way {
+                        r.constraints.push((&when.expr, take));
+                    }
+                    if take {
+                        r.taken.push(format!("<when test=\"{}\">", when.test));
+                    }
+                    render(&when.content, combination, point, take, out, r);
+                }
+                if let Some(otherwise) = &choose.otherwise {
+                    let take = emit && way == choose.whens.len();
+                    if take {
+                        r.taken.push("<otherwise>".to_string());
+                    }
+                    render(otherwise, combination, point, take, out, r);
+                }
+            }
+            SqlElement::YoTrim(trim) => {
+                let mut content = String::new();
+                render(&trim.content, combination, point, emit, &mut content, r);
+                if emit {
+                    render_trim(trim, &content, out);
+                }
+            }
+            SqlElement::YoInclude(inc) => {
+                render(&inc.content, combination, point, emit, out, r);
+            }
+        }
+    }
+}
+
+/// the distinct renderings of a statement content its tests can lead to
+fn renderings(content: &[SqlElement], named: bool) -> Vec<Rendering<'_>> {
+    let mut points = Vec::new();
+    branch_points(content, &mut points);
+
+    let mut rendered = HashSet::new();
+    let mut renderings = Vec::new();
+    for combination in combinations(&points) {
+        let mut r = Rendering {
+            sql: String::new(),
+            taken: Vec::new(),
+            constraints: Vec::new(),
+            properties: Vec::new(),
+            unresolved: false,
+            named,
+        };
+        let mut sql = String::new();
+        render(content, &combination, &mut 0, true, &mut sql, &mut r);
+        if !feasible(&r.constraints) {
+            continue;
+        }
+        r.sql = sql.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if rendered.insert(r.sql.clone()) {
+            renderings.push(r);
+        }
+    }
+    renderings
+}
+
+/// an error on a statement of mapper
+fn statement_error(mapper: &Mapper, statement: &Statement, message: String) -> ParseMapperError {
+    let (line, column) = mapper
+        .locations
+        .get(&("statement".to_string(), statement.id.to_string()))
+        .cloned()
+        .unwrap_or((0, 0));
+    ParseMapperError {
+        message: format!("{}: {}", statement.id, message),
+        file: mapper.file.clone(),
+        line,
+        column,
+        element: statement.kind.to_string(),
+        code: None,
+    }
+}
+
+/// parse every rendering of the statements of the mappers. A statement is
+/// reported once, with the first of its renderings that fails: as an error
+/// if all of them fail, as a warning if only some branches lead to invalid
+/// SQL or the failing SQL has a ${} that may fit once substituted. Returns
+/// the errors and the warnings.
+pub fn check_sql(mappers: &[Mapper]) -> (Vec<ParseMapperError>, Vec<ParseMapperError>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let dialect = MySqlDialect {};
+
+    let mut mappers: Vec<&Mapper> = mappers.iter().collect();
+    mappers.sort_by(|a, b| a.file.cmp(&b.file));
+    for mapper in mappers {
+        for statement in mapper.statements() {
+            let mut failure = None;
+            let mut unresolved = false;
+            let mut valid = false;
+            for r in renderings(statement.content, false) {
+                match Parser::parse_sql(&dialect, &r.sql) {
+                    Ok(_) => valid = true,
+                    Err(e) => {
+                        if failure.is_none() {
+                            let branches = if r.taken.is_empty() {
+                                String::new()
+                            } else {
+                                format!(" with {}", r.taken.join(", "))
+                            };
+                            failure =
+                                Some(format!("SQL syntax error{}: {}: {}", branches, e, r.sql));
+                            unresolved = r.unresolved;
+                        }
+                    }
+                }
+                if valid && failure.is_some() {
+                    break;
+                }
+            }
+
+            if let Some(message) = failure {
+                let e = statement_error(mapper, &statement, message);
+                if valid || unresolved {
+                    warnings.push(e);
+                } else {
+                    errors.push(e);
+                }
+            }
+        }
+    }
+    (errors, warnings)
+}
+
+/// what a parsed statement refers to
+#[derive(Default)]
+struct References {
+    /// the tables, with their alias
+    tables: Vec<(String, Option<String>)>,
+    /// the names of WITH queries and select aliases, they are not in the
+    /// schema
+    names: Vec<String>,
+    /// a derived table or table function is used, its columns are unknown
+    opaque: bool,
+}
+
+impl Visitor for References {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        if let Some(with) = &query.with {
+            for cte in &with.cte_tables {
+                self.names.push(cte.alias.name.value.clone());
+            }
+        }
+        if let SetExpr::Select(select) = query.body.as_ref() {
+            for item in &select.projection {
+                if let SelectItem::ExprWithAlias { alias, .. } = item {
+                    self.names.push(alias.value.clone());
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_table_factor(&mut self, table_factor: &TableFactor) -> ControlFlow<()> {
+        match table_factor {
+            TableFactor::Table {
+                name, alias, args, ..
+            } if args.is_none() && !is_subst(&name.0) => {
+                let name = name.0.last().map(|i| i.value.clone()).unwrap_or_default();
+                self.tables
+                    .push((name, alias.as_ref().map(|a| a.name.value.clone())));
+            }
+            _ => self.opaque = true,
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// whether a name is, or is qualified by, a ${} placeholder
+fn is_subst(parts: &[Ident]) -> bool {
+    parts.iter().any(|i| i.value == SUBST_PLACEHOLDER)
+}
+
+/// checks a parsed statement against the schema
+struct SchemaCheck<'a> {
+    schema: &'a Schema,
+    refs: References,
+    /// the #{} properties, `@yb_<index>` in the SQL
+    properties: &'a [String],
+    /// the yo_type of the properties
+    types: &'a HashMap<String, String>,
+    messages: Vec<String>,
+}
+
+impl<'a> SchemaCheck<'a> {
+    fn error(&mut self, message: String) {
+        if !self.messages.contains(&message) {
+            self.messages.push(message);
+        }
+    }
+
+    fn check_tables(&mut self) {
+        let mut unknown = Vec::new();
+        for (name, _) in &self.refs.tables {
+            if self.schema.table(name).is_none() && !self.refs.names.contains(name) {
+                unknown.push(format!("table {} is not in db.xml", name));
+            }
+        }
+        for message in unknown {
+            self.error(message);
+        }
+    }
+
+    /// the column of an identifier, None if it is not a column or can not
+    /// be told
+    fn column(&mut self, parts: &[Ident]) -> Option<&'a Column> {
+        let schema = self.schema;
+        if is_subst(parts) {
+            return None;
+        }
+        match parts {
+            [name] => {
+                let name = &name.value;
+                if name.starts_with('@') || self.refs.names.contains(name) {
+                    return None;
+                }
+                let tables: Vec<&Table> = self
+                    .refs
+                    .tables
+                    .iter()
+                    .filter_map(|(t, _)| schema.table(t))
+                    .collect();
+                if let Some(column) = tables.iter().find_map(|t| t.column(name)) {
+                    return Some(column);
+                }
+                // some of the tables are not known
+                if self.refs.opaque || tables.len() < self.refs.tables.len() || tables.is_empty() {
+                    return None;
+                }
+                let names: Vec<&str> = tables.iter().map(|t| t.name.as_str()).collect();
+                self.error(format!(
+                    "column {} is not in table {}",
+                    name,
+                    names.join(", ")
+                ));
+                None
+            }
+            [.., qualifier, name] => {
+                let table = self
+                    .refs
+                    .tables
+                    .iter()
+                    .find(|(t, alias)| match alias {
+                        Some(alias) => *alias == qualifier.value,
+                        None => *t == qualifier.value,
+                    })
+                    .and_then(|(t, _)| schema.table(t))?;
+                match table.column(&name.value) {
+                    Some(column) => Some(column),
+                    None => {
+                        self.error(format!(
+                            "column {} is not in table {}",
+                            name.value, table.name
+                        ));
+                        None
+                    }
+                }
+            }
+            [] => None,
+        }
+    }
+
+    fn expr_column(&mut self, expr: &Expr) -> Option<&'a Column> {
+        match expr {
+            Expr::Identifier(ident) => self.column(std::slice::from_ref(ident)),
+            Expr::CompoundIdentifier(parts) => self.column(parts),
+            Expr::Nested(e) => self.expr_column(e),
+            _ => None,
+        }
+    }
+
+    /// the property of a placeholder
+    fn property(&self, expr: &Expr) -> Option<&'a str> {
+        let properties = self.properties;
+        match expr {
+            Expr::Identifier(ident) => {
+                let index: usize = ident.value.strip_prefix("@yb_")?.parse().ok()?;
+                properties.get(index).map(|p| p.as_str())
+            }
+            Expr::Nested(e) => self.property(e),
+            _ => None,
+        }
+    }
+
+    /// the property of value must have a type the column can take
+    fn check_type(&mut self, column: Option<&Column>, value: &Expr) {
+        let (column, property) = match (column, self.property(value)) {
+            (Some(column), Some(property)) => (column, property),
+            _ => return,
+        };
+        let yo_type = match self.types.get(property) {
+            Some(yo_type) => yo_type,
+            None => return,
+        };
+        let compatible =
+            *yo_type == column.yo_type || (yo_type == "int64_t" && column.yo_type == "double");
+        if !compatible {
+            self.error(format!(
+                "#{{{}}} is {}, column {} is {}",
+                property, yo_type, column.name, column.yo_type
+            ));
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                self.expr_column(expr);
+            }
+            Expr::BinaryOp { left, op, right } => {
+                if matches!(
+                    op,
+                    BinaryOperator::Eq
+                        | BinaryOperator::NotEq
+                        | BinaryOperator::Lt
+                        | BinaryOperator::LtEq
+                        | BinaryOperator::Gt
+                        | BinaryOperator::GtEq
+                        | BinaryOperator::Spaceship
+                ) {
+                    let column = self.expr_column(left);
+                    self.check_type(column, right);
+                    let column = self.expr_column(right);
+                    self.check_type(column, left);
+                }
+            }
+            Expr::InList { expr, list, .. } => {
+                let column = self.expr_column(expr);
+                for item in list {
+                    self.check_type(column, item);
+                }
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => {
+                let column = self.expr_column(expr);
+                self.check_type(column, low);
+                self.check_type(column, high);
+            }
+            _ => {}
+        }
+    }
+
+    /// the columns and values of INSERT and UPDATE
+    fn check_statement(&mut self, statement: &SqlStatement) {
+        match statement {
+            SqlStatement::Insert(insert) => {
+                if is_subst(&insert.table_name.0) {
+                    return;
+                }
+                let name = insert
+                    .table_name
+                    .0
+                    .last()
+                    .map(|i| i.value.clone())
+                    .unwrap_or_default();
+                let table = match self.schema.table(&name) {
+                    Some(table) => table,
+                    None => {
+                        self.error(format!("table {} is not in db.xml", name));
+                        return;
+                    }
+                };
+                let mut columns = Vec::new();
+                for ident in &insert.columns {
+                    match table.column(&ident.value) {
+                        Some(column) => columns.push(Some(column)),
+                        None => {
+                            self.error(format!(
+                                "column {} is not in table {}",
+                                ident.value, table.name
+                            ));
+                            columns.push(None);
+                        }
+                    }
+                }
+                if let Some(source) = &insert.source {
+                    if let SetExpr::Values(values) = source.body.as_ref() {
+                        for row in &values.rows {
+                            for (column, value) in columns.iter().zip(row) {
+                                self.check_type(*column, value);
+                            }
+                        }
+                    }
+                }
+            }
+            SqlStatement::Update { assignments, .. } => {
+                for assignment in assignments {
+                    if let AssignmentTarget::ColumnName(name) = &assignment.target {
+                        let column = self.column(&name.0);
+                        self.check_type(column, &assignment.value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// check the tables and columns the statements refer to, and the types of
+/// the #{} properties compared with or assigned to a column, against the
+/// schema of db.xml. The statements that are not valid SQL are left to
+/// `check_sql`.
+pub fn check_schema(mappers: &[Mapper], schema: &Schema) -> Vec<ParseMapperError> {
+    let mut errors = Vec::new();
+    let dialect = MySqlDialect {};
+
+    let mut mappers: Vec<&Mapper> = mappers.iter().collect();
+    mappers.sort_by(|a, b| a.file.cmp(&b.file));
+    for mapper in mappers {
+        for statement in mapper.statements() {
+            let mut types = HashMap::new();
+            if !statement.params.is_empty() {
+                for param in statement.params {
+                    types.insert(param.name.clone(), param.yo_type.clone());
+                }
+            } else if let Some(result_map) = mapper.type_maps.get(statement.parameter_type) {
+                for result in &result_map.results {
+                    types.insert(result.property.clone(), result.yo_type.clone());
+                }
+            }
+
+            let mut messages: Vec<String> = Vec::new();
+            for r in renderings(statement.content, true) {
+                let parsed = match Parser::parse_sql(&dialect, &r.sql) {
+                    Ok(parsed) => parsed,
+                    Err(_) => continue,
+                };
+                for sql_statement in &parsed {
+                    let mut refs = References::default();
+                    let _ = sql_statement.visit(&mut refs);
+                    let mut check = SchemaCheck {
+                        schema,
+                        refs,
+                        properties: &r.properties,
+                        types: &types,
+                        messages: Vec::new(),
+                    };
+                    check.check_tables();
+                    check.check_statement(sql_statement);
+                    let _ = visit_expressions(sql_statement, |expr| {
+                        check.check_expr(expr);
+                        ControlFlow::<()>::Continue(())
+                    });
+                    for message in check.messages {
+                        if !messages.contains(&message) {
+                            messages.push(message);
+                        }
+                    }
+                }
+            }
+
+            for message in messages {
+                errors.push(statement_error(mapper, &statement, message));
+            }
+        }
+    }
+    errors
+}
+
+/// the columns `<column> = <value>` fixes in the AND terms of a WHERE
+fn fixed_columns(expr: &Expr, columns: &mut Vec<String>) {
+    match expr {
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            fixed_columns(left, columns);
+            fixed_columns(right, columns);
+        }
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::Eq,
+            right,
+        } => {
+            for (column, value) in [(left, right), (right, left)] {
+                let name = match column.as_ref() {
+                    Expr::Identifier(ident) => &ident.value,
+                    Expr::CompoundIdentifier(parts) => match parts.last() {
+                        Some(ident) => &ident.value,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                if matches!(value.as_ref(), Expr::Value(_)) {
+                    columns.push(name.to_lowercase());
+                }
+            }
+        }
+        Expr::Nested(e) => fixed_columns(e, columns),
+        _ => {}
+    }
+}
+
+/// whether a SELECT surely returns several rows: one of the ways the dynamic
+/// SQL renders parses as a query without `LIMIT 1` whose WHERE does not fix
+/// the <id> columns of result_map to single values. A resultMap without <id>
+/// tells nothing, so its SELECT is never taken as returning several rows.
+pub fn returns_many_rows(content: &[SqlElement], result_map: &YoResultMap) -> bool {
+    let dialect = MySqlDialect {};
+    let id_columns: Vec<String> = result_map
+        .results
+        .iter()
+        .filter(|r| r.is_id)
+        .map(|r| r.column.to_lowercase())
+        .collect();
+    if id_columns.is_empty() {
+        return false;
+    }
+
+    renderings(content, false).iter().any(|r| {
+        let parsed = match Parser::parse_sql(&dialect, &r.sql) {
+            Ok(parsed) => parsed,
+            Err(_) => return false,
+        };
+        let query = match parsed.first() {
+            Some(SqlStatement::Query(query)) => query,
+            _ => return false,
+        };
+        if let Some(Expr::Value(Value::Number(n, _))) = &query.limit {
+            if n == "1" {
+                return false;
+            }
+        }
+        let mut columns = Vec::new();
+        if let SetExpr::Select(select) = query.body.as_ref() {
+            if let Some(selection) = &select.selection {
+                fixed_columns(selection, &mut columns);
+            }
+        }
+        !id_columns.iter().all(|c| columns.contains(c))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::genc::test_util::{gen, run};
+    use crate::gen::mapper::test_util::{load, mapper_xml};
+
+    fn check(body: &str) -> (Vec<ParseMapperError>, Vec<ParseMapperError>) {
+        let (mappers, errors) = load(&[("t-mapper.xml", &mapper_xml(body))]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        check_sql(&mappers)
+    }
+
+    #[test]
+    fn subst_in_from_and_order_by() {
+        let (errors, warnings) = check(
+            r#"<select id="s" parameterType="yb_t_t" resultMap="m">
+                SELECT id, name FROM ${table} WHERE id = #{id} ORDER BY ${column}
+            </select>
+            <resultMap id="m" type="yb_t_t">
+                <result column="id" property="id" yo_type="int64_t"/>
+                <result column="table" property="table" yo_type="yb_string_t"/>
+                <result column="column" property="column" yo_type="yb_string_t"/>
+            </resultMap>"#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn subst_that_can_not_fit_is_a_warning() {
+        let (errors, warnings) = check(
+            r#"<delete id="d" parameterType="yb_t_t">DELETE ${what} x FROM t</delete>
+            <resultMap id="m" type="yb_t_t">
+                <result column="what" property="what" yo_type="yb_string_t"/>
+            </resultMap>"#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(warnings.len(), 1);
+    }
+
+    fn check_against_schema(body: &str) -> Vec<String> {
+        let (mappers, errors) = load(&[("t-mapper.xml", &mapper_xml(body))]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let schema = Schema {
+            tables: vec![crate::check::schema::parse_create_table(
+                "CREATE TABLE t (id BIGINT PRIMARY KEY, name VARCHAR(10))",
+            )
+            .unwrap()],
+        };
+        check_schema(&mappers, &schema)
+            .into_iter()
+            .map(|e| e.message)
+            .collect()
+    }
+
+    #[test]
+    fn unknown_tables() {
+        let messages = check_against_schema(
+            r#"<insert id="i">INSERT INTO u (id) VALUES (1)</insert>
+            <update id="u">UPDATE u SET id = 1</update>
+            <insert id="ok">INSERT INTO t (id, name) VALUES (1, 'a')</insert>
+            <insert id="subst" parameterType="yb_t_t">INSERT INTO ${name} (id) VALUES (1)</insert>
+            <resultMap id="m" type="yb_t_t">
+                <result column="name" property="name" yo_type="yb_string_t"/>
+            </resultMap>"#,
+        );
+        assert_eq!(
+            messages,
+            vec![
+                "i: table u is not in db.xml".to_string(),
+                "u: table u is not in db.xml".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_sql_is_an_error() {
+        let (errors, _) = check(r#"<delete id="d">DELETE FROM t WHERE</delete>"#);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("SQL syntax error"));
+    }
+
+    #[test]
+    fn many_rows_only_when_sure() {
+        let (mappers, errors) = load(&[(
+            "t-mapper.xml",
+            &mapper_xml(
+                r#"<select id="all" resultMap="m">SELECT id, name FROM t</select>
+                <select id="by_id" parameterType="int64_t" resultMap="m">SELECT id, name FROM t WHERE id = #{id}</select>
+                <select id="first" resultMap="m">SELECT id, name FROM t LIMIT 1</select>
+                <select id="no_id" resultMap="n">SELECT id, name FROM t</select>
+                <resultMap id="m" type="yb_t_t">
+                    <id column="id" property="id" yo_type="int64_t"/>
+                    <result column="name" property="name" yo_type="yb_string_t"/>
+                </resultMap>
+                <resultMap id="n" type="yb_t_t">
+                    <result column="id" property="id" yo_type="int64_t"/>
+                    <result column="name" property="name" yo_type="yb_string_t"/>
+                </resultMap>"#,
+            ),
+        )]);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let mapper = &mappers[0];
+        let many = |id: &str| {
+            let select = &mapper.selects[id];
+            returns_many_rows(&select.content, &mapper.result_maps[&select.result_map])
+        };
+        assert!(many("all"));
+        assert!(!many("by_id"));
+        assert!(!many("first"));
+        assert!(!many("no_id"));
+    }
+
+    #[test]
+    fn combinations_cap() {
+        assert_eq!(combinations(&[2, 3]).len(), 6);
+        assert_eq!(combinations(&[2; 6]).len(), MAX_COMBINATIONS);
+
+        // 2^7 > MAX_COMBINATIONS: all taken, all skipped and each way of
+        // each point with the others taken
+        let points = [2; 7];
+        let sampled = combinations(&points);
+        assert_eq!(sampled.len(), 2 + 2 * points.len());
+        assert_eq!(sampled[0], vec![1; 7]);
+        assert_eq!(sampled[1], vec![0; 7]);
+        assert!(sampled.iter().all(|c| c.len() == points.len()));
+
+        // a product that overflows is capped too
+        let points = [3; 64];
+        let sampled = combinations(&points);
+        assert_eq!(sampled.len(), 2 + 3 * points.len());
+        assert_eq!(sampled[1], vec![2; 64]);
+    }
+
+    #[test]
+    fn trim_as_the_c_runtime() {
+        // prefix, suffix, prefixOverrides, suffixOverrides, content
+        let cases = [
+            ("(", ")", "", ",", "`id`, `name`,\n        "),
+            ("SET", "", "", ",", " \n\t "),
+            ("WHERE", "", "AND |OR ", "", "\n    and a = ? "),
+            ("WHERE", "", "and|or", "", "OR b = ?"),
+            ("", "", "  |AND", "", "AND c"),
+            ("(", ")", "", ",|;", "a, b;"),
+            ("VALUES (", ")", "", ",", "?,\r\n"),
+            ("", "", "ANDAND", "", "AND"),
+            ("", "", "a", "a", "a"),
+            ("", "", "", "", "x"),
+        ];
+        let mut main_c =
+            "#include <stdio.h>\n#include \"yb_common.h\"\n\nint main(void) {\n".to_string();
+        let mut expected = String::new();
+        for (prefix, suffix, prefix_overrides, suffix_overrides, content) in cases.iter() {
+            let trim = YoTrim {
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+                prefix_overrides: prefix_overrides.to_string(),
+                suffix_overrides: suffix_overrides.to_string(),
+                content: Vec::new(),
+            };
+            let mut out = String::new();
+            render_trim(&trim, content, &mut out);
+            expected.push_str(&format!("[{}]\n", out));
+            main_c.push_str(&format!(
+                "    {{\n        yb_string_t src = yb_string_from_cstr({:?});\n        yb_string_t dest = yb_string_new();\n        if (yb_string_trim(src, {:?}, {:?}, {:?}, {:?}, dest) != YB_OK) return 1;\n        printf(\"[%.*s]\\n\", (int)yb_string_length(dest), yb_string_data(dest));\n        yb_string_free(src);\n        yb_string_free(dest);\n    }}\n",
+                content, prefix, suffix, prefix_overrides, suffix_overrides
+            ));
+        }
+        main_c.push_str("    return 0;\n}\n");
+
+        let out = gen(&[]);
+        if let Some(output) = run(&out, &main_c) {
+            assert_eq!(output, expected);
+        }
+    }
+}
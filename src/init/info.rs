@@ -1,10 +1,11 @@
-/// # The Mapper Info struct to describe table of mysql
+//! # The Mapper Info struct to describe table of mysql
 
 /// Date type of table column.
 /// Only 3 types support:
 /// All integer types tag as INT.
 /// All string, text, blob, date ... types tag as STRING
 /// All floating point number tag as FLOAT
+#[allow(clippy::upper_case_acronyms)]
 pub enum ColumnType {
     INT,
     STRING,
@@ -12,6 +13,7 @@ pub enum ColumnType {
 }
 
 /// Column description
+#[allow(dead_code)]
 pub struct ColumnInfo {
     /// Column name
     pub name: String,
@@ -8,7 +8,7 @@ use std::path::Path;
 use xml::writer::{EmitterConfig, EventWriter, Result, XmlEvent};
 
 // generate db.xml.
-// define the database.
+// define the database and the DDL of its tables.
 fn gen_db_xml(inf: &info::DBInfo, path: &Path) -> Result<()> {
     let file = File::create(path.join("db.xml"))?;
     let mut writer = EmitterConfig::new()
@@ -26,14 +26,28 @@ fn gen_db_xml(inf: &info::DBInfo, path: &Path) -> Result<()> {
     writer.write(event)?;
     let event: XmlEvent = XmlEvent::start_element("create").into();
     writer.write(event)?;
-    let event: XmlEvent = XmlEvent::characters(&inf.create).into();
+    let event: XmlEvent = XmlEvent::characters(&inf.create);
     writer.write(event)?;
     let event: XmlEvent = XmlEvent::end_element().into(); // create
     writer.write(event)?;
 
+    for table in &inf.tables {
+        let event: XmlEvent = XmlEvent::start_element("table")
+            .attr("name", &table.name)
+            .into();
+        writer.write(event)?;
+        let event: XmlEvent = XmlEvent::start_element("create").into();
+        writer.write(event)?;
+        writer.write(XmlEvent::characters(&table.create))?;
+        let event: XmlEvent = XmlEvent::end_element().into(); // create
+        writer.write(event)?;
+        let event: XmlEvent = XmlEvent::end_element().into(); // table
+        writer.write(event)?;
+    }
+
     writer.write(XmlEvent::end_element())?; // db
     writer.write(XmlEvent::characters("\n"))?;
-    return Ok(());
+    Ok(())
 }
 
 fn gen_col_type_str(t: &info::ColumnType) -> String {
@@ -44,14 +58,6 @@ fn gen_col_type_str(t: &info::ColumnType) -> String {
     }
 }
 
-fn gen_col_type_null_str(t: &info::ColumnType) -> String {
-    match t {
-        info::ColumnType::INT => "YB_INT_NULL".to_string(),
-        info::ColumnType::STRING => "YB_STRING_NULL".to_string(),
-        info::ColumnType::FLOAT => "YB_FLOAT_NULL".to_string(),
-    }
-}
-
 fn gen_result_map(
     inf: &info::TableInfo,
     name_norm: &str,
@@ -69,7 +75,9 @@ fn gen_result_map(
     for col in &inf.columns {
         let col_name_norm = re.replace_all(&col.name, "_");
         let yo_type = gen_col_type_str(&col.type_);
-        let result: XmlEvent = XmlEvent::start_element("result")
+        // <id> tells a select by primary key returns one row
+        let element = if col.primary_key { "id" } else { "result" };
+        let result: XmlEvent = XmlEvent::start_element(element)
             .attr("column", &col.name)
             .attr("property", &col_name_norm)
             .attr("yo_type", &yo_type)
@@ -81,7 +89,7 @@ fn gen_result_map(
     let result_map: XmlEvent = XmlEvent::end_element().into(); // resultMap
     writer.write(result_map)?;
     // end resultMap
-    return Ok(());
+    Ok(())
 }
 
 fn gen_base_column_list(inf: &info::TableInfo, writer: &mut EventWriter<File>) -> Result<()> {
@@ -97,13 +105,13 @@ fn gen_base_column_list(inf: &info::TableInfo, writer: &mut EventWriter<File>) -
         }
         first = false;
         let col_name_norm = re.replace_all(&col.name, "_");
-        let sql: XmlEvent = XmlEvent::characters(&col_name_norm).into();
+        let sql: XmlEvent = XmlEvent::characters(&col_name_norm);
         writer.write(sql)?;
     }
     let base_column_list: XmlEvent = XmlEvent::end_element().into();
     writer.write(base_column_list)?;
 
-    return Ok(());
+    Ok(())
 }
 
 fn gen_insert_all(
@@ -129,7 +137,7 @@ fn gen_insert_all(
         }
         first = false;
         let col_name_norm = re.replace_all(&col.name, "_");
-        let sql: XmlEvent = XmlEvent::characters(&col_name_norm).into();
+        let sql: XmlEvent = XmlEvent::characters(&col_name_norm);
         writer.write(sql)?;
     }
     writer.write(XmlEvent::characters(") VALUES ("))?;
@@ -141,14 +149,14 @@ fn gen_insert_all(
         first = false;
         let col_name_norm = re.replace_all(&col.name, "_");
         let value_name_wrap = format!("#{{{}}}", col_name_norm);
-        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap).into();
+        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap);
         writer.write(sql)?;
     }
     writer.write(XmlEvent::characters(")"))?;
     let insert: XmlEvent = XmlEvent::end_element().into();
     writer.write(insert)?;
 
-    return Ok(());
+    Ok(())
 }
 
 fn gen_delete_by_primary_key(
@@ -158,7 +166,7 @@ fn gen_delete_by_primary_key(
     writer: &mut EventWriter<File>,
 ) -> Result<()> {
     let re = Regex::new(r"[^0-9a-zA-Z_]").unwrap();
-    let pri_name_norm = re.replace_all(&key_name, "_");
+    let pri_name_norm = re.replace_all(key_name, "_");
     let delete_name = format!("{}_delete_by_{}", name_norm, pri_name_norm);
     let mut key_type = &info::ColumnType::INT;
     for col in &inf.columns {
@@ -177,7 +185,7 @@ fn gen_delete_by_primary_key(
     writer.write(XmlEvent::characters("DELETE FROM `"))?;
     writer.write(XmlEvent::characters(&inf.name))?;
     writer.write(XmlEvent::characters("` WHERE `"))?;
-    writer.write(XmlEvent::characters(&key_name))?;
+    writer.write(XmlEvent::characters(key_name))?;
     writer.write(XmlEvent::characters("` = #{"))?;
     writer.write(XmlEvent::characters(&pri_name_norm))?;
     writer.write(XmlEvent::characters("}"))?;
@@ -185,7 +193,7 @@ fn gen_delete_by_primary_key(
     let delete: XmlEvent = XmlEvent::end_element().into();
     writer.write(delete)?;
 
-    return Ok(());
+    Ok(())
 }
 
 fn gen_select_by_primary_key(
@@ -195,7 +203,7 @@ fn gen_select_by_primary_key(
     writer: &mut EventWriter<File>,
 ) -> Result<()> {
     let re = Regex::new(r"[^0-9a-zA-Z_]").unwrap();
-    let pri_name_norm = re.replace_all(&key_name, "_");
+    let pri_name_norm = re.replace_all(key_name, "_");
     let select_name = format!("{}_select_by_{}", name_norm, pri_name_norm);
 
     let mut key_type = &info::ColumnType::INT;
@@ -225,13 +233,13 @@ fn gen_select_by_primary_key(
     writer.write(XmlEvent::characters(&inf.name))?;
     writer.write(XmlEvent::characters("` WHERE "))?;
     let where_cond = format!("{} = #{{{}}}", key_name, pri_name_norm);
-    let sql: XmlEvent = XmlEvent::characters(&where_cond).into();
+    let sql: XmlEvent = XmlEvent::characters(&where_cond);
     writer.write(sql)?;
 
     let select: XmlEvent = XmlEvent::end_element().into();
     writer.write(select)?;
 
-    return Ok(());
+    Ok(())
 }
 
 fn gen_update_by_primary_key(
@@ -241,7 +249,7 @@ fn gen_update_by_primary_key(
     writer: &mut EventWriter<File>,
 ) -> Result<()> {
     let re = Regex::new(r"[^0-9a-zA-Z_]").unwrap();
-    let pri_name_norm = re.replace_all(&key_name, "_");
+    let pri_name_norm = re.replace_all(key_name, "_");
     let update_name = format!("{}_update_by_{}", name_norm, pri_name_norm);
     let base_result_map_name = format!("yb_{}_t", name_norm);
 
@@ -261,20 +269,20 @@ fn gen_update_by_primary_key(
         first = false;
         let col_name_norm = re.replace_all(&col.name, "_");
         let value_name_wrap = format!("`{}` = #{{{}}}", col.name, col_name_norm);
-        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap).into();
+        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap);
         writer.write(sql)?;
     }
     writer.write(XmlEvent::characters(" WHERE "))?;
-    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm).into();
+    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm);
     writer.write(sql)?;
     writer.write(XmlEvent::characters(" = #{"))?;
-    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm).into();
+    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm);
     writer.write(sql)?;
     writer.write(XmlEvent::characters("}"))?;
     let update: XmlEvent = XmlEvent::end_element().into();
     writer.write(update)?;
 
-    return Ok(());
+    Ok(())
 }
 
 fn gen_update_by_primary_key_selective(
@@ -284,7 +292,7 @@ fn gen_update_by_primary_key_selective(
     writer: &mut EventWriter<File>,
 ) -> Result<()> {
     let re = Regex::new(r"[^0-9a-zA-Z_]").unwrap();
-    let pri_name_norm = re.replace_all(&key_name, "_");
+    let pri_name_norm = re.replace_all(key_name, "_");
     let update_name = format!("{}_update_by_{}_selective", name_norm, pri_name_norm);
     let base_result_map_name = format!("yb_{}_t", name_norm);
 
@@ -308,13 +316,13 @@ fn gen_update_by_primary_key_selective(
 
         let col_name_norm = re.replace_all(&col.name, "_");
         let value_name_wrap = format!("`{}` = #{{{}}}, ", col.name, col_name_norm);
-        let test_cond = format!("{} != {}", col_name_norm, gen_col_type_null_str(&col.type_));
+        let test_cond = format!("dirty({})", col_name_norm);
 
         let if_: XmlEvent = XmlEvent::start_element("if")
             .attr("test", &test_cond)
             .into();
         writer.write(if_)?;
-        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap).into();
+        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap);
         writer.write(sql)?;
         let if_: XmlEvent = XmlEvent::end_element().into();
         writer.write(if_)?;
@@ -323,16 +331,16 @@ fn gen_update_by_primary_key_selective(
     writer.write(trim)?;
 
     writer.write(XmlEvent::characters(" WHERE "))?;
-    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm).into();
+    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm);
     writer.write(sql)?;
     writer.write(XmlEvent::characters(" = #{"))?;
-    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm).into();
+    let sql: XmlEvent = XmlEvent::characters(&pri_name_norm);
     writer.write(sql)?;
     writer.write(XmlEvent::characters("}"))?;
     let update: XmlEvent = XmlEvent::end_element().into();
     writer.write(update)?;
 
-    return Ok(());
+    Ok(())
 }
 
 fn gen_insert_selective(
@@ -361,12 +369,12 @@ fn gen_insert_selective(
     writer.write(insert_selective_columns)?;
     for col in &inf.columns {
         let col_name_norm = re.replace_all(&col.name, "_");
-        let col_condition = &format!("{} != {}", col_name_norm, gen_col_type_null_str(&col.type_));
+        let col_condition = &format!("dirty({})", col_name_norm);
         let col_selective: XmlEvent = XmlEvent::start_element("if")
             .attr("test", col_condition)
             .into();
         writer.write(col_selective)?;
-        let sql: XmlEvent = XmlEvent::characters(&col_name_norm).into();
+        let sql: XmlEvent = XmlEvent::characters(&col_name_norm);
         writer.write(sql)?;
         writer.write(XmlEvent::characters(","))?;
         let col_selective: XmlEvent = XmlEvent::end_element().into();
@@ -384,13 +392,13 @@ fn gen_insert_selective(
     writer.write(insert_selective_values)?;
     for col in &inf.columns {
         let col_name_norm = re.replace_all(&col.name, "_");
-        let col_condition = &format!("{} != {}", col_name_norm, gen_col_type_null_str(&col.type_));
+        let col_condition = &format!("dirty({})", col_name_norm);
         let col_selective: XmlEvent = XmlEvent::start_element("if")
             .attr("test", col_condition)
             .into();
         writer.write(col_selective)?;
         let value_name_wrap = format!("#{{{}}}", col_name_norm);
-        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap).into();
+        let sql: XmlEvent = XmlEvent::characters(&value_name_wrap);
         writer.write(sql)?;
         writer.write(XmlEvent::characters(","))?;
         let col_selective: XmlEvent = XmlEvent::end_element().into();
@@ -402,7 +410,7 @@ fn gen_insert_selective(
     let insert: XmlEvent = XmlEvent::end_element().into();
     writer.write(insert)?;
 
-    return Ok(());
+    Ok(())
 }
 
 // generate table-mapper.xml
@@ -430,9 +438,9 @@ fn gen_table_xml(inf: &info::TableInfo, path: &Path) -> Result<()> {
 
     gen_base_column_list(inf, &mut writer)?;
 
-    gen_insert_all(&inf, &name_norm, &mut writer)?;
+    gen_insert_all(inf, &name_norm, &mut writer)?;
 
-    gen_insert_selective(&inf, &name_norm, &mut writer)?;
+    gen_insert_selective(inf, &name_norm, &mut writer)?;
 
     for col in &inf.columns {
         if col.primary_key {
@@ -451,7 +459,7 @@ fn gen_table_xml(inf: &info::TableInfo, path: &Path) -> Result<()> {
 
     writer.write(XmlEvent::characters("\n"))?;
 
-    return Ok(());
+    Ok(())
 }
 
 // generate the mapper xml files.
@@ -459,11 +467,11 @@ pub fn generate(inf: &info::DBInfo, dir: &str) -> Result<()> {
     fs::create_dir_all(dir)?;
     let path = Path::new(dir);
 
-    gen_db_xml(inf, &path)?;
+    gen_db_xml(inf, path)?;
 
     for table in &inf.tables {
-        gen_table_xml(table, &path)?;
+        gen_table_xml(table, path)?;
     }
 
-    return Ok(());
+    Ok(())
 }
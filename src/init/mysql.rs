@@ -13,20 +13,20 @@ fn get_create_database_sql(con: &mut Conn, db_name: &str) -> Result<String> {
     let re = Regex::new(r"CREATE DATABASE").unwrap();
     let res_sql = re.replace(&sql, "CREATE DATABASE IF NOT EXISTS");
 
-    return Result::Ok(String::from(res_sql));
+    Result::Ok(String::from(res_sql))
 }
 
 // get all table name of a database.
 fn get_table_list(con: &mut Conn, db_name: &str) -> Result<Vec<String>> {
     let mut tables: Vec<String> = Vec::new();
-    let mut rows = con.query_iter(format!("SHOW TABLES FROM `{}`", db_name))?;
+    let rows = con.query_iter(format!("SHOW TABLES FROM `{}`", db_name))?;
 
-    while let Some(row) = rows.next() {
+    for row in rows {
         let table = row?.get::<String, usize>(0).unwrap();
         tables.push(table);
     }
 
-    return Result::Ok(tables);
+    Result::Ok(tables)
 }
 
 // get table DDL for a table.
@@ -38,7 +38,7 @@ fn get_table_create_sql(con: &mut Conn, table_name: &str) -> Result<String> {
     let re = Regex::new(r"CREATE TABLE").unwrap();
     let res_sql = re.replace(&sql, "CREATE TABLE IF NOT EXISTS");
 
-    return Result::Ok(String::from(res_sql));
+    Result::Ok(String::from(res_sql))
 }
 
 // parse a column type to info::ColumnType enum.
@@ -53,14 +53,14 @@ fn parse_column_type(column_type: &str) -> Result<info::ColumnType> {
         return Ok(info::ColumnType::FLOAT);
     }
 
-    return Ok(info::ColumnType::STRING);
+    Ok(info::ColumnType::STRING)
 }
 
 // get column info from a table.
 fn get_table_columns(con: &mut Conn, table_name: &str) -> Result<Vec<info::ColumnInfo>> {
     let mut columns: Vec<info::ColumnInfo> = Vec::new();
-    let mut rows = con.query_iter(format!("SHOW FULL COLUMNS FROM `{}`", table_name))?;
-    while let Some(row) = rows.next() {
+    let rows = con.query_iter(format!("SHOW FULL COLUMNS FROM `{}`", table_name))?;
+    for row in rows {
         let column = row?;
         let column_name = column.get::<String, usize>(0).unwrap();
         let column_type = column.get::<String, usize>(1).unwrap();
@@ -96,16 +96,16 @@ fn get_table_columns(con: &mut Conn, table_name: &str) -> Result<Vec<info::Colum
         columns.push(column_info);
     }
 
-    return Ok(columns);
+    Ok(columns)
 }
 
 // get table structure info.
 fn get_table_info(con: &mut Conn, table_name: &str) -> Result<info::TableInfo> {
-    return Result::Ok(info::TableInfo {
+    Result::Ok(info::TableInfo {
         name: table_name.to_string(),
         columns: get_table_columns(con, table_name)?,
         create: get_table_create_sql(con, table_name)?,
-    });
+    })
 }
 
 // get all table structure info from a database.
@@ -118,7 +118,7 @@ fn get_all_table_info(con: &mut Conn, db_name: &str) -> Result<Vec<info::TableIn
         tables.push(table_info);
     }
 
-    return Result::Ok(tables);
+    Result::Ok(tables)
 }
 
 // get all table and database structure info from a database.
@@ -133,9 +133,9 @@ pub fn get_info(opt: &info::DBOpt) -> Result<info::DBInfo> {
     let mut con = Conn::new(opts)?;
     con.ping();
 
-    return Ok(info::DBInfo {
+    Ok(info::DBInfo {
         name: opt.database.clone(),
         create: get_create_database_sql(&mut con, &opt.database)?,
         tables: get_all_table_info(&mut con, &opt.database)?,
-    });
+    })
 }
@@ -2,10 +2,51 @@
 
 extern crate clap;
 use clap::{App, Arg, SubCommand};
+mod check;
 mod gen;
 mod init;
 
-use log::{debug, error, info, trace, warn};
+/// the mapper files selected by the input, include and exclude options
+fn mapper_files(matches: &clap::ArgMatches) -> gen::mapper::MapperFiles {
+    let values = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map(|values| values.map(String::from).collect())
+            .unwrap_or_default()
+    };
+    gen::mapper::MapperFiles {
+        inputs: values("input"),
+        include: values("include"),
+        exclude: values("exclude"),
+    }
+}
+
+/// the schema of the db option, or of the first db.xml of the input dirs if
+/// there is one. A db option that can not be loaded is fatal, a db.xml found
+/// in the input dirs is skipped with a warning.
+fn load_db(inputs: &[String], db: Option<&str>) -> Option<check::schema::Schema> {
+    let (path, explicit) = match db {
+        Some(db) => (std::path::PathBuf::from(db), true),
+        None => (
+            inputs
+                .iter()
+                .map(|input| std::path::Path::new(input).join("db.xml"))
+                .find(|db| db.exists())?,
+            false,
+        ),
+    };
+    match check::schema::load_schema(&path) {
+        Ok(schema) => Some(schema),
+        Err(e) if explicit => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            log::warn!("{}, the schema is not checked", e);
+            None
+        }
+    }
+}
 
 fn main() {
     let cli = App::new("yobatis")
@@ -76,11 +117,37 @@ fn main() {
                     Arg::with_name("input")
                         .short("i")
                         .long("input")
-                        .help("mapper files dir")
+                        .help("mapper files dir, searched recursively, may be given several times")
                         .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
                         .required(true)
                         .default_value("."),
                 )
+                .arg(
+                    Arg::with_name("include")
+                        .long("include")
+                        .help("glob pattern of the mapper files, relative to the input dir")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .default_value("**/*-mapper.xml"),
+                )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .help("glob pattern of the files to skip, relative to the input dir")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("database-id")
+                        .long("database-id")
+                        .help("the target database, picks the statements and fragments with this databaseId")
+                        .takes_value(true)
+                        .default_value("mysql"),
+                )
                 .arg(
                     Arg::with_name("output")
                         .short("o")
@@ -89,16 +156,82 @@ fn main() {
                         .takes_value(true)
                         .required(true)
                         .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("db")
+                        .long("db")
+                        .help("db.xml with the DDL of the tables, defaults to db.xml in the mapper files dirs")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("no-sql-check")
+                        .long("no-sql-check")
+                        .help("do not check the SQL of the statements with a SQL parser"),
+                )
+                .arg(
+                    Arg::with_name("no-schema-check")
+                        .long("no-schema-check")
+                        .help("do not check the tables and columns of the statements against db.xml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Validate mapper files without generating code")
+                .arg(
+                    Arg::with_name("input")
+                        .short("i")
+                        .long("input")
+                        .help("mapper files dir, searched recursively, may be given several times")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::with_name("include")
+                        .long("include")
+                        .help("glob pattern of the mapper files, relative to the input dir")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .default_value("**/*-mapper.xml"),
+                )
+                .arg(
+                    Arg::with_name("exclude")
+                        .long("exclude")
+                        .help("glob pattern of the files to skip, relative to the input dir")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("database-id")
+                        .long("database-id")
+                        .help("the target database, picks the statements and fragments with this databaseId")
+                        .takes_value(true)
+                        .default_value("mysql"),
+                )
+                .arg(
+                    Arg::with_name("db")
+                        .long("db")
+                        .help("db.xml with the DDL of the tables, defaults to db.xml in the mapper files dirs")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .short("f")
+                        .long("format")
+                        .help("output format, one diagnostic per line")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text"),
                 ),
         );
     let matches = cli.get_matches();
 
-    env_logger::init();
-    trace!("trace");
-    debug!("debug");
-    info!("info");
-    warn!("warn");
-    error!("error");
+    // show generator warnings unless RUST_LOG says otherwise
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
     if let Some(matches) = matches.subcommand_matches("init") {
         let host = matches.value_of("host").unwrap_or("not kown");
@@ -112,16 +245,74 @@ fn main() {
         };
         let output = matches.value_of("output").unwrap();
         let inf = init::mysql::get_info(&dbopt).unwrap();
-        init::mapper::generate(&inf, &output).unwrap();
+        init::mapper::generate(&inf, output).unwrap();
     }
 
     if let Some(matches) = matches.subcommand_matches("gen") {
-        let input = matches.value_of("input").unwrap();
+        let files = mapper_files(matches);
         let output = matches.value_of("output").unwrap();
-        println!("Value for input: {}", input);
+        println!("Value for input: {}", files.inputs.join(", "));
         println!("Value for output: {}", output);
 
-        let mappers = gen::mapper::parse_mappers(&input).unwrap();
-        gen::genc::gen_c(mappers, &output).unwrap();
+        let mappers =
+            match gen::mapper::parse_mappers(&files, matches.value_of("database-id").unwrap()) {
+                Ok(mappers) => mappers,
+                Err(errors) => {
+                    for e in &errors {
+                        eprintln!("error: {}", e);
+                    }
+                    eprintln!("{} error(s) found in mappers", errors.len());
+                    std::process::exit(1);
+                }
+            };
+        if !matches.is_present("no-sql-check") {
+            let (errors, warnings) = gen::sql::check_sql(&mappers);
+            for w in &warnings {
+                log::warn!("{}", w);
+            }
+            if !errors.is_empty() {
+                for e in &errors {
+                    eprintln!("error: {}", e);
+                }
+                eprintln!("{} error(s) found in mappers", errors.len());
+                std::process::exit(1);
+            }
+        }
+        if !matches.is_present("no-schema-check") {
+            if let Some(schema) = load_db(&files.inputs, matches.value_of("db")) {
+                let errors = gen::sql::check_schema(&mappers, &schema);
+                if !errors.is_empty() {
+                    for e in &errors {
+                        eprintln!("error: {}", e);
+                    }
+                    eprintln!("{} error(s) found in mappers", errors.len());
+                    std::process::exit(1);
+                }
+            }
+        }
+        if let Err(e) = gen::genc::gen_c(mappers, output) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let files = mapper_files(matches);
+        let schema = load_db(&files.inputs, matches.value_of("db"));
+        let (mappers, errors) =
+            gen::mapper::load_mappers(&files, matches.value_of("database-id").unwrap());
+        let diagnostics = check::lint::check(&mappers, errors, schema.as_ref());
+        for diagnostic in &diagnostics {
+            match matches.value_of("format") {
+                Some("json") => println!("{}", diagnostic.to_json()),
+                _ => println!("{}", diagnostic.to_text()),
+            }
+        }
+        if diagnostics
+            .iter()
+            .any(|d| d.severity == check::lint::Severity::Error)
+        {
+            std::process::exit(1);
+        }
     }
 }
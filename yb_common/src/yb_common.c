@@ -5,6 +5,7 @@
 #include <pthread.h>
 #include <stdio.h>
 #include <string.h>
+#include <strings.h>
 
 #ifndef YB_STRING_FLAG_OWN_DATA
 /// \brief bitmask for yb_string_s::flags. If set, the yb_string_s owns the
@@ -69,6 +70,7 @@ yb_string_t yb_string_from(const char* str, int64_t len) {
     yb_string_t r = yb_string_from_ref(copy, len);
     if (r == NULL) {
         free(copy);
+        return NULL;
     }
     r->flag |= YB_STRING_FLAG_OWN_DATA;
     return r;
@@ -85,7 +87,7 @@ void yb_string_free(yb_string_t s) {
 }
 
 yb_string_t yb_string_from_cstr(const char* str) {
-    return yb_string_new(str, strlen(str));
+    return yb_string_from(str, strlen(str));
 }
 
 yb_string_t yb_string_from_cstr_ref(const char* str) {
@@ -384,43 +386,119 @@ int yb_string_from_hex(yb_string_t s, const char* in, int64_t in_len) {
     return YB_OK;
 }
 
+// the ASCII whitespace of <trim>, as in Rust's is_ascii_whitespace
+static int yb_trim_is_space(char c) {
+    return c == ' ' || c == '\t' || c == '\n' || c == '\f' || c == '\r';
+}
+
+// the length of the first of the `|` separated overrides that data starts
+// with (at_end 0) or ends with (at_end 1), case insensitively, or -1
+static int64_t yb_trim_match(const char* data, int64_t len,
+                             const char* overrides, int at_end) {
+    const char* p = overrides;
+    while (*p != '\0') {
+        const char* sep = strchr(p, '|');
+        const char* next = sep != NULL ? sep + 1 : p + strlen(p);
+        const char* o_end = sep != NULL ? sep : next;
+        while (p < o_end && yb_trim_is_space(*p)) {
+            ++p;
+        }
+        while (o_end > p && yb_trim_is_space(o_end[-1])) {
+            --o_end;
+        }
+        int64_t o_len = o_end - p;
+        if (o_len > 0 && o_len <= len &&
+            strncasecmp(at_end ? data + len - o_len : data, p,
+                        (size_t)o_len) == 0) {
+            return o_len;
+        }
+        p = next;
+    }
+    return -1;
+}
+
 int yb_string_trim(yb_string_t src, const char* prefix, const char* suffix,
                    const char* prefix_override, const char* suffix_override,
                    yb_string_t dest) {
-    if (src == NULL) {
+    if (src == NULL || dest == NULL || prefix == NULL || suffix == NULL ||
+        prefix_override == NULL || suffix_override == NULL) {
         return YB_FAIL;
     }
-    if (prefix == NULL || suffix == NULL) {
-        return YB_FAIL;
+
+    const char* data = src->data;
+    int64_t len = src->len;
+    while (len > 0 && yb_trim_is_space(*data)) {
+        ++data;
+        --len;
+    }
+    while (len > 0 && yb_trim_is_space(data[len - 1])) {
+        --len;
+    }
+    if (len == 0) {
+        return YB_OK;
     }
 
-    int64_t start = 0;
-    int64_t end = src->len;
-    int64_t prefix_len = strlen(prefix_override);
-    int64_t suffix_len = strlen(suffix_override);
-    if (prefix_len + suffix_len > src->len) {
-        return YB_FAIL;
+    int64_t n = yb_trim_match(data, len, prefix_override, 0);
+    if (n > 0) {
+        data += n;
+        len -= n;
+        while (len > 0 && yb_trim_is_space(*data)) {
+            ++data;
+            --len;
+        }
     }
-    yb_string_t to_remove = yb_string_new();
-    yb_string_ref_data(to_remove, src->data + prefix_len, prefix_len);
-    if (yb_string_compare(to_remove, prefix_override) == 0) {
-        start = prefix_len;
+    n = yb_trim_match(data, len, suffix_override, 1);
+    if (n > 0) {
+        len -= n;
+        while (len > 0 && yb_trim_is_space(data[len - 1])) {
+            --len;
+        }
     }
-    yb_string_ref_data(to_remove, src->data + src->len - suffix_len,
-                       suffix_len);
-    if (yb_string_compare(to_remove, suffix_override) == 0) {
-        end -= suffix_len;
+
+    if (yb_string_append_c_str(dest, " ") != YB_OK ||
+        yb_string_append_c_str(dest, prefix) != YB_OK ||
+        yb_string_append_c_str(dest, " ") != YB_OK ||
+        yb_string_append_data(dest, data, len) != YB_OK ||
+        yb_string_append_c_str(dest, " ") != YB_OK ||
+        yb_string_append_c_str(dest, suffix) != YB_OK ||
+        yb_string_append_c_str(dest, " ") != YB_OK) {
+        return YB_FAIL;
     }
+    return YB_OK;
+}
 
-    yb_string_free(to_remove);
+int yb_string_append_int(yb_string_t s, int64_t v) {
+    char buf[32];
+    snprintf(buf, sizeof(buf), "%lld", (long long)v);
+    return yb_string_append_c_str(s, buf);
+}
 
-    yb_string_clear(dest);
-    yb_string_append_c_str(dest, prefix);
-    yb_string_append_data(dest, src->data + start, end - start);
-    yb_string_append_c_str(dest, suffix);
+int yb_string_append_double(yb_string_t s, double v) {
+    char buf[64];
+    snprintf(buf, sizeof(buf), "%.17g", v);
+    return yb_string_append_c_str(s, buf);
+}
 
-    return YB_OK;
+int yb_string_append_escaped(MYSQL* conn, yb_string_t s,
+                             const yb_string_t value) {
+    if (s == NULL || value == NULL) {
+        return YB_FAIL;
+    }
+    char* p = (char*)malloc(value->len * 2 + 1);
+    if (p == NULL) {
+        return YB_FAIL;
+    }
+    unsigned long len =
+        mysql_real_escape_string(conn, p, value->data, value->len);
+    if (len == (unsigned long)-1) {
+        free(p);
+        return YB_FAIL;
+    }
+    int r = yb_string_append_data(s, p, len);
+    free(p);
+    return r;
 }
+
 static inline uint32_t murmurhash(const char* key, uint32_t len,
                                   uint32_t seed) {
     uint32_t c1 = 0xcc9e2d51;
@@ -488,6 +566,77 @@ static inline uint32_t murmurhash(const char* key, uint32_t len,
     return h;
 }
 
+struct yb_list_s {
+    void** elems;
+    int64_t len;
+    int64_t cap;
+};
+
+yb_list_t yb_list_new() {
+    yb_list_t l = malloc(sizeof(struct yb_list_s));
+    if (l == NULL) {
+        return NULL;
+    }
+    l->elems = NULL;
+    l->len = 0;
+    l->cap = 0;
+    return l;
+}
+
+void yb_list_free(yb_list_t l, void (*free_elem)(void* elem)) {
+    if (l == NULL) {
+        return;
+    }
+    if (free_elem != NULL) {
+        for (int64_t i = 0; i < l->len; ++i) {
+            free_elem(l->elems[i]);
+        }
+    }
+    free(l->elems);
+    free(l);
+}
+
+int yb_list_push(yb_list_t l, void* elem) {
+    if (l == NULL) {
+        return YB_FAIL;
+    }
+    if (l->len == l->cap) {
+        int64_t cap = l->cap == 0 ? 8 : l->cap * 2;
+        void** elems = realloc(l->elems, sizeof(void*) * cap);
+        if (elems == NULL) {
+            return YB_FAIL;
+        }
+        l->elems = elems;
+        l->cap = cap;
+    }
+    l->elems[l->len++] = elem;
+    return YB_OK;
+}
+
+void* yb_list_get(const yb_list_t l, int64_t i) {
+    if (l == NULL || i < 0 || i >= l->len) {
+        return NULL;
+    }
+    return l->elems[i];
+}
+
+int64_t yb_list_len(const yb_list_t l) {
+    if (l == NULL) {
+        return 0;
+    }
+    return l->len;
+}
+
+void yb_list_foreach(yb_list_t l, void (*fn)(void* elem, void* arg),
+                     void* arg) {
+    if (l == NULL) {
+        return;
+    }
+    for (int64_t i = 0; i < l->len; ++i) {
+        fn(l->elems[i], arg);
+    }
+}
+
 struct yb_hash_entry {
     yb_string_t key;
     yb_string_t value;
@@ -682,42 +831,536 @@ void yb_hash_map_remove(yb_hash_map_t map, yb_string_t key) {
     map->elems--;
 }
 
-#define TEST_CASE_W(cond) arg->##cond
-#define yb_stmt_gen_string(str, e, arg_t, arg)           \
-    do {                                                 \
-        struct yb_stmt_elem* cur = e;                    \
-        for (; cur; cur = cur->next) {                   \
-            switch (cur->type) {                         \
-                case YB_STMT_ELEM_TYPE_TEXT:             \
-                    yb_string_append(s, cur->text.text); \
-                    break;                               \
-                case YB_STMT_ELEM_TYPE_IF:               \
-            }                                            \
-        }                                                \
-    } while (0)
-
-yb_string_t yb_stmt_gen_string(struct yb_stmt_elem* e, void* arg) {
-    yb_string_t s = yb_string_new();
-
-    struct yb_stmt_elem* cur = e;
-    for (; cur; cur = cur->next) {
-        switch (cur->type) {
-            case YB_STMT_ELEM_TYPE_TEXT: {
-                yb_string_append(s, cur->text.text);
-                break;
-            }
-            case YB_STMT_ELEM_TYPE_IF: {
-                // TODO
-                break;
-            }
-            case YB_STMT_ELEM_TYPE_TRIM: {
-                // TODO
-                break;
-            }
+static void yb_error_fill(yb_error_t* err, const char* stmt_id,
+                          unsigned int code, const char* sqlstate,
+                          const char* message) {
+    err->code = code;
+    snprintf(err->sqlstate, sizeof(err->sqlstate), "%s", sqlstate);
+    snprintf(err->message, sizeof(err->message), "%s", message);
+    err->stmt_id = stmt_id;
+}
+
+int yb_error_category(unsigned int code) {
+    switch (code) {
+        case 1062:
+        case 1586:
+            return YB_ERR_DUPLICATE_KEY;
+        case 1216:
+        case 1217:
+        case 1451:
+        case 1452:
+            return YB_ERR_FOREIGN_KEY;
+        case 1213:
+            return YB_ERR_DEADLOCK;
+        case 1205:
+            return YB_ERR_LOCK_WAIT_TIMEOUT;
+        case 2006:
+        case 2013:
+        case 2055:
+            return YB_ERR_CONNECTION_LOST;
+        case 1406:
+            return YB_ERR_DATA_TOO_LONG;
+        case 1048:
+        case 1364:
+            return YB_ERR_NOT_NULL;
+        case 1264:
+            return YB_ERR_OUT_OF_RANGE;
+        default:
+            return YB_FAIL;
+    }
+}
+
+static int yb_error_is(const yb_error_t* err, int category) {
+    return err != NULL && err->code != 0 &&
+           yb_error_category(err->code) == category;
+}
+
+int yb_error_is_duplicate_key(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_DUPLICATE_KEY);
+}
+
+int yb_error_is_foreign_key(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_FOREIGN_KEY);
+}
+
+int yb_error_is_deadlock(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_DEADLOCK);
+}
+
+int yb_error_is_lock_wait_timeout(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_LOCK_WAIT_TIMEOUT);
+}
+
+int yb_error_is_connection_lost(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_CONNECTION_LOST);
+}
+
+int yb_error_is_data_too_long(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_DATA_TOO_LONG);
+}
+
+int yb_error_is_not_null(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_NOT_NULL);
+}
+
+int yb_error_is_out_of_range(const yb_error_t* err) {
+    return yb_error_is(err, YB_ERR_OUT_OF_RANGE);
+}
+
+void yb_error_clear(yb_error_t* err) {
+    if (err == NULL) {
+        return;
+    }
+    yb_error_fill(err, NULL, 0, "00000", "");
+}
+
+void yb_error_set(yb_error_t* err, const char* stmt_id, const char* message) {
+    if (err == NULL) {
+        return;
+    }
+    yb_error_fill(err, stmt_id, 0, "HY000", message);
+}
+
+int yb_error_from_conn(yb_error_t* err, const char* stmt_id, MYSQL* conn) {
+    if (conn == NULL || mysql_errno(conn) == 0) {
+        yb_error_set(err, stmt_id, "out of memory");
+        return YB_FAIL;
+    }
+    if (err != NULL) {
+        yb_error_fill(err, stmt_id, mysql_errno(conn), mysql_sqlstate(conn),
+                      mysql_error(conn));
+    }
+    return yb_error_category(mysql_errno(conn));
+}
+
+int yb_error_from_stmt(yb_error_t* err, const char* stmt_id,
+                       MYSQL_STMT* stmt) {
+    if (stmt == NULL || mysql_stmt_errno(stmt) == 0) {
+        yb_error_set(err, stmt_id, "the statement failed without an error");
+        return YB_FAIL;
+    }
+    if (err != NULL) {
+        yb_error_fill(err, stmt_id, mysql_stmt_errno(stmt),
+                      mysql_stmt_sqlstate(stmt), mysql_stmt_error(stmt));
+    }
+    return yb_error_category(mysql_stmt_errno(stmt));
+}
+
+//! the type MYSQL_BIND.is_null points to, MySQL 8.0 replaced my_bool with
+//! bool
+#if defined(MARIADB_BASE_VERSION) || !defined(MYSQL_VERSION_ID) || \
+    MYSQL_VERSION_ID < 80001
+typedef my_bool yb_bind_bool_t;
+#else
+typedef bool yb_bind_bool_t;
+#endif
+
+struct yb_stmt_result_s {
+    MYSQL_STMT* stmt;
+    MYSQL_RES* meta;
+    MYSQL_FIELD* fields;
+    unsigned int num_fields;
+    //! every column is bound as an empty string buffer, mysql_stmt_fetch()
+    //! only sets the length and is_null of each column. mysql_stmt_bind_result()
+    //! copies the binds, so they point to is_null and length here rather
+    //! than being left NULL.
+    MYSQL_BIND* bind;
+    yb_bind_bool_t* is_null;
+    unsigned long* length;
+};
+
+static yb_stmt_result_t yb_stmt_result_bind(MYSQL_STMT* stmt, int store) {
+    yb_stmt_result_t r = calloc(1, sizeof(struct yb_stmt_result_s));
+    if (r == NULL) {
+        return NULL;
+    }
+    r->stmt = stmt;
+    if (store && mysql_stmt_store_result(stmt) != 0) {
+        yb_stmt_result_free(r);
+        return NULL;
+    }
+    r->meta = mysql_stmt_result_metadata(stmt);
+    if (r->meta == NULL) {
+        yb_stmt_result_free(r);
+        return NULL;
+    }
+    r->num_fields = mysql_num_fields(r->meta);
+    r->fields = mysql_fetch_fields(r->meta);
+    r->bind = calloc(r->num_fields + 1, sizeof(MYSQL_BIND));
+    r->is_null = calloc(r->num_fields + 1, sizeof(yb_bind_bool_t));
+    r->length = calloc(r->num_fields + 1, sizeof(unsigned long));
+    if (r->bind == NULL || r->is_null == NULL || r->length == NULL) {
+        yb_stmt_result_free(r);
+        return NULL;
+    }
+    for (unsigned int i = 0; i < r->num_fields; ++i) {
+        r->bind[i].buffer_type = MYSQL_TYPE_STRING;
+        r->bind[i].is_null = &r->is_null[i];
+        r->bind[i].length = &r->length[i];
+    }
+    if (mysql_stmt_bind_result(stmt, r->bind)) {
+        yb_stmt_result_free(r);
+        return NULL;
+    }
+    return r;
+}
+
+yb_stmt_result_t yb_stmt_result_new(MYSQL_STMT* stmt) {
+    return yb_stmt_result_bind(stmt, 1);
+}
+
+yb_stmt_result_t yb_stmt_result_stream(MYSQL_STMT* stmt) {
+    return yb_stmt_result_bind(stmt, 0);
+}
+
+void yb_stmt_result_free(yb_stmt_result_t r) {
+    if (r == NULL) {
+        return;
+    }
+    if (r->meta != NULL) {
+        mysql_free_result(r->meta);
+    }
+    mysql_stmt_free_result(r->stmt);
+    free(r->bind);
+    free(r->is_null);
+    free(r->length);
+    free(r);
+}
+
+int yb_stmt_result_fetch(yb_stmt_result_t r) {
+    // the values do not fit the empty buffers, so a row is always truncated
+    int rc = mysql_stmt_fetch(r->stmt);
+    if (rc == 0 || rc == MYSQL_DATA_TRUNCATED) {
+        return YB_OK;
+    }
+    if (rc == MYSQL_NO_DATA) {
+        return YB_NO_ROW;
+    }
+    return YB_FAIL;
+}
+
+int yb_stmt_result_column(yb_stmt_result_t r, const char* prefix,
+                          const char* name) {
+    size_t prefix_len = strlen(prefix);
+    for (unsigned int i = 0; i < r->num_fields; ++i) {
+        const char* field = r->fields[i].name;
+        if (strncasecmp(field, prefix, prefix_len) == 0 &&
+            strcasecmp(field + prefix_len, name) == 0) {
+            return (int)i;
         }
     }
+    return -1;
+}
 
-    return s;
+int yb_stmt_result_is_null(yb_stmt_result_t r, int column) {
+    if (column < 0) {
+        return 1;
+    }
+    return r->is_null[column] ? 1 : 0;
+}
+
+int yb_stmt_result_get_int(yb_stmt_result_t r, int column, int64_t* v) {
+    if (column < 0) {
+        return YB_OK;
+    }
+    if (r->is_null[column]) {
+        *v = 0;
+        return YB_OK;
+    }
+    MYSQL_BIND bind;
+    memset(&bind, 0, sizeof(MYSQL_BIND));
+    bind.buffer_type = MYSQL_TYPE_LONGLONG;
+    bind.buffer = v;
+    if (mysql_stmt_fetch_column(r->stmt, &bind, column, 0) != 0) {
+        return YB_FAIL;
+    }
+    return YB_OK;
+}
+
+int yb_stmt_result_get_double(yb_stmt_result_t r, int column, double* v) {
+    if (column < 0) {
+        return YB_OK;
+    }
+    if (r->is_null[column]) {
+        *v = 0.0;
+        return YB_OK;
+    }
+    MYSQL_BIND bind;
+    memset(&bind, 0, sizeof(MYSQL_BIND));
+    bind.buffer_type = MYSQL_TYPE_DOUBLE;
+    bind.buffer = v;
+    if (mysql_stmt_fetch_column(r->stmt, &bind, column, 0) != 0) {
+        return YB_FAIL;
+    }
+    return YB_OK;
+}
+
+int yb_stmt_result_get_string(yb_stmt_result_t r, int column, yb_string_t* v) {
+    if (column < 0) {
+        return YB_OK;
+    }
+    if (r->is_null[column]) {
+        yb_string_free(*v);
+        *v = YB_STRING_NULL;
+        return YB_OK;
+    }
+    unsigned long len = r->length[column];
+    char* data = malloc(len + 1);
+    if (data == NULL) {
+        return YB_FAIL;
+    }
+    MYSQL_BIND bind;
+    memset(&bind, 0, sizeof(MYSQL_BIND));
+    bind.buffer_type = MYSQL_TYPE_STRING;
+    bind.buffer = data;
+    bind.buffer_length = len + 1;
+    if (mysql_stmt_fetch_column(r->stmt, &bind, column, 0) != 0) {
+        free(data);
+        return YB_FAIL;
+    }
+    yb_string_t s = yb_string_from(data, len);
+    free(data);
+    if (s == NULL) {
+        return YB_FAIL;
+    }
+    yb_string_free(*v);
+    *v = s;
+    return YB_OK;
+}
+
+#define YB_STMT_CACHE_BUCKETS 64
+
+// a cached statement
+struct yb_stmt_cache_entry_s {
+    MYSQL_STMT* stmt;
+    char* sql;
+    unsigned long sql_len;
+    uint64_t hash;
+    int in_use;
+    struct yb_stmt_cache_entry_s* prev;
+    struct yb_stmt_cache_entry_s* next;
+};
+// the cached statements of a connection, most recently used first
+struct yb_stmt_cache_conn_s {
+    MYSQL* conn;
+    unsigned long thread_id;
+    struct yb_stmt_cache_entry_s* head;
+    struct yb_stmt_cache_entry_s* tail;
+    size_t size;
+    struct yb_stmt_cache_conn_s* next;
+};
+
+// the mutex guards the buckets, the statements of a connection are only
+// touched by the thread using it
+static pthread_mutex_t yb_stmt_cache_mutex = PTHREAD_MUTEX_INITIALIZER;
+static struct yb_stmt_cache_conn_s* yb_stmt_cache_conns[YB_STMT_CACHE_BUCKETS];
+static size_t yb_stmt_cache_capacity = YB_STMT_CACHE_SIZE;
+
+void yb_stmt_cache_set_capacity(size_t capacity) {
+    yb_stmt_cache_capacity = capacity;
+}
+
+static uint64_t yb_stmt_cache_hash(const char* sql, unsigned long len) {
+    // FNV-1a
+    uint64_t h = 14695981039346656037ULL;
+    for (unsigned long i = 0; i < len; ++i) {
+        h ^= (unsigned char)sql[i];
+        h *= 1099511628211ULL;
+    }
+    return h;
+}
+
+static struct yb_stmt_cache_conn_s** yb_stmt_cache_slot(MYSQL* conn) {
+    return &yb_stmt_cache_conns[((uintptr_t)conn / sizeof(void*)) %
+                                YB_STMT_CACHE_BUCKETS];
+}
+
+// the cache of conn, created if create is set
+static struct yb_stmt_cache_conn_s* yb_stmt_cache_find(MYSQL* conn,
+                                                       int create) {
+    pthread_mutex_lock(&yb_stmt_cache_mutex);
+    struct yb_stmt_cache_conn_s** slot = yb_stmt_cache_slot(conn);
+    struct yb_stmt_cache_conn_s* c = *slot;
+    while (c != NULL && c->conn != conn) {
+        c = c->next;
+    }
+    if (c == NULL && create) {
+        c = calloc(1, sizeof(struct yb_stmt_cache_conn_s));
+        if (c != NULL) {
+            c->conn = conn;
+            c->thread_id = mysql_thread_id(conn);
+            c->next = *slot;
+            *slot = c;
+        }
+    }
+    pthread_mutex_unlock(&yb_stmt_cache_mutex);
+    return c;
+}
+
+static void yb_stmt_cache_unlink(struct yb_stmt_cache_conn_s* c,
+                                 struct yb_stmt_cache_entry_s* e) {
+    if (e->prev != NULL) {
+        e->prev->next = e->next;
+    } else {
+        c->head = e->next;
+    }
+    if (e->next != NULL) {
+        e->next->prev = e->prev;
+    } else {
+        c->tail = e->prev;
+    }
+    e->prev = NULL;
+    e->next = NULL;
+    c->size--;
+}
+
+static void yb_stmt_cache_push_front(struct yb_stmt_cache_conn_s* c,
+                                     struct yb_stmt_cache_entry_s* e) {
+    e->prev = NULL;
+    e->next = c->head;
+    if (c->head != NULL) {
+        c->head->prev = e;
+    } else {
+        c->tail = e;
+    }
+    c->head = e;
+    c->size++;
+}
+
+static struct yb_stmt_cache_entry_s* yb_stmt_cache_entry(
+    struct yb_stmt_cache_conn_s* c, MYSQL_STMT* stmt) {
+    struct yb_stmt_cache_entry_s* e = c != NULL ? c->head : NULL;
+    while (e != NULL && e->stmt != stmt) {
+        e = e->next;
+    }
+    return e;
+}
+
+// remove the statements of c, those in use are closed when released
+static void yb_stmt_cache_drop(struct yb_stmt_cache_conn_s* c) {
+    while (c->head != NULL) {
+        struct yb_stmt_cache_entry_s* e = c->head;
+        yb_stmt_cache_unlink(c, e);
+        if (!e->in_use) {
+            mysql_stmt_close(e->stmt);
+        }
+        free(e->sql);
+        free(e);
+    }
+}
+
+int yb_stmt_cache_prepare(MYSQL* conn, const char* sql, unsigned long len,
+                          MYSQL_STMT** stmt) {
+    *stmt = NULL;
+    struct yb_stmt_cache_conn_s* c = yb_stmt_cache_find(conn, 1);
+    if (c != NULL && c->thread_id != mysql_thread_id(conn)) {
+        // reconnected, the statements are gone with the old session
+        yb_stmt_cache_drop(c);
+        c->thread_id = mysql_thread_id(conn);
+    }
+
+    uint64_t hash = yb_stmt_cache_hash(sql, len);
+    struct yb_stmt_cache_entry_s* e = c != NULL ? c->head : NULL;
+    for (; e != NULL; e = e->next) {
+        if (!e->in_use && e->hash == hash && e->sql_len == len &&
+            memcmp(e->sql, sql, len) == 0) {
+            e->in_use = 1;
+            yb_stmt_cache_unlink(c, e);
+            yb_stmt_cache_push_front(c, e);
+            *stmt = e->stmt;
+            return YB_OK;
+        }
+    }
+
+    *stmt = mysql_stmt_init(conn);
+    if (*stmt == NULL || mysql_stmt_prepare(*stmt, sql, len) != 0) {
+        return YB_FAIL;
+    }
+    if (c == NULL || yb_stmt_cache_capacity == 0) {
+        // not cached, closed when released
+        return YB_OK;
+    }
+    e = calloc(1, sizeof(struct yb_stmt_cache_entry_s));
+    char* copy = malloc(len + 1);
+    if (e == NULL || copy == NULL) {
+        free(e);
+        free(copy);
+        return YB_OK;
+    }
+    memcpy(copy, sql, len);
+    copy[len] = '\0';
+    e->stmt = *stmt;
+    e->sql = copy;
+    e->sql_len = len;
+    e->hash = hash;
+    e->in_use = 1;
+    yb_stmt_cache_push_front(c, e);
+    return YB_OK;
+}
+
+void yb_stmt_cache_release(MYSQL* conn, MYSQL_STMT* stmt) {
+    if (stmt == NULL) {
+        return;
+    }
+    struct yb_stmt_cache_conn_s* c = yb_stmt_cache_find(conn, 0);
+    struct yb_stmt_cache_entry_s* e = yb_stmt_cache_entry(c, stmt);
+    if (e == NULL) {
+        mysql_stmt_close(stmt);
+        return;
+    }
+    // the next use starts without the rows and the cursor of this one, and
+    // with the default of 1 prefetched row
+    unsigned long cursor_type = CURSOR_TYPE_NO_CURSOR;
+    unsigned long prefetch_rows = 1;
+    mysql_stmt_free_result(stmt);
+    mysql_stmt_attr_set(stmt, STMT_ATTR_CURSOR_TYPE, &cursor_type);
+    mysql_stmt_attr_set(stmt, STMT_ATTR_PREFETCH_ROWS, &prefetch_rows);
+    e->in_use = 0;
+
+    // evict the least recently used statements
+    e = c->tail;
+    while (e != NULL && c->size > yb_stmt_cache_capacity) {
+        struct yb_stmt_cache_entry_s* prev = e->prev;
+        if (!e->in_use) {
+            yb_stmt_cache_unlink(c, e);
+            mysql_stmt_close(e->stmt);
+            free(e->sql);
+            free(e);
+        }
+        e = prev;
+    }
+}
+
+void yb_stmt_cache_close(MYSQL* conn, MYSQL_STMT* stmt) {
+    if (stmt == NULL) {
+        return;
+    }
+    struct yb_stmt_cache_conn_s* c = yb_stmt_cache_find(conn, 0);
+    struct yb_stmt_cache_entry_s* e = yb_stmt_cache_entry(c, stmt);
+    if (e != NULL) {
+        yb_stmt_cache_unlink(c, e);
+        free(e->sql);
+        free(e);
+    }
+    mysql_stmt_close(stmt);
+}
+
+void yb_stmt_cache_clear(MYSQL* conn) {
+    pthread_mutex_lock(&yb_stmt_cache_mutex);
+    struct yb_stmt_cache_conn_s** slot = yb_stmt_cache_slot(conn);
+    while (*slot != NULL && (*slot)->conn != conn) {
+        slot = &(*slot)->next;
+    }
+    struct yb_stmt_cache_conn_s* c = *slot;
+    if (c != NULL) {
+        *slot = c->next;
+    }
+    pthread_mutex_unlock(&yb_stmt_cache_mutex);
+
+    if (c != NULL) {
+        yb_stmt_cache_drop(c);
+        free(c);
+    }
 }
 
 // mysql connection pool entry
@@ -872,6 +1515,7 @@ MYSQL* yb_mysql_pool_get_connection(yb_mysql_pool_t pool) {
         pool->avail = entry->next;
         // clean edle timeout connections
         if (cur > pool->max_idle_time + entry->touch) {
+            yb_stmt_cache_clear(entry->conn);
             mysql_close(entry->conn);
             free(entry);
             entry = NULL;
@@ -909,6 +1553,7 @@ static void yb_mysql_pool_prune(yb_mysql_pool_t pool) {
     while (pool->cur_connections > pool->min_connections) {
         struct yb_mysql_entry_s* entry = pool->avail;
         pool->avail = entry->next;
+        yb_stmt_cache_clear(entry->conn);
         mysql_close(entry->conn);
         free(entry);
         pool->cur_connections--;
@@ -3,15 +3,20 @@
 
 #include <float.h>
 #include <mysql/mysql.h>
+#include <stddef.h>
 #include <stdint.h>
 
 #ifndef YB_INT_NULL
 /// Null value for integer (int64_t)
+/// @deprecated NULL fields are tracked by <type>_is_null() and
+/// <type>_set_null(), a field holding this value is not NULL.
 #define YB_INT_NULL INT64_MIN
 #endif
 
 #ifndef YB_FLOAT_NULL
 /// Null value for float (double)
+/// @deprecated NULL fields are tracked by <type>_is_null() and
+/// <type>_set_null(), a field holding this value is not NULL.
 #define YB_FLOAT_NULL DBL_MIN
 #endif
 
@@ -28,6 +33,32 @@
 /// Fail value
 #define YB_FAIL -1
 #endif
+#ifndef YB_NO_ROW
+/// No row found value
+#define YB_NO_ROW 1
+#endif
+
+/// Fail values of the MySQL errors a caller may handle, generated functions
+/// return them instead of YB_FAIL. Every fail value is negative.
+/// duplicate key (1062, 1586)
+#define YB_ERR_DUPLICATE_KEY -2
+/// foreign key violation (1451, 1452, 1216, 1217)
+#define YB_ERR_FOREIGN_KEY -3
+/// deadlock (1213)
+#define YB_ERR_DEADLOCK -4
+/// lock wait timeout (1205)
+#define YB_ERR_LOCK_WAIT_TIMEOUT -5
+/// connection lost (2006, 2013, 2055)
+#define YB_ERR_CONNECTION_LOST -6
+/// data too long for a column (1406)
+#define YB_ERR_DATA_TOO_LONG -7
+/// NULL for a NOT NULL column (1048, 1364)
+#define YB_ERR_NOT_NULL -8
+/// value out of range for a column (1264)
+#define YB_ERR_OUT_OF_RANGE -9
+/// a ${} string can not be escaped, out of memory or
+/// mysql_real_escape_string() failed, e.g. with NO_BACKSLASH_ESCAPES
+#define YB_ERR_ESCAPE -10
 
 /// string type yb_string_t generalizes how sequences of bytes are manipulated
 /// and stored. String creation, manipulation, and description are all handled
@@ -233,17 +264,81 @@ int yb_string_to_hex(const yb_string_t s, char* out, int64_t out_len);
 /// is not a valid hexadecimal string.
 int yb_string_from_hex(yb_string_t s, const char* in, int64_t in_len);
 
-/// @brief trim string src -> dest.
+/// @brief append the content of a <trim> element to dest.
+///
+/// src is trimmed of whitespace, then the first of the `|` separated
+/// prefix_override values it starts with and the first of the suffix_override
+/// values it ends with are removed, matched case insensitively. The rest is
+/// appended as " prefix content suffix ". Nothing is appended when src is
+/// only whitespace.
 /// @param src[in] source yb_string_t object.
-/// @param prefix[in] add prefix to dest.
-/// @param suffix[in] add suffix to dest.
-/// @param prefix_override[in] will remove prefix of src.
-/// @param suffix_override[in] will remove suffix of src.
-/// @param dest[out] destination yb_string_t object.
+/// @param prefix[in] prepended to the content.
+/// @param suffix[in] appended to the content.
+/// @param prefix_override[in] prefixes to remove from src.
+/// @param suffix_override[in] suffixes to remove from src.
+/// @param dest[out] yb_string_t object to append to.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if an argument is NULL or out of memory.
 int yb_string_trim(yb_string_t src, const char* prefix, const char* suffix,
                    const char* prefix_override, const char* suffix_override,
                    yb_string_t dest);
 
+/// @brief append the decimal representation of \a v to \a s.
+/// @param s an yb_string_t
+/// @param v the integer to append.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed.
+int yb_string_append_int(yb_string_t s, int64_t v);
+
+/// @brief append the decimal representation of \a v to \a s.
+/// @param s an yb_string_t
+/// @param v the float number to append.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed.
+int yb_string_append_double(yb_string_t s, double v);
+
+/// @brief append \a value to \a s, escaped by mysql_real_escape_string() for
+/// the character set of \a conn.
+/// @param conn the connection the statement will be sent to.
+/// @param s an yb_string_t
+/// @param value the yb_string_t to escape and append.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed, fail if \a value is
+/// YB_STRING_NULL.
+int yb_string_append_escaped(MYSQL* conn, yb_string_t s,
+                             const yb_string_t value);
+
+/// a growable list of pointers, e.g. the structs fetched by a select.
+struct yb_list_s;
+typedef struct yb_list_s* yb_list_t;
+
+/// @brief create a new empty list.
+/// @return a new list object, freed by yb_list_free().
+/// @retval NULL Memory allocation failed.
+yb_list_t yb_list_new();
+
+/// @brief free a list.
+/// @param l a list object.
+/// @param free_elem called on each element to free it, may be NULL if the
+/// elements are not owned by the list.
+void yb_list_free(yb_list_t l, void (*free_elem)(void* elem));
+
+/// @brief append \a elem to \a l.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed, fail if \a l is NULL.
+int yb_list_push(yb_list_t l, void* elem);
+
+/// @brief the element at \a i of \a l.
+/// @retval NULL if \a i is out of range.
+void* yb_list_get(const yb_list_t l, int64_t i);
+
+/// @brief the number of elements of \a l. 0 if l==NULL.
+int64_t yb_list_len(const yb_list_t l);
+
+/// @brief call \a fn on each element of \a l in order, with \a arg.
+void yb_list_foreach(yb_list_t l, void (*fn)(void* elem, void* arg),
+                     void* arg);
+
 /// hash map from yb_string_t to yb_string_t.
 struct yb_hash_map_s;
 typedef struct yb_hash_map_s* yb_hash_map_t;
@@ -279,10 +374,147 @@ yb_string_t yb_hash_map_get(yb_hash_map_t map, yb_string_t key);
 /// @param key a key to remove.
 void yb_hash_map_remove(yb_hash_map_t map, yb_string_t key);
 
-/// @brief generate statements by yb_stmt_elem list.
-/// @param e a yb_stmt_elem list.
-/// @return a new yb_string_t object.
-yb_string_t yb_stmt_gen_string(struct yb_stmt_elem* e);
+/// why a generated function returned YB_FAIL. \a code and \a sqlstate are
+/// those of MySQL, \a code is 0 for a failure of the client, e.g. out of
+/// memory or a rejected ${} value.
+typedef struct yb_error_s {
+    unsigned int code;
+    char sqlstate[SQLSTATE_LENGTH + 1];
+    char message[MYSQL_ERRMSG_SIZE];
+    /// the id of the statement, a static string.
+    const char* stmt_id;
+} yb_error_t;
+
+/// @brief the fail value of a MySQL errno.
+/// @return one of YB_ERR_*, YB_FAIL for the other errors.
+int yb_error_category(unsigned int code);
+
+/// @brief whether \a err is one of the errors, false if \a err is NULL.
+int yb_error_is_duplicate_key(const yb_error_t* err);
+int yb_error_is_foreign_key(const yb_error_t* err);
+int yb_error_is_deadlock(const yb_error_t* err);
+int yb_error_is_lock_wait_timeout(const yb_error_t* err);
+int yb_error_is_connection_lost(const yb_error_t* err);
+int yb_error_is_data_too_long(const yb_error_t* err);
+int yb_error_is_not_null(const yb_error_t* err);
+int yb_error_is_out_of_range(const yb_error_t* err);
+
+/// @brief reset \a err to no error, it is ignored if \a err is NULL.
+void yb_error_clear(yb_error_t* err);
+
+/// @brief set a failure of the client to \a err, it is ignored if \a err is
+/// NULL.
+void yb_error_set(yb_error_t* err, const char* stmt_id, const char* message);
+
+/// @brief set the last error of \a conn to \a err, it is ignored if \a err
+/// is NULL.
+/// @return the fail value of the error, see yb_error_category().
+int yb_error_from_conn(yb_error_t* err, const char* stmt_id, MYSQL* conn);
+
+/// @brief set the last error of \a stmt to \a err, it is ignored if \a err
+/// is NULL.
+/// @return the fail value of the error, see yb_error_category().
+int yb_error_from_stmt(yb_error_t* err, const char* stmt_id,
+                       MYSQL_STMT* stmt);
+
+/// the result set of an executed MYSQL_STMT, read row by row. The columns are
+/// found by name, and each value is fetched by mysql_stmt_fetch_column() with
+/// the type it is read as, so string buffers are sized to the value.
+struct yb_stmt_result_s;
+typedef struct yb_stmt_result_s* yb_stmt_result_t;
+
+/// @brief store the result set of \a stmt and bind its columns.
+/// @param stmt an executed MYSQL_STMT, it is not owned by the result.
+/// @return a new result object, freed by yb_stmt_result_free().
+/// @retval NULL if the operation is failed.
+yb_stmt_result_t yb_stmt_result_new(MYSQL_STMT* stmt);
+
+/// @brief bind the columns of \a stmt and read its rows as they are fetched,
+/// without storing the result set. Memory stays constant however many rows
+/// there are, but the connection can not run other statements until the
+/// result is freed, unless \a stmt uses a server side cursor.
+/// @param stmt an executed MYSQL_STMT, it is not owned by the result.
+/// @return a new result object, freed by yb_stmt_result_free().
+/// @retval NULL if the operation is failed.
+yb_stmt_result_t yb_stmt_result_stream(MYSQL_STMT* stmt);
+
+/// @brief free a result object.
+void yb_stmt_result_free(yb_stmt_result_t r);
+
+/// @brief fetch the next row.
+/// @retval YB_OK if a row is fetched.
+/// @retval YB_NO_ROW if there are no more rows.
+/// @retval YB_FAIL if the operation is failed.
+int yb_stmt_result_fetch(yb_stmt_result_t r);
+
+/// @brief find a column by name, case insensitive.
+/// @param r a result object.
+/// @param prefix prepended to \a name, may be "".
+/// @param name the column name.
+/// @return the index of the column, -1 if there is no such column.
+int yb_stmt_result_column(yb_stmt_result_t r, const char* prefix,
+                          const char* name);
+
+/// @brief whether the value of \a column in the current row is NULL.
+/// @return 1 if the value is NULL or \a column is -1, 0 if not.
+int yb_stmt_result_is_null(yb_stmt_result_t r, int column);
+
+/// @brief read the value of \a column in the current row as an integer,
+/// 0 if it is NULL. \a v is untouched if \a column is -1.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed.
+int yb_stmt_result_get_int(yb_stmt_result_t r, int column, int64_t* v);
+
+/// @brief read the value of \a column in the current row as a float number,
+/// 0.0 if it is NULL. \a v is untouched if \a column is -1.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed.
+int yb_stmt_result_get_double(yb_stmt_result_t r, int column, double* v);
+
+/// @brief read the value of \a column in the current row as a string,
+/// YB_STRING_NULL if it is NULL. The old string of \a v is freed. \a v is
+/// untouched if \a column is -1.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed.
+int yb_stmt_result_get_string(yb_stmt_result_t r, int column, yb_string_t* v);
+
+#ifndef YB_STMT_CACHE_SIZE
+/// the prepared statements kept for each connection by default
+#define YB_STMT_CACHE_SIZE 64
+#endif
+
+/// the prepared statements of each connection, keyed by the connection and
+/// the SQL text and evicted least recently used first. A statement is in use
+/// from yb_stmt_cache_prepare() until yb_stmt_cache_release() or
+/// yb_stmt_cache_close(), and is only touched by the thread that uses its
+/// connection. The statements of a connection are dropped when its thread
+/// id changes, i.e. after a reconnect.
+
+/// @brief set the statements kept for each connection, 0 disables the cache.
+/// It should be called before any statement is prepared.
+void yb_stmt_cache_set_capacity(size_t capacity);
+
+/// @brief get a prepared statement of \a sql on \a conn, from the cache or
+/// newly prepared.
+/// @param stmt set to the statement, or to the failed statement if the
+/// prepare is failed, NULL if the statement can not be created.
+/// @retval YB_OK if the operation is successful.
+/// @retval YB_FAIL if the operation is failed, \a stmt is closed by
+/// yb_stmt_cache_close().
+int yb_stmt_cache_prepare(MYSQL* conn, const char* sql, unsigned long len,
+                          MYSQL_STMT** stmt);
+
+/// @brief return \a stmt to the cache of \a conn after it is used, its
+/// result is freed and its cursor attributes are reset. It is ignored if \a
+/// stmt is NULL.
+void yb_stmt_cache_release(MYSQL* conn, MYSQL_STMT* stmt);
+
+/// @brief remove \a stmt from the cache of \a conn and close it, after a
+/// failure. It is ignored if \a stmt is NULL.
+void yb_stmt_cache_close(MYSQL* conn, MYSQL_STMT* stmt);
+
+/// @brief close the cached statements of \a conn, before mysql_close().
+void yb_stmt_cache_clear(MYSQL* conn);
 
 /// define a thread-safe MYSQL connection pool.
 struct yb_mysql_pool_s;
@@ -0,0 +1,440 @@
+// a fake libmysqlclient for running generated code in tests, it prints the
+// executed statements and answers them from a queue of outcomes
+#include <mysql/mysql.h>
+
+#include <ctype.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <strings.h>
+
+struct st_mysql {
+    unsigned long thread_id;
+    unsigned int err;
+    char error[MYSQL_ERRMSG_SIZE];
+    char sqlstate[SQLSTATE_LENGTH + 1];
+};
+
+struct st_mysql_res {
+    unsigned int num_fields;
+    MYSQL_FIELD* fields;
+};
+
+// what the next execute gets
+struct fake_outcome {
+    unsigned int num_fields;
+    unsigned int num_rows;
+    char** cells;
+    unsigned int err;
+    char error[MYSQL_ERRMSG_SIZE];
+    char sqlstate[SQLSTATE_LENGTH + 1];
+    unsigned long long affected;
+    struct fake_outcome* next;
+};
+
+struct st_mysql_stmt {
+    MYSQL* conn;
+    char* sql;
+    unsigned long param_count;
+    MYSQL_BIND* params;
+    MYSQL_BIND* result;
+    unsigned long cursor_type;
+    unsigned long prefetch_rows;
+    struct fake_outcome* outcome;
+    long row;
+    unsigned int err;
+    char error[MYSQL_ERRMSG_SIZE];
+    char sqlstate[SQLSTATE_LENGTH + 1];
+};
+
+static struct fake_outcome* fake_head;
+static struct fake_outcome* fake_tail;
+static unsigned long fake_thread_id;
+static unsigned long fake_prepare_count;
+static int fake_escape_fails;
+
+static char* fake_strdup(const char* s) {
+    if (s == NULL) {
+        return NULL;
+    }
+    char* copy = malloc(strlen(s) + 1);
+    strcpy(copy, s);
+    return copy;
+}
+
+static struct fake_outcome* fake_push(void) {
+    struct fake_outcome* o = calloc(1, sizeof(struct fake_outcome));
+    o->affected = 1;
+    if (fake_tail != NULL) {
+        fake_tail->next = o;
+    } else {
+        fake_head = o;
+    }
+    fake_tail = o;
+    return o;
+}
+
+static struct fake_outcome* fake_pop(void) {
+    struct fake_outcome* o = fake_head;
+    if (o != NULL) {
+        fake_head = o->next;
+        if (fake_head == NULL) {
+            fake_tail = NULL;
+        }
+    }
+    return o;
+}
+
+static void fake_outcome_free(struct fake_outcome* o) {
+    if (o == NULL) {
+        return;
+    }
+    for (unsigned int i = 0; i < o->num_fields * (o->num_rows + 1); ++i) {
+        free(o->cells[i]);
+    }
+    free(o->cells);
+    free(o);
+}
+
+void fake_mysql_push_rows(unsigned int num_fields, unsigned int num_rows,
+                          const char* const* cells) {
+    struct fake_outcome* o = fake_push();
+    o->num_fields = num_fields;
+    o->num_rows = num_rows;
+    o->cells = calloc(num_fields * (num_rows + 1) + 1, sizeof(char*));
+    for (unsigned int i = 0; i < num_fields * (num_rows + 1); ++i) {
+        o->cells[i] = fake_strdup(cells[i]);
+    }
+}
+
+void fake_mysql_push_error(unsigned int err, const char* sqlstate,
+                           const char* message) {
+    struct fake_outcome* o = fake_push();
+    o->err = err;
+    snprintf(o->sqlstate, sizeof(o->sqlstate), "%s", sqlstate);
+    snprintf(o->error, sizeof(o->error), "%s", message);
+}
+
+void fake_mysql_push_affected(unsigned long long rows) {
+    fake_push()->affected = rows;
+}
+
+void fake_mysql_escape_fails(int fails) {
+    fake_escape_fails = fails;
+}
+
+void fake_mysql_reconnect(MYSQL* mysql) {
+    mysql->thread_id = ++fake_thread_id;
+}
+
+unsigned long fake_mysql_prepared(void) {
+    return fake_prepare_count;
+}
+
+MYSQL* mysql_init(MYSQL* mysql) {
+    if (mysql == NULL) {
+        mysql = calloc(1, sizeof(MYSQL));
+    }
+    mysql->thread_id = ++fake_thread_id;
+    strcpy(mysql->sqlstate, "00000");
+    return mysql;
+}
+
+MYSQL* mysql_real_connect(MYSQL* mysql, const char* host, const char* user,
+                          const char* passwd, const char* db,
+                          unsigned int port, const char* unix_socket,
+                          unsigned long clientflag) {
+    (void)host, (void)user, (void)passwd, (void)db, (void)port;
+    (void)unix_socket, (void)clientflag;
+    return mysql;
+}
+
+int mysql_set_character_set(MYSQL* mysql, const char* csname) {
+    (void)mysql, (void)csname;
+    return 0;
+}
+
+int mysql_options(MYSQL* mysql, enum mysql_option option, const void* arg) {
+    (void)mysql, (void)option, (void)arg;
+    return 0;
+}
+
+void mysql_close(MYSQL* mysql) {
+    free(mysql);
+}
+
+unsigned int mysql_errno(MYSQL* mysql) {
+    return mysql->err;
+}
+
+const char* mysql_error(MYSQL* mysql) {
+    return mysql->error;
+}
+
+const char* mysql_sqlstate(MYSQL* mysql) {
+    return mysql->sqlstate;
+}
+
+unsigned long mysql_real_escape_string(MYSQL* mysql, char* to,
+                                       const char* from,
+                                       unsigned long length) {
+    (void)mysql;
+    if (fake_escape_fails) {
+        return (unsigned long)-1;
+    }
+    char* p = to;
+    for (unsigned long i = 0; i < length; ++i) {
+        switch (from[i]) {
+            case '\0': *p++ = '\\'; *p++ = '0'; break;
+            case '\n': *p++ = '\\'; *p++ = 'n'; break;
+            case '\r': *p++ = '\\'; *p++ = 'r'; break;
+            case '\032': *p++ = '\\'; *p++ = 'Z'; break;
+            case '\'':
+            case '"':
+            case '\\': *p++ = '\\'; *p++ = from[i]; break;
+            default: *p++ = from[i];
+        }
+    }
+    *p = '\0';
+    return (unsigned long)(p - to);
+}
+
+unsigned long mysql_thread_id(MYSQL* mysql) {
+    return mysql->thread_id;
+}
+
+MYSQL_STMT* mysql_stmt_init(MYSQL* mysql) {
+    MYSQL_STMT* stmt = calloc(1, sizeof(MYSQL_STMT));
+    stmt->conn = mysql;
+    stmt->prefetch_rows = 1;
+    strcpy(stmt->sqlstate, "00000");
+    return stmt;
+}
+
+int mysql_stmt_prepare(MYSQL_STMT* stmt, const char* query,
+                       unsigned long length) {
+    free(stmt->sql);
+    stmt->sql = malloc(length + 1);
+    memcpy(stmt->sql, query, length);
+    stmt->sql[length] = '\0';
+    stmt->param_count = 0;
+    for (unsigned long i = 0; i < length; ++i) {
+        stmt->param_count += query[i] == '?';
+    }
+    fake_prepare_count++;
+    return 0;
+}
+
+bool mysql_stmt_bind_param(MYSQL_STMT* stmt, MYSQL_BIND* bnd) {
+    free(stmt->params);
+    stmt->params = calloc(stmt->param_count + 1, sizeof(MYSQL_BIND));
+    memcpy(stmt->params, bnd, stmt->param_count * sizeof(MYSQL_BIND));
+    return 0;
+}
+
+bool mysql_stmt_bind_result(MYSQL_STMT* stmt, MYSQL_BIND* bnd) {
+    stmt->result = bnd;
+    return 0;
+}
+
+static void fake_print_sql(const char* sql) {
+    int space = 0;
+    int start = 1;
+    for (; *sql != '\0'; ++sql) {
+        if (isspace((unsigned char)*sql)) {
+            space = 1;
+            continue;
+        }
+        if (space && !start) {
+            putchar(' ');
+        }
+        putchar(*sql);
+        space = 0;
+        start = 0;
+    }
+}
+
+static void fake_print_param(const MYSQL_BIND* b) {
+    if (b->buffer_type == MYSQL_TYPE_NULL ||
+        (b->is_null != NULL && *b->is_null)) {
+        printf("NULL");
+        return;
+    }
+    switch (b->buffer_type) {
+        case MYSQL_TYPE_LONGLONG:
+            printf("%lld", *(const long long*)b->buffer);
+            break;
+        case MYSQL_TYPE_DOUBLE:
+            printf("%g", *(const double*)b->buffer);
+            break;
+        default: {
+            unsigned long len =
+                b->length != NULL ? *b->length : b->buffer_length;
+            printf("'%.*s'", (int)len, (const char*)b->buffer);
+        }
+    }
+}
+
+static int fake_is_query(const char* sql) {
+    while (isspace((unsigned char)*sql) || *sql == '(') {
+        ++sql;
+    }
+    return strncasecmp(sql, "select", 6) == 0;
+}
+
+int mysql_stmt_execute(MYSQL_STMT* stmt) {
+    fake_print_sql(stmt->sql);
+    printf(" [");
+    for (unsigned long i = 0; i < stmt->param_count; ++i) {
+        printf(i > 0 ? ", " : "");
+        fake_print_param(&stmt->params[i]);
+    }
+    printf("]");
+    if (stmt->cursor_type != CURSOR_TYPE_NO_CURSOR) {
+        printf(" (cursor, prefetch %lu)", stmt->prefetch_rows);
+    } else if (stmt->prefetch_rows != 1) {
+        printf(" (prefetch %lu)", stmt->prefetch_rows);
+    }
+    printf("\n");
+
+    fake_outcome_free(stmt->outcome);
+    stmt->outcome = fake_pop();
+    stmt->row = -1;
+    if (stmt->outcome == NULL) {
+        stmt->outcome = calloc(1, sizeof(struct fake_outcome));
+        stmt->outcome->affected = 1;
+    }
+    stmt->err = stmt->outcome->err;
+    strcpy(stmt->error, stmt->outcome->error);
+    strcpy(stmt->sqlstate, stmt->err != 0 ? stmt->outcome->sqlstate : "00000");
+    stmt->conn->err = stmt->err;
+    strcpy(stmt->conn->error, stmt->error);
+    strcpy(stmt->conn->sqlstate, stmt->sqlstate);
+    return stmt->err != 0 ? 1 : 0;
+}
+
+int mysql_stmt_store_result(MYSQL_STMT* stmt) {
+    (void)stmt;
+    return 0;
+}
+
+static const char* fake_cell(MYSQL_STMT* stmt, unsigned int column) {
+    struct fake_outcome* o = stmt->outcome;
+    return o->cells[(stmt->row + 1) * o->num_fields + column];
+}
+
+int mysql_stmt_fetch(MYSQL_STMT* stmt) {
+    struct fake_outcome* o = stmt->outcome;
+    if (o == NULL || stmt->row + 1 >= (long)o->num_rows) {
+        return MYSQL_NO_DATA;
+    }
+    stmt->row++;
+    for (unsigned int i = 0; i < o->num_fields && stmt->result != NULL; ++i) {
+        const char* cell = fake_cell(stmt, i);
+        *stmt->result[i].is_null = cell == NULL;
+        *stmt->result[i].length = cell != NULL ? strlen(cell) : 0;
+    }
+    return MYSQL_DATA_TRUNCATED;
+}
+
+int mysql_stmt_fetch_column(MYSQL_STMT* stmt, MYSQL_BIND* bind_arg,
+                            unsigned int column, unsigned long offset) {
+    (void)offset;
+    const char* cell = fake_cell(stmt, column);
+    if (bind_arg->is_null != NULL) {
+        *bind_arg->is_null = cell == NULL;
+    }
+    if (cell == NULL) {
+        return 0;
+    }
+    switch (bind_arg->buffer_type) {
+        case MYSQL_TYPE_LONGLONG:
+            *(long long*)bind_arg->buffer = strtoll(cell, NULL, 10);
+            break;
+        case MYSQL_TYPE_DOUBLE:
+            *(double*)bind_arg->buffer = strtod(cell, NULL);
+            break;
+        default: {
+            unsigned long len = strlen(cell);
+            unsigned long n = len < bind_arg->buffer_length
+                                  ? len
+                                  : bind_arg->buffer_length;
+            memcpy(bind_arg->buffer, cell, n);
+            if (n < bind_arg->buffer_length) {
+                ((char*)bind_arg->buffer)[n] = '\0';
+            }
+            if (bind_arg->length != NULL) {
+                *bind_arg->length = len;
+            }
+        }
+    }
+    return 0;
+}
+
+bool mysql_stmt_free_result(MYSQL_STMT* stmt) {
+    fake_outcome_free(stmt->outcome);
+    stmt->outcome = NULL;
+    stmt->result = NULL;
+    return 0;
+}
+
+bool mysql_stmt_close(MYSQL_STMT* stmt) {
+    fake_outcome_free(stmt->outcome);
+    free(stmt->params);
+    free(stmt->sql);
+    free(stmt);
+    return 0;
+}
+
+bool mysql_stmt_attr_set(MYSQL_STMT* stmt, enum enum_stmt_attr_type attr_type,
+                         const void* attr) {
+    if (attr_type == STMT_ATTR_CURSOR_TYPE) {
+        stmt->cursor_type = *(const unsigned long*)attr;
+    } else if (attr_type == STMT_ATTR_PREFETCH_ROWS) {
+        stmt->prefetch_rows = *(const unsigned long*)attr;
+    }
+    return 0;
+}
+
+unsigned long long mysql_stmt_affected_rows(MYSQL_STMT* stmt) {
+    return stmt->outcome != NULL ? stmt->outcome->affected : 0;
+}
+
+unsigned int mysql_stmt_errno(MYSQL_STMT* stmt) {
+    return stmt->err;
+}
+
+const char* mysql_stmt_error(MYSQL_STMT* stmt) {
+    return stmt->error;
+}
+
+const char* mysql_stmt_sqlstate(MYSQL_STMT* stmt) {
+    return stmt->sqlstate;
+}
+
+MYSQL_RES* mysql_stmt_result_metadata(MYSQL_STMT* stmt) {
+    struct fake_outcome* o = stmt->outcome;
+    if (o == NULL || (o->num_fields == 0 && !fake_is_query(stmt->sql))) {
+        return NULL;
+    }
+    MYSQL_RES* res = calloc(1, sizeof(MYSQL_RES));
+    res->num_fields = o->num_fields;
+    res->fields = calloc(o->num_fields + 1, sizeof(MYSQL_FIELD));
+    for (unsigned int i = 0; i < o->num_fields; ++i) {
+        res->fields[i].name = o->cells[i];
+    }
+    return res;
+}
+
+unsigned int mysql_num_fields(MYSQL_RES* res) {
+    return res->num_fields;
+}
+
+MYSQL_FIELD* mysql_fetch_fields(MYSQL_RES* res) {
+    return res->fields;
+}
+
+void mysql_free_result(MYSQL_RES* result) {
+    free(result->fields);
+    free(result);
+}
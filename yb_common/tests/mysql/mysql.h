@@ -0,0 +1,116 @@
+// the part of the MySQL C API yb_common and the generated code use, for the
+// fake client of fake_mysql.c
+#ifndef FAKE_MYSQL_H__
+#define FAKE_MYSQL_H__
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#define MYSQL_VERSION_ID 80036
+#define SQLSTATE_LENGTH 5
+#define MYSQL_ERRMSG_SIZE 512
+#define MYSQL_NO_DATA 100
+#define MYSQL_DATA_TRUNCATED 101
+
+typedef struct st_mysql MYSQL;
+typedef struct st_mysql_stmt MYSQL_STMT;
+typedef struct st_mysql_res MYSQL_RES;
+
+typedef struct st_mysql_field {
+    char* name;
+} MYSQL_FIELD;
+
+enum enum_field_types {
+    MYSQL_TYPE_LONGLONG,
+    MYSQL_TYPE_DOUBLE,
+    MYSQL_TYPE_STRING,
+    MYSQL_TYPE_NULL,
+};
+
+enum enum_stmt_attr_type {
+    STMT_ATTR_UPDATE_MAX_LENGTH,
+    STMT_ATTR_CURSOR_TYPE,
+    STMT_ATTR_PREFETCH_ROWS,
+};
+
+enum enum_cursor_type {
+    CURSOR_TYPE_NO_CURSOR = 0,
+    CURSOR_TYPE_READ_ONLY = 1,
+};
+
+enum mysql_option {
+    MYSQL_OPT_CONNECT_TIMEOUT,
+    MYSQL_OPT_READ_TIMEOUT,
+    MYSQL_OPT_WRITE_TIMEOUT,
+};
+
+typedef struct MYSQL_BIND {
+    unsigned long* length;
+    bool* is_null;
+    void* buffer;
+    bool* error;
+    enum enum_field_types buffer_type;
+    unsigned long buffer_length;
+    bool is_unsigned;
+} MYSQL_BIND;
+
+MYSQL* mysql_init(MYSQL* mysql);
+MYSQL* mysql_real_connect(MYSQL* mysql, const char* host, const char* user,
+                          const char* passwd, const char* db,
+                          unsigned int port, const char* unix_socket,
+                          unsigned long clientflag);
+int mysql_set_character_set(MYSQL* mysql, const char* csname);
+int mysql_options(MYSQL* mysql, enum mysql_option option, const void* arg);
+void mysql_close(MYSQL* mysql);
+unsigned int mysql_errno(MYSQL* mysql);
+const char* mysql_error(MYSQL* mysql);
+const char* mysql_sqlstate(MYSQL* mysql);
+unsigned long mysql_real_escape_string(MYSQL* mysql, char* to,
+                                       const char* from,
+                                       unsigned long length);
+unsigned long mysql_thread_id(MYSQL* mysql);
+
+MYSQL_STMT* mysql_stmt_init(MYSQL* mysql);
+int mysql_stmt_prepare(MYSQL_STMT* stmt, const char* query,
+                       unsigned long length);
+bool mysql_stmt_bind_param(MYSQL_STMT* stmt, MYSQL_BIND* bnd);
+bool mysql_stmt_bind_result(MYSQL_STMT* stmt, MYSQL_BIND* bnd);
+int mysql_stmt_execute(MYSQL_STMT* stmt);
+int mysql_stmt_store_result(MYSQL_STMT* stmt);
+int mysql_stmt_fetch(MYSQL_STMT* stmt);
+int mysql_stmt_fetch_column(MYSQL_STMT* stmt, MYSQL_BIND* bind_arg,
+                            unsigned int column, unsigned long offset);
+bool mysql_stmt_free_result(MYSQL_STMT* stmt);
+bool mysql_stmt_close(MYSQL_STMT* stmt);
+bool mysql_stmt_attr_set(MYSQL_STMT* stmt, enum enum_stmt_attr_type attr_type,
+                         const void* attr);
+unsigned long long mysql_stmt_affected_rows(MYSQL_STMT* stmt);
+unsigned int mysql_stmt_errno(MYSQL_STMT* stmt);
+const char* mysql_stmt_error(MYSQL_STMT* stmt);
+const char* mysql_stmt_sqlstate(MYSQL_STMT* stmt);
+MYSQL_RES* mysql_stmt_result_metadata(MYSQL_STMT* stmt);
+unsigned int mysql_num_fields(MYSQL_RES* res);
+MYSQL_FIELD* mysql_fetch_fields(MYSQL_RES* res);
+void mysql_free_result(MYSQL_RES* result);
+
+/// the fake prints every executed statement as
+/// `<sql> [<params>]` with the whitespace of the sql collapsed, and a
+/// `(cursor, prefetch N)` or `(prefetch N)` suffix for the attributes set
+
+/// queue a result set for the next execute, cells holds num_fields column
+/// names and then num_rows rows, a NULL cell is a NULL value
+void fake_mysql_push_rows(unsigned int num_fields, unsigned int num_rows,
+                          const char* const* cells);
+/// queue a failure for the next execute
+void fake_mysql_push_error(unsigned int err, const char* sqlstate,
+                           const char* message);
+/// queue the affected rows of the next execute, 1 by default
+void fake_mysql_push_affected(unsigned long long rows);
+/// make mysql_real_escape_string fail, as with NO_BACKSLASH_ESCAPES
+void fake_mysql_escape_fails(int fails);
+/// a new thread id for conn, as after a reconnect
+void fake_mysql_reconnect(MYSQL* mysql);
+/// the number of statements prepared so far
+unsigned long fake_mysql_prepared(void);
+
+#endif  // FAKE_MYSQL_H__